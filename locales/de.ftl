@@ -0,0 +1,181 @@
+language-name = Deutsch
+
+## pages
+
+page-default = Speichere deine Zwischenablage!
+page-home = Teile deine Zwischenablage
+page-view-clip = Clip ansehen
+page-password-required = Passwort erforderlich
+page-clip-stats = Clip-Statistik
+page-trashed-clip = Clip im Papierkorb
+
+footer-course = ist Teil des Rust-Programmierkurses auf
+nav-language = Sprache
+nav-theme = Design
+theme-light = Hell
+theme-dark = Dunkel
+theme-auto = Automatisch
+
+## the new clip form
+
+home-clip = Clip
+home-content-placeholder = Füge hier deinen Inhalt ein
+home-goodies = Optionale Extras
+home-error-header = Fehler beim Speichern des Clips
+home-submit = Speichern!
+field-title = Titel
+field-expires = Läuft ab
+field-attach-file = Datei anhängen
+field-choose-file = Datei auswählen
+field-no-file = Keine Datei ausgewählt
+field-password-protected = Passwortschutz
+field-password = Passwort
+
+## a clip
+
+clip-created = Erstellt
+clip-never = Nie
+clip-expires-relative = Läuft { $time } ab
+# %s is replaced by the minutes and seconds left
+clip-expires-countdown = Läuft in %s ab
+clip-expired = Dieser Clip ist abgelaufen
+clip-attachment = Anhang
+clip-download = Herunterladen
+clip-view-raw = Rohtext
+clip-copy-link = Link kopieren
+clip-copied = Kopiert!
+clip-views =
+    { $count ->
+        [one] 1 Aufruf
+       *[other] { $count } Aufrufe
+    }
+clip-statistics = Statistik
+clip-delete = Löschen
+clip-owner-key-notice =
+    Das ist der Besitzerschlüssel des Clips, der nur jetzt angezeigt wird. Bewahre ihn auf, um
+    den Clip von anderswo mit clip-cli --owner-key zu löschen oder wiederherzustellen.
+
+password-notice = Dieser Clip ist passwortgeschützt. Bitte gib unten das Passwort ein, um ihn anzusehen.
+password-error-header = Fehler beim Abrufen des Clips
+password-unlock = Entsperren
+
+## statistics
+
+stats-title = Statistik für
+stats-last-days =
+    { $count ->
+        [one] Letzter Tag
+       *[other] Letzte { $count } Tage
+    }
+stats-views = Aufrufe
+stats-visitors = Besucher
+stats-page = Seite
+stats-raw = Rohtext
+stats-api = API
+stats-chart = Aufrufe pro Tag
+stats-bar = { $day }: { $views } Aufrufe, etwa { $visitors } Besucher
+stats-referrers = Häufigste Verweise
+stats-site = Website
+stats-no-referrers = Keine Aufrufe kamen von anderen Websites.
+stats-visitors-note =
+    Besucher werden über einen täglich wechselnden Hash geschätzt, wer an einem anderen Tag
+    wiederkommt, wird also erneut gezählt. Bots werden nicht gezählt.
+
+## a deleted or expired clip
+
+trash-notice =
+    Dieser Clip wurde gelöscht oder ist abgelaufen. Als sein Ersteller kannst du ihn
+    wiederherstellen, bis er aus dem Papierkorb entfernt wird.
+trash-error-header = Fehler beim Wiederherstellen
+trash-expiry-help = Ein abgelaufener Clip braucht ein neues Ablaufdatum.
+trash-restore = Wiederherstellen
+
+## error pages
+
+error-request-id = Anfrage-ID:
+error-search-placeholder = Kurzcode eines Clips
+error-search-open = Öffnen
+error-back-home = Zurück zur Startseite
+
+status-401 = Nicht autorisiert
+status-403 = Verboten
+status-404 = Nicht gefunden
+status-429 = Zu viele Anfragen
+status-500 = Interner Serverfehler
+
+error-generic = Etwas ist schiefgelaufen.
+error-internal = Bei uns ist etwas schiefgelaufen. Bitte versuche es erneut.
+error-not-found = Hier ist nichts. Der Clip ist vielleicht abgelaufen oder wurde gelöscht.
+error-unauthorized = Ohne Passwort oder Schlüssel darfst du diese Seite nicht sehen.
+error-too-many-requests = Zu viele Anfragen. Bitte warte einen Moment und versuche es erneut.
+error-server = Serverfehler
+error-server-retry = Ein Serverfehler ist aufgetreten. Bitte versuche es erneut
+error-form = Ein Fehler ist aufgetreten, bitte versuche es erneut
+error-clip-not-found = Clip nicht gefunden
+error-permission-denied = Das darfst du nicht
+error-password-required = Für diesen Clip ist ein Passwort erforderlich
+error-stats-owner-only = Nur wer den Clip erstellt hat, kann seine Statistik sehen
+error-owner-only = Nur wer den Clip erstellt hat, kann ihn löschen oder wiederherstellen
+
+## what was wrong with a clip
+
+clip-error-invalid-password = Falsches Passwort
+clip-error-invalid-title = Der Titel ist ungültig
+clip-error-empty-content = Füge Inhalt ein oder hänge eine Datei an
+clip-error-invalid-date = Das Ablaufdatum ist ungültig
+clip-error-invalid-id = Die Clip-ID ist ungültig
+clip-error-invalid-views = Die Anzahl der Aufrufe ist ungültig
+
+## times relative to now
+
+time-just-now = gerade eben
+time-in-years =
+    { $count ->
+        [one] in 1 Jahr
+       *[other] in { $count } Jahren
+    }
+time-in-months =
+    { $count ->
+        [one] in 1 Monat
+       *[other] in { $count } Monaten
+    }
+time-in-days =
+    { $count ->
+        [one] in 1 Tag
+       *[other] in { $count } Tagen
+    }
+time-in-hours =
+    { $count ->
+        [one] in 1 Stunde
+       *[other] in { $count } Stunden
+    }
+time-in-minutes =
+    { $count ->
+        [one] in 1 Minute
+       *[other] in { $count } Minuten
+    }
+time-ago-years =
+    { $count ->
+        [one] vor 1 Jahr
+       *[other] vor { $count } Jahren
+    }
+time-ago-months =
+    { $count ->
+        [one] vor 1 Monat
+       *[other] vor { $count } Monaten
+    }
+time-ago-days =
+    { $count ->
+        [one] vor 1 Tag
+       *[other] vor { $count } Tagen
+    }
+time-ago-hours =
+    { $count ->
+        [one] vor 1 Stunde
+       *[other] vor { $count } Stunden
+    }
+time-ago-minutes =
+    { $count ->
+        [one] vor 1 Minute
+       *[other] vor { $count } Minuten
+    }
@@ -0,0 +1,181 @@
+language-name = English
+
+## pages
+
+page-default = Stash Your Clipboard!
+page-home = Share Your Clipboard
+page-view-clip = View Clip
+page-password-required = Password Required
+page-clip-stats = Clip Statistics
+page-trashed-clip = Clip in the Trash
+
+footer-course = is a component of the Rust programming course available at
+nav-language = Language
+nav-theme = Theme
+theme-light = Light
+theme-dark = Dark
+theme-auto = Auto
+
+## the new clip form
+
+home-clip = Clip
+home-content-placeholder = Paste your content here
+home-goodies = Optional Goodies
+home-error-header = Error Posting Clip
+home-submit = Stash it!
+field-title = Title
+field-expires = Expires
+field-attach-file = Attach a File
+field-choose-file = Choose a file
+field-no-file = No file selected
+field-password-protected = Password Protected
+field-password = Password
+
+## a clip
+
+clip-created = Created
+clip-never = Never
+clip-expires-relative = Expires { $time }
+# %s is replaced by the minutes and seconds left
+clip-expires-countdown = Expires in %s
+clip-expired = This clip has expired
+clip-attachment = Attachment
+clip-download = Download
+clip-view-raw = View Raw
+clip-copy-link = Copy Link
+clip-copied = Copied!
+clip-views =
+    { $count ->
+        [one] 1 view
+       *[other] { $count } views
+    }
+clip-statistics = Statistics
+clip-delete = Delete
+clip-owner-key-notice =
+    This is the owner key of the clip, which is only shown now. Keep it to delete or restore
+    the clip with clip-cli --owner-key from elsewhere.
+
+password-notice = This clip is password protected. Please enter the password below in order to view the clip.
+password-error-header = Error Retrieving Clip
+password-unlock = Unlock
+
+## statistics
+
+stats-title = Statistics for
+stats-last-days =
+    { $count ->
+        [one] Last day
+       *[other] Last { $count } days
+    }
+stats-views = Views
+stats-visitors = Visitors
+stats-page = Page
+stats-raw = Raw
+stats-api = API
+stats-chart = Views per day
+stats-bar = { $day }: { $views } views, about { $visitors } visitors
+stats-referrers = Top referrers
+stats-site = Site
+stats-no-referrers = No views came from other sites.
+stats-visitors-note =
+    Visitors are estimated from a hash that changes every day, so someone returning on
+    another day is counted again. Bots are not counted.
+
+## a deleted or expired clip
+
+trash-notice =
+    This clip was deleted or has expired. As its creator, you can restore it until it is
+    purged from the trash.
+trash-error-header = Error Restoring Clip
+trash-expiry-help = A clip that has expired needs a new expiry.
+trash-restore = Restore
+
+## error pages
+
+error-request-id = Request id:
+error-search-placeholder = Short code of a clip
+error-search-open = Open
+error-back-home = Back to the home page
+
+status-401 = Unauthorized
+status-403 = Forbidden
+status-404 = Not Found
+status-429 = Too Many Requests
+status-500 = Internal Server Error
+
+error-generic = Something went wrong.
+error-internal = Something went wrong on our side. Please try again.
+error-not-found = There is nothing here. The clip may have expired or been deleted.
+error-unauthorized = You are not allowed to see this page without a password or key.
+error-too-many-requests = Too many requests. Please wait a moment and try again.
+error-server = server error
+error-server-retry = A server error occurred. Please try again
+error-form = An error occurred, please try again
+error-clip-not-found = Clip not found
+error-permission-denied = You are not allowed to do this
+error-password-required = A password is required to view this clip
+error-stats-owner-only = Only the creator of a clip can see its statistics
+error-owner-only = Only the creator of a clip can delete or restore it
+
+## what was wrong with a clip
+
+clip-error-invalid-password = Invalid password
+clip-error-invalid-title = The title is not valid
+clip-error-empty-content = Paste some content or attach a file
+clip-error-invalid-date = The expiry date is not valid
+clip-error-invalid-id = The clip id is not valid
+clip-error-invalid-views = The view count is not valid
+
+## times relative to now
+
+time-just-now = just now
+time-in-years =
+    { $count ->
+        [one] in 1 year
+       *[other] in { $count } years
+    }
+time-in-months =
+    { $count ->
+        [one] in 1 month
+       *[other] in { $count } months
+    }
+time-in-days =
+    { $count ->
+        [one] in 1 day
+       *[other] in { $count } days
+    }
+time-in-hours =
+    { $count ->
+        [one] in 1 hour
+       *[other] in { $count } hours
+    }
+time-in-minutes =
+    { $count ->
+        [one] in 1 minute
+       *[other] in { $count } minutes
+    }
+time-ago-years =
+    { $count ->
+        [one] 1 year ago
+       *[other] { $count } years ago
+    }
+time-ago-months =
+    { $count ->
+        [one] 1 month ago
+       *[other] { $count } months ago
+    }
+time-ago-days =
+    { $count ->
+        [one] 1 day ago
+       *[other] { $count } days ago
+    }
+time-ago-hours =
+    { $count ->
+        [one] 1 hour ago
+       *[other] { $count } hours ago
+    }
+time-ago-minutes =
+    { $count ->
+        [one] 1 minute ago
+       *[other] { $count } minutes ago
+    }
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS view_salts;
+DROP TABLE IF EXISTS clip_view_events;
+ALTER TABLE clips DROP COLUMN owner_key;
@@ -0,0 +1,19 @@
+ALTER TABLE clips ADD COLUMN owner_key TEXT;
+
+CREATE TABLE IF NOT EXISTS clip_view_events
+(
+    clip_id     TEXT NOT NULL REFERENCES clips (id) ON DELETE CASCADE,
+    day         DATE NOT NULL,
+    web_views   BIGINT NOT NULL,
+    raw_views   BIGINT NOT NULL,
+    api_views   BIGINT NOT NULL,
+    visitors    BLOB NOT NULL,
+    referrers   TEXT NOT NULL,
+    PRIMARY KEY (clip_id, day)
+);
+
+CREATE TABLE IF NOT EXISTS view_salts
+(
+    day     DATE PRIMARY KEY NOT NULL,
+    salt    BLOB NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS audit_salt;
+DROP TABLE IF EXISTS audit_log;
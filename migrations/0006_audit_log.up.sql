@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS audit_log
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    at          DATETIME NOT NULL,
+    event       TEXT NOT NULL,
+    actor       TEXT NOT NULL,
+    short_code  TEXT,
+    outcome     TEXT NOT NULL,
+    detail      TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_at ON audit_log (at);
+CREATE INDEX IF NOT EXISTS audit_log_short_code ON audit_log (short_code);
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+CREATE TABLE IF NOT EXISTS audit_salt
+(
+    id      INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    salt    BLOB NOT NULL
+);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS clips_deleted_at;
+
+-- without the tombstone, clips in the trash would come back
+DELETE FROM clips WHERE deleted_at IS NOT NULL;
+ALTER TABLE clips DROP COLUMN deleted_at;
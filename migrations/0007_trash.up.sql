@@ -0,0 +1,3 @@
+ALTER TABLE clips ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS clips_deleted_at ON clips (deleted_at);
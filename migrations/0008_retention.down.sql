@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS clips_api_key_id;
+
+ALTER TABLE clips DROP COLUMN api_key_id;
+ALTER TABLE clips DROP COLUMN size;
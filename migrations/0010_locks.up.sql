@@ -0,0 +1,8 @@
+-- leases that let one instance at a time do work such as running the background jobs
+CREATE TABLE IF NOT EXISTS locks
+(
+    name TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    -- unix time in milliseconds
+    expires_at INTEGER NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS api_keys
+(
+    api_key BYTEA PRIMARY KEY
+);
@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS blobs;
+
+ALTER TABLE clips DROP COLUMN attachment_sha256;
+ALTER TABLE clips DROP COLUMN attachment_size;
+ALTER TABLE clips DROP COLUMN attachment_mime;
+ALTER TABLE clips DROP COLUMN attachment_name;
@@ -0,0 +1,10 @@
+ALTER TABLE clips ADD COLUMN attachment_name TEXT;
+ALTER TABLE clips ADD COLUMN attachment_mime TEXT;
+ALTER TABLE clips ADD COLUMN attachment_size BIGINT;
+ALTER TABLE clips ADD COLUMN attachment_sha256 TEXT;
+
+CREATE TABLE IF NOT EXISTS blobs
+(
+    sha256  TEXT PRIMARY KEY NOT NULL,
+    data    BYTEA NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE clips ADD COLUMN content_blob TEXT;
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS audit_log
+(
+    id          BIGSERIAL PRIMARY KEY,
+    at          TIMESTAMP NOT NULL,
+    event       TEXT NOT NULL,
+    actor       TEXT NOT NULL,
+    short_code  TEXT,
+    outcome     TEXT NOT NULL,
+    detail      TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_at ON audit_log (at);
+CREATE INDEX IF NOT EXISTS audit_log_short_code ON audit_log (short_code);
+
+CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
+
+CREATE TABLE IF NOT EXISTS audit_salt
+(
+    id      INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    salt    BYTEA NOT NULL
+);
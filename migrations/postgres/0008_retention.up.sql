@@ -0,0 +1,9 @@
+ALTER TABLE clips ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE clips ADD COLUMN api_key_id TEXT;
+
+-- content moved to a blob store other than the database is not counted for existing clips
+UPDATE clips SET size = OCTET_LENGTH(content)
+    + COALESCE(attachment_size, 0)
+    + COALESCE((SELECT OCTET_LENGTH(data) FROM blobs WHERE blobs.sha256 = clips.content_blob), 0);
+
+CREATE INDEX IF NOT EXISTS clips_api_key_id ON clips (api_key_id);
@@ -0,0 +1,2 @@
+-- keys created before expiry existed never expire
+ALTER TABLE api_keys ADD COLUMN expires_at TIMESTAMP;
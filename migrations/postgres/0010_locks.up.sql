@@ -0,0 +1,7 @@
+-- leases that let one instance at a time do work such as running the background jobs
+CREATE TABLE IF NOT EXISTS locks
+(
+    name TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS clips
+(
+    id          TEXT PRIMARY KEY NOT NULL,
+    short_code  TEXT UNIQUE NOT NULL,
+    content     TEXT NOT NULL,
+    title       TEXT,
+    created_at  TIMESTAMP NOT NULL,
+    expires_at  TIMESTAMP,
+    password    TEXT,
+    views       BIGINT NOT NULL
+);
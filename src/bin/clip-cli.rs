@@ -1,8 +1,10 @@
-use clipshare::domain::clip::field::{Content, ExpiresAt, Password, ShortCode, Title};
-use clipshare::service::ask::{GetClip, NewClip, UpdateClip};
+use clipshare::domain::clip::field::{Content, ExpiresAt, OwnerKey, Password, ShortCode, Title};
+use clipshare::service::ask::{DeleteClip, GetClip, NewClip, RestoreClip, UpdateClip};
 use clipshare::web::api::{ApiKey, API_KEY_HEADER};
+use clipshare::web::owner_cookie;
 use clipshare::Clip;
 use std::error::Error;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -15,7 +17,10 @@ enum Command {
     },
     New {
         #[structopt(help = "content")]
-        clip: String,
+        clip: Option<String>,
+
+        #[structopt(short, long, parse(from_os_str), help = "file to upload")]
+        file: Option<PathBuf>,
 
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
@@ -26,6 +31,28 @@ enum Command {
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
     },
+    /// moves a clip created with this API key, or on the web page, to the trash
+    Delete {
+        short_code: ShortCode,
+
+        #[structopt(short, long, help = "owner key, for clips created on the web page")]
+        owner_key: Option<String>,
+    },
+    /// takes a deleted or expired clip created with this API key, or on the web page, out of
+    /// the trash
+    Restore {
+        short_code: ShortCode,
+
+        #[structopt(short, long, help = "owner key, for clips created on the web page")]
+        owner_key: Option<String>,
+
+        #[structopt(
+            short,
+            long,
+            help = "new expiration date, needed once the clip expired"
+        )]
+        expires_at: Option<ExpiresAt>,
+    },
     Update {
         short_code: ShortCode,
         clip: String,
@@ -76,6 +103,74 @@ fn new_clip(addr: &str, ask_svc: NewClip, api_key: ApiKey) -> Result<Clip, Box<d
     Ok(request.json(&ask_svc).send()?.json()?)
 }
 
+fn upload_clip(
+    addr: &str,
+    ask_svc: NewClip,
+    file: &Path,
+    api_key: ApiKey,
+) -> Result<Clip, Box<dyn Error>> {
+    use reqwest::blocking::multipart::Form;
+
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip", addr);
+
+    let mut form = Form::new()
+        .text("content", ask_svc.content.into_inner())
+        .file("file", file)?;
+    if let Some(title) = ask_svc.title.into_inner() {
+        form = form.text("title", title);
+    }
+    if let Some(expires_at) = ask_svc.exprires_at.into_inner() {
+        form = form.text(
+            "expires_at",
+            expires_at.into_inner().format("%Y-%m-%d").to_string(),
+        );
+    }
+    if let Some(password) = ask_svc.password.into_inner() {
+        form = form.text("password", password);
+    }
+
+    let request = client
+        .post(addr)
+        .header(API_KEY_HEADER, api_key.to_base64())
+        .multipart(form);
+    Ok(request.send()?.json()?)
+}
+
+fn delete_clip(addr: &str, ask_svc: DeleteClip, api_key: ApiKey) -> Result<String, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/{}", addr, ask_svc.short_code.as_str());
+    let mut request = client.delete(addr);
+    if let Some(owner) = ask_svc.owner {
+        request = request.header(
+            reqwest::header::COOKIE,
+            format!("{}={}", owner_cookie(&ask_svc.short_code), owner.as_str()),
+        );
+    }
+
+    request = request.header(API_KEY_HEADER, api_key.to_base64());
+    Ok(request.send()?.json()?)
+}
+
+fn restore_clip(addr: &str, ask_svc: RestoreClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/{}/restore", addr, ask_svc.short_code.as_str());
+    let mut request = client.post(addr);
+    if let Some(expires_at) = ask_svc.expires_at.into_inner() {
+        let expires_at = expires_at.into_inner().format("%Y-%m-%d").to_string();
+        request = request.query(&[("expires_at", expires_at)]);
+    }
+    if let Some(owner) = ask_svc.owner {
+        request = request.header(
+            reqwest::header::COOKIE,
+            format!("{}={}", owner_cookie(&ask_svc.short_code), owner.as_str()),
+        );
+    }
+
+    request = request.header(API_KEY_HEADER, api_key.to_base64());
+    Ok(request.send()?.json()?)
+}
+
 fn update_clip(addr: &str, ask_svc: UpdateClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
     let addr = format!("{}/api/clip", addr);
@@ -101,17 +196,60 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
         }
         Command::New {
             clip,
+            file,
             password,
             expires_at,
             title,
         } => {
+            let content = match (clip, &file) {
+                (Some(clip), _) => clip,
+                (None, Some(file)) => file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                (None, None) => return Err("either clip content or --file is required".into()),
+            };
             let req = NewClip {
-                content: Content::new(clip.as_str())?,
+                content: Content::new(content.as_str())?,
                 title: title.unwrap_or_default(),
                 exprires_at: expires_at.unwrap_or_default(),
                 password: password.unwrap_or_default(),
+                attachment: None,
+                owner: None,
+                api_key: None,
+            };
+            let clip = match file {
+                Some(file) => upload_clip(opt.addr.as_str(), req, &file, opt.api_key)?,
+                None => new_clip(opt.addr.as_str(), req, opt.api_key)?,
+            };
+            println!("{:#?}", clip);
+            Ok(())
+        }
+        Command::Delete {
+            short_code,
+            owner_key,
+        } => {
+            let req = DeleteClip {
+                owner: owner_key.map(OwnerKey::new),
+                api_key: None,
+                short_code,
+            };
+            let message = delete_clip(opt.addr.as_str(), req, opt.api_key)?;
+            println!("{}", message);
+            Ok(())
+        }
+        Command::Restore {
+            short_code,
+            owner_key,
+            expires_at,
+        } => {
+            let req = RestoreClip {
+                owner: owner_key.map(OwnerKey::new),
+                api_key: None,
+                expires_at: expires_at.unwrap_or_default(),
+                short_code,
             };
-            let clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
+            let clip = restore_clip(opt.addr.as_str(), req, opt.api_key)?;
             println!("{:#?}", clip);
             Ok(())
         }
@@ -1,9 +1,25 @@
-use clipshare::data::AppDatabase;
-use clipshare::domain::maintenance::Maintenance;
+use chrono::NaiveDateTime;
+use clipshare::config::{self, AppConfig};
+use clipshare::data::blob::{self, AppBlobStore, BlobStoreConfig, S3Credentials};
+use clipshare::data::migrate::{MigrationError, MigrationState, Migrations};
+use clipshare::data::repository::AppRepository;
+use clipshare::data::Database;
+use clipshare::domain::audit::{self, AuditFilter};
+use clipshare::domain::jobs::{JobContext, Scheduler};
+use clipshare::domain::retention::{Removal, RetentionConfig};
+use clipshare::logging;
+use clipshare::metrics::AppMetrics;
+use clipshare::service::audit::Audit;
+use clipshare::service::{action, ServiceError};
+use clipshare::web::assets::Assets;
 use clipshare::web::renderer::Renderer;
 use clipshare::web::views::Views;
 use dotenv::dotenv;
+use sqlx::migrate::{Migrate, MigrateDatabase};
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -11,39 +27,374 @@ use structopt::StructOpt;
 struct Opt {
     #[structopt(
         default_value = "sqlite:data.db",
-        help = "connection string to sqlite database"
+        help = "connection string to the database: sqlite:<file> or postgres://..."
     )]
     connection_string: String,
 
-    #[structopt(short, long, parse(from_os_str), default_value = "templates/")]
-    template_directory: PathBuf,
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "directory whose .hbs files replace or add to the built-in templates"
+    )]
+    template_directory: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "development mode: read the templates in --template-directory (templates/ by default) again on every request and show rendering errors on the error page"
+    )]
+    dev: bool,
+
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        default_value = "clipshare.toml",
+        env = "CLIPSHARE_CONFIG",
+        help = "TOML configuration file, CLIPSHARE_ environment variables override its values"
+    )]
+    config: PathBuf,
+
+    #[structopt(
+        long,
+        default_value = "database",
+        env = "CLIPSHARE_BLOB_STORE",
+        help = "where clip contents and attachments are stored: database, fs:<dir> or s3:<endpoint>/<bucket>"
+    )]
+    blob_store: BlobStoreConfig,
+
+    #[structopt(long, default_value = "us-east-1", env = "CLIPSHARE_S3_REGION")]
+    s3_region: String,
+
+    #[structopt(long, default_value = "", env = "CLIPSHARE_S3_ACCESS_KEY")]
+    s3_access_key: String,
+
+    #[structopt(
+        long,
+        default_value = "",
+        env = "CLIPSHARE_S3_SECRET_KEY",
+        hide_env_values = true
+    )]
+    s3_secret_key: String,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// manages the database schema
+    Migrate(MigrateCommand),
+    /// reads the audit log
+    Audit(AuditCommand),
+    /// lists the clips the configured retention policies would remove, without removing them
+    Retention,
+}
+
+#[derive(StructOpt, Debug)]
+enum MigrateCommand {
+    /// lists applied and pending migrations
+    Status,
+    /// applies every pending migration
+    Run,
+    /// reverts the most recently applied migration
+    Revert,
+}
+
+#[derive(StructOpt, Debug)]
+enum AuditCommand {
+    /// writes audit entries as newline-delimited JSON, oldest first
+    Export {
+        #[structopt(long, help = "first moment to export, RFC 3339 or a date", parse(try_from_str = parse_time))]
+        since: Option<NaiveDateTime>,
+        #[structopt(long, help = "moment to stop at (exclusive), RFC 3339 or a date", parse(try_from_str = parse_time))]
+        until: Option<NaiveDateTime>,
+        #[structopt(
+            short,
+            long,
+            parse(from_os_str),
+            help = "file to write to instead of stdout"
+        )]
+        output: Option<PathBuf>,
+    },
+}
+
+fn parse_time(time: &str) -> Result<NaiveDateTime, String> {
+    audit::parse_time(time).ok_or_else(|| format!("not a time or date: {}", time))
+}
+
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>, context: &str) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}: {}", context, e);
+        std::process::exit(1)
+    })
+}
+
+async fn migrate<D>(database: &Database<D>, command: &MigrateCommand) -> Result<(), MigrationError>
+where
+    D: Migrations,
+    D::Connection: Migrate,
+{
+    match command {
+        MigrateCommand::Status => {
+            for migration in database.migration_status().await? {
+                let state = match migration.state {
+                    MigrationState::Applied => "applied",
+                    MigrationState::Pending => "pending",
+                    MigrationState::Unknown => "unknown to this build",
+                };
+                println!(
+                    "{:>4}  {:<24} {}",
+                    migration.version, migration.description, state
+                );
+            }
+        }
+        MigrateCommand::Run => {
+            database.migrate().await?;
+            println!("database schema is up to date");
+        }
+        MigrateCommand::Revert => match database.revert().await? {
+            Some(version) => println!("reverted migration {}", version),
+            None => println!("no migrations to revert"),
+        },
+    }
+    Ok(())
+}
+
+/// pages through the audit log and writes every entry as one line of JSON
+async fn export_audit(
+    repository: &AppRepository,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+    output: &mut dyn Write,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut filter = AuditFilter {
+        since,
+        until,
+        limit: audit::MAX_ENTRIES,
+        ..Default::default()
+    };
+    let mut exported = 0;
+    loop {
+        let entries = action::audit_log(&filter, repository.as_ref()).await?;
+        for entry in &entries {
+            serde_json::to_writer(&mut *output, entry)?;
+            output.write_all(b"\n")?;
+        }
+        exported += entries.len() as u64;
+        match entries.last() {
+            Some(last) if entries.len() == filter.limit() as usize => filter.after = Some(last.id),
+            _ => break,
+        }
+    }
+    output.flush()?;
+    Ok(exported)
+}
+
+/// plans the retention policies without applying them
+async fn report_retention(
+    repository: &AppRepository,
+    retention: &RetentionConfig,
+) -> Result<Vec<Removal>, ServiceError> {
+    if !retention.is_enabled() {
+        eprintln!("no retention policy is configured");
+    }
+    let config = RetentionConfig {
+        dry_run: true,
+        ..retention.clone()
+    };
+    action::apply_retention(
+        &config,
+        repository.as_ref(),
+        &Audit::system(repository.as_ref()),
+    )
+    .await
+}
+
+/// connects to the database, then either runs the requested migrate command or returns the
+/// database, with pending migrations applied when it is about to be served
+fn prepare_database<D>(
+    rt: &tokio::runtime::Runtime,
+    connection_string: &str,
+    command: Option<&Command>,
+) -> Option<Database<D>>
+where
+    D: Migrations + MigrateDatabase,
+    D::Connection: Migrate,
+{
+    let database = exit_on_error(
+        rt.block_on(Database::<D>::new(connection_string)),
+        "failed to open database",
+    );
+    match command {
+        Some(Command::Migrate(command)) => {
+            exit_on_error(rt.block_on(migrate(&database, command)), "migration failed");
+            None
+        }
+        // reading the log must not change the schema behind the server's back
+        Some(Command::Audit(_) | Command::Retention) => Some(database),
+        None => {
+            exit_on_error(
+                rt.block_on(database.migrate()),
+                "failed to migrate database",
+            );
+            Some(database)
+        }
+    }
+}
+
+/// opens the repository and blob store for the database named by the connection string scheme.
+/// returns `None` when a command was run instead
+fn open_storage(
+    rt: &tokio::runtime::Runtime,
+    opt: Opt,
+    command: Option<&Command>,
+) -> Option<(AppRepository, AppBlobStore)> {
+    let credentials = S3Credentials {
+        region: opt.s3_region,
+        access_key: opt.s3_access_key,
+        secret_key: opt.s3_secret_key,
+    };
+    let connection_string = opt.connection_string.as_str();
+
+    if connection_string.starts_with("postgres:") || connection_string.starts_with("postgresql:") {
+        #[cfg(feature = "postgres")]
+        {
+            use clipshare::data::repository::PostgresRepository;
+
+            let database = prepare_database::<sqlx::Postgres>(rt, connection_string, command)?;
+            let blobs = blob::open(opt.blob_store, credentials, database.get_pool().clone());
+            let blobs = exit_on_error(blobs, "failed to open blob store");
+            return Some((Arc::new(PostgresRepository::new(database)), blobs));
+        }
+        #[cfg(not(feature = "postgres"))]
+        panic!("httpd was built without PostgreSQL support, enable the `postgres` feature");
+    }
+
+    #[cfg(feature = "sqlite")]
+    {
+        use clipshare::data::repository::SqliteRepository;
+
+        let database = prepare_database::<sqlx::Sqlite>(rt, connection_string, command)?;
+        let blobs = blob::open(opt.blob_store, credentials, database.get_pool().clone());
+        let blobs = exit_on_error(blobs, "failed to open blob store");
+        Some((Arc::new(SqliteRepository::new(database)), blobs))
+    }
+    #[cfg(not(feature = "sqlite"))]
+    panic!("httpd was built without SQLite support, enable the `sqlite` feature");
 }
 
 fn main() {
     dotenv().ok();
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
+    let command = opt.command.take();
+
+    let figment = config::figment(Some(&opt.config));
+    let app_config = match command {
+        // the other commands only need the connection string
+        Some(Command::Retention) | None => {
+            exit_on_error(AppConfig::from_figment(&figment), "refusing to start")
+        }
+        Some(_) => AppConfig::default(),
+    };
+    // commands report on the terminal themselves and an export may be written to stdout, so
+    // only the server logs
+    if command.is_none() {
+        exit_on_error(logging::init(&app_config.log), "failed to set up logging");
+    }
 
     let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
 
     let handle = rt.handle().clone();
-    let renderer = Renderer::new(opt.template_directory.clone());
+    let renderer = match opt.dev {
+        true => {
+            tracing::info!("development mode: templates are reloaded on every request");
+            Renderer::dev(
+                opt.template_directory
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from("templates/")),
+            )
+        }
+        false => Renderer::new(opt.template_directory.clone()),
+    };
+    let renderer = exit_on_error(renderer, "failed to load the templates");
 
-    let database = rt.block_on(async move { AppDatabase::new(&opt.connection_string).await });
+    let Some((repository, blobs)) = open_storage(&rt, opt, command.as_ref()) else {
+        return;
+    };
+    if let Some(Command::Audit(AuditCommand::Export {
+        since,
+        until,
+        output,
+    })) = command
+    {
+        let mut output: Box<dyn Write> = match output {
+            Some(path) => Box::new(BufWriter::new(exit_on_error(
+                File::create(path),
+                "failed to create output file",
+            ))),
+            None => Box::new(BufWriter::new(std::io::stdout().lock())),
+        };
+        let exported = rt.block_on(export_audit(&repository, since, until, &mut output));
+        let exported = exit_on_error(exported, "audit export failed");
+        eprintln!("exported {} audit entries", exported);
+        return;
+    }
+    if let Some(Command::Retention) = command {
+        let removals = rt.block_on(report_retention(&repository, &app_config.retention));
+        let removals = exit_on_error(removals, "retention report failed");
+        for removal in &removals {
+            println!(
+                "{}\t{}\t{}",
+                removal.short_code.as_str(),
+                removal.reason,
+                removal.size
+            );
+        }
+        let bytes: u64 = removals.iter().map(|removal| removal.size).sum();
+        eprintln!("{} clips, {} bytes would be removed", removals.len(), bytes);
+        return;
+    }
 
-    let views = Views::new(database.get_pool().clone(), handle.clone());
-    let maintenance = Maintenance::spawn(database.get_pool().clone(), handle.clone());
+    let assets = exit_on_error(
+        Assets::from_config(&app_config),
+        "failed to read static_dir or theme_css",
+    );
+    let metrics = AppMetrics::default();
+    let views = Views::new(
+        repository.clone(),
+        metrics.clone(),
+        app_config.views_flush_interval(),
+        app_config.views_batch_size,
+        handle.clone(),
+    );
+    let context = JobContext {
+        repository: repository.clone(),
+        blobs: blobs.clone(),
+        metrics: metrics.clone(),
+        trash_retention: app_config.trash_retention(),
+        retention: app_config.retention.clone(),
+    };
+    let scheduler = Scheduler::spawn(context, &app_config.jobs, handle.clone());
 
     let config = clipshare::RocketConfig {
+        figment,
+        config: app_config,
         renderer,
-        database,
+        assets,
+        repository,
+        blobs,
+        metrics,
         views,
-        maintenance,
+        scheduler,
     };
 
     rt.block_on(async move {
-        clipshare::rocket(config)
+        let rocket = clipshare::rocket(config)
             .launch()
             .await
-            .expect("failed to launch rocket server")
+            .expect("failed to launch rocket server");
+        // after the grace period, once no request is left to need the database
+        clipshare::shutdown(&rocket).await;
     });
 }
@@ -0,0 +1,289 @@
+// Application settings are read from a TOML file and `CLIPSHARE_` environment variables,
+// layered on top of Rocket's own figment so one file can configure both.
+use crate::domain::jobs::{JobName, JobsConfig};
+use crate::domain::retention::RetentionConfig;
+use crate::logging::{LogConfig, Redacted};
+use rocket::figment::providers::{Env, Format, Toml};
+use rocket::figment::Figment;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use strum::IntoEnumIterator;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read configuration: {0}")]
+    Figment(Box<rocket::figment::Error>),
+
+    #[error("invalid configuration value `{key}`: {reason}")]
+    Invalid { key: &'static str, reason: String },
+}
+
+impl From<rocket::figment::Error> for ConfigError {
+    fn from(err: rocket::figment::Error) -> Self {
+        Self::Figment(Box::new(err))
+    }
+}
+
+fn invalid<T: Into<String>>(key: &'static str, reason: T) -> ConfigError {
+    ConfigError::Invalid {
+        key,
+        reason: reason.into(),
+    }
+}
+
+/// settings for creating and storing clips
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClipConfig {
+    pub short_code_length: usize,
+    /// characters short codes are made of
+    pub short_code_alphabet: String,
+    /// clip contents larger than this many bytes are moved to the blob store
+    pub inline_content_limit: usize,
+}
+
+impl Default for ClipConfig {
+    fn default() -> Self {
+        Self {
+            short_code_length: 10,
+            short_code_alphabet: "abcd1234".to_owned(),
+            inline_content_limit: crate::data::blob::INLINE_CONTENT_LIMIT,
+        }
+    }
+}
+
+impl ClipConfig {
+    pub fn short_code_chars(&self) -> Vec<char> {
+        self.short_code_alphabet.chars().collect()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !(4..=64).contains(&self.short_code_length) {
+            return Err(invalid(
+                "clip.short_code_length",
+                format!("must be between 4 and 64, got {}", self.short_code_length),
+            ));
+        }
+
+        let mut chars = self.short_code_chars();
+        if let Some(c) = chars
+            .iter()
+            .find(|c| !(c.is_ascii_alphanumeric() || **c == '-' || **c == '_'))
+        {
+            return Err(invalid(
+                "clip.short_code_alphabet",
+                format!(
+                    "'{}' is not allowed in URLs, use letters, digits, '-' and '_'",
+                    c
+                ),
+            ));
+        }
+        chars.sort_unstable();
+        chars.dedup();
+        if chars.len() < 2 {
+            return Err(invalid(
+                "clip.short_code_alphabet",
+                "needs at least two distinct characters",
+            ));
+        }
+
+        if self.inline_content_limit == 0 {
+            return Err(invalid("clip.inline_content_limit", "must be positive"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// seconds deleted and expired clips stay in the trash, where their owners can restore
+    /// them, before they are purged
+    pub trash_retention: u64,
+    /// seconds between writes of buffered view counts to the database
+    pub views_flush_interval: u64,
+    /// number of clips with buffered views that triggers a write before the interval is up
+    pub views_batch_size: usize,
+    /// seconds allowed for flushing views and closing the database on shutdown
+    pub shutdown_timeout: u64,
+    /// directory whose files replace or add to the static files built into the binary
+    pub static_dir: Option<PathBuf>,
+    /// stylesheet linked after the built-in themes on every page, which can set their color
+    /// variables
+    pub theme_css: Option<PathBuf>,
+    /// key for the admin endpoints such as `/metrics`, sent in the `x-admin-key` header. they
+    /// are disabled without one
+    pub admin_key: Option<Redacted<String>>,
+    /// seconds new API keys are valid for. they do not expire without one
+    pub api_key_lifetime: Option<u64>,
+    pub log: LogConfig,
+    pub clip: ClipConfig,
+    pub retention: RetentionConfig,
+    pub jobs: JobsConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            trash_retention: 7 * 24 * 60 * 60,
+            views_flush_interval: 5,
+            views_batch_size: 100,
+            shutdown_timeout: 10,
+            static_dir: None,
+            theme_css: None,
+            admin_key: None,
+            api_key_lifetime: None,
+            log: LogConfig::default(),
+            clip: ClipConfig::default(),
+            retention: RetentionConfig::default(),
+            jobs: JobsConfig::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// reads and validates the configuration from a figment built by [`figment`]
+    pub fn from_figment(figment: &Figment) -> Result<Self, ConfigError> {
+        let config: Self = figment.extract()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn api_key_lifetime(&self) -> Option<Duration> {
+        self.api_key_lifetime.map(Duration::from_secs)
+    }
+
+    pub fn trash_retention(&self) -> Duration {
+        Duration::from_secs(self.trash_retention)
+    }
+
+    pub fn views_flush_interval(&self) -> Duration {
+        Duration::from_secs(self.views_flush_interval)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.views_flush_interval == 0 {
+            return Err(invalid(
+                "views_flush_interval",
+                "must be at least one second",
+            ));
+        }
+        if !(1..=10_000).contains(&self.views_batch_size) {
+            return Err(invalid(
+                "views_batch_size",
+                format!("must be between 1 and 10000, got {}", self.views_batch_size),
+            ));
+        }
+        if self.shutdown_timeout == 0 {
+            return Err(invalid("shutdown_timeout", "must be at least one second"));
+        }
+        if let Some(static_dir) = self.static_dir.as_ref().filter(|dir| !dir.is_dir()) {
+            return Err(invalid(
+                "static_dir",
+                format!("'{}' is not a directory", static_dir.display()),
+            ));
+        }
+        if let Some(theme_css) = self.theme_css.as_ref().filter(|file| !file.is_file()) {
+            return Err(invalid(
+                "theme_css",
+                format!("'{}' is not a file", theme_css.display()),
+            ));
+        }
+        if matches!(&self.admin_key, Some(Redacted(key)) if key.len() < 16) {
+            return Err(invalid("admin_key", "must be at least 16 characters"));
+        }
+        if let Err(e) = self.log.env_filter() {
+            return Err(invalid("log.filter", e));
+        }
+        if self.api_key_lifetime == Some(0) {
+            return Err(invalid("api_key_lifetime", "must be at least one second"));
+        }
+        if self.jobs.lease == 0 {
+            return Err(invalid("jobs.lease", "must be at least one second"));
+        }
+        for job in JobName::iter() {
+            if self.jobs.schedule(job).interval == 0 {
+                return Err(invalid(
+                    "jobs",
+                    format!("the interval of {} must be at least one second", job),
+                ));
+            }
+        }
+        if self.retention.max_age_days == Some(0) || self.retention.idle_days == Some(0) {
+            return Err(invalid(
+                "retention",
+                "`max_age_days` and `idle_days` must be at least one day",
+            ));
+        }
+        self.clip.validate()
+    }
+}
+
+/// Rocket's figment (`Rocket.toml`, `ROCKET_` variables) with the TOML file at `path` merged in,
+/// then `CLIPSHARE_` variables on top. nested keys use `__`, e.g. `CLIPSHARE_CLIP__SHORT_CODE_LENGTH`.
+/// a missing file is not an error
+pub fn figment(path: Option<&Path>) -> Figment {
+    let figment = rocket::Config::figment();
+    let figment = match path {
+        Some(path) => figment.merge(Toml::file(path)),
+        None => figment,
+    };
+    figment.merge(Env::prefixed("CLIPSHARE_").split("__").global())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    // the jail's closure returns figment's error
+    #[allow(clippy::result_large_err)]
+    fn layers_file_and_env() {
+        // the jail restores the environment and keeps other jailed tests out meanwhile
+        figment::Jail::expect_with(|jail| {
+            jail.create_file(
+                "clipshare.toml",
+                r#"
+                port = 9000
+
+                [jobs.expired_cleanup]
+                interval = 60
+
+                [clip]
+                short_code_length = 6
+                short_code_alphabet = "xyz"
+                "#,
+            )?;
+            jail.set_env("CLIPSHARE_CLIP__INLINE_CONTENT_LIMIT", 1024);
+            let path = Path::new("clipshare.toml");
+
+            let figment = figment(Some(path));
+            let config = AppConfig::from_figment(&figment).unwrap();
+            assert_eq!(config.jobs.expired_cleanup.interval, 60);
+            assert_eq!(config.jobs.expired_cleanup.jitter, 0);
+            assert_eq!(config.jobs.optimize.interval, 24 * 60 * 60);
+            assert_eq!(config.views_flush_interval, 5);
+            assert_eq!(config.clip.short_code_length, 6);
+            assert_eq!(config.clip.short_code_alphabet, "xyz");
+            assert_eq!(config.clip.inline_content_limit, 1024);
+            let rocket: rocket::Config = figment.extract()?;
+            assert_eq!(rocket.port, 9000);
+
+            jail.set_env("CLIPSHARE_CLIP__SHORT_CODE_ALPHABET", "a/b");
+            let invalid = AppConfig::from_figment(&super::figment(Some(path)));
+            assert!(matches!(
+                invalid,
+                Err(ConfigError::Invalid {
+                    key: "clip.short_code_alphabet",
+                    ..
+                })
+            ));
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,85 @@
+use super::{validate_key, BlobError, BlobStore};
+#[cfg(feature = "postgres")]
+use crate::data::pg_query;
+#[cfg(feature = "sqlite")]
+use crate::data::query;
+use crate::data::DataError;
+
+/// stores blobs in the `blobs` table of the application database
+pub struct DatabaseBlobStore<D: sqlx::Database>(sqlx::Pool<D>);
+
+impl<D: sqlx::Database> DatabaseBlobStore<D> {
+    pub fn new(pool: sqlx::Pool<D>) -> Self {
+        Self(pool)
+    }
+}
+
+impl From<DataError> for BlobError {
+    fn from(err: DataError) -> Self {
+        match err {
+            DataError::Database(e) => Self::Database(e),
+            DataError::NotFound => Self::NotFound(String::new()),
+        }
+    }
+}
+
+fn not_found_as_key<T>(key: &str, result: Result<T, DataError>) -> Result<T, BlobError> {
+    match result {
+        Err(DataError::NotFound) => Err(BlobError::NotFound(key.to_owned())),
+        other => Ok(other?),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[rocket::async_trait]
+impl BlobStore for DatabaseBlobStore<sqlx::Sqlite> {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        validate_key(key)?;
+        Ok(query::put_blob(key, data, &self.0).await?)
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        validate_key(key)?;
+        not_found_as_key(key, query::get_blob(key, &self.0).await)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        validate_key(key)?;
+        Ok(query::delete_blob(key, &self.0).await?)
+    }
+
+    async fn stored_bytes(&self) -> Result<Option<u64>, BlobError> {
+        Ok(Some(query::blob_bytes(&self.0).await?))
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>, BlobError> {
+        Ok(Some(query::blob_keys(&self.0).await?))
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[rocket::async_trait]
+impl BlobStore for DatabaseBlobStore<sqlx::Postgres> {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        validate_key(key)?;
+        Ok(pg_query::put_blob(key, data, &self.0).await?)
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        validate_key(key)?;
+        not_found_as_key(key, pg_query::get_blob(key, &self.0).await)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        validate_key(key)?;
+        Ok(pg_query::delete_blob(key, &self.0).await?)
+    }
+
+    async fn stored_bytes(&self) -> Result<Option<u64>, BlobError> {
+        Ok(Some(pg_query::blob_bytes(&self.0).await?))
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>, BlobError> {
+        Ok(Some(pg_query::blob_keys(&self.0).await?))
+    }
+}
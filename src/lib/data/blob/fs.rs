@@ -0,0 +1,110 @@
+use super::{validate_key, BlobError, BlobStore};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::fs;
+
+/// stores blobs as files below a root directory, sharded by the first two characters of the key
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(root: PathBuf) -> Result<Self, BlobError> {
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf, BlobError> {
+        validate_key(key)?;
+        Ok(self.root.join(&key[..2]).join(key))
+    }
+}
+
+#[rocket::async_trait]
+impl BlobStore for FsBlobStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        let path = self.path_for(key)?;
+        if fs::try_exists(&path).await? {
+            return Ok(());
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+
+        // write next to the destination and rename, so readers never see a partial blob
+        let partial = path.with_extension(format!("{}.partial", uuid::Uuid::new_v4()));
+        fs::write(&partial, data).await?;
+        if let Err(e) = fs::rename(&partial, &path).await {
+            let _ = fs::remove_file(&partial).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        match fs::read(self.path_for(key)?).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(BlobError::NotFound(key.to_owned())),
+            other => Ok(other?),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match fs::remove_file(self.path_for(key)?).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            other => Ok(other?),
+        }
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>, BlobError> {
+        let mut keys = vec![];
+        let mut shards = fs::read_dir(&self.root).await?;
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut blobs = fs::read_dir(shard.path()).await?;
+            while let Some(blob) = blobs.next_entry().await? {
+                // skips writes in progress, which are named after their key plus a suffix
+                match blob.file_name().into_string() {
+                    Ok(key) if validate_key(&key).is_ok() => keys.push(key),
+                    _ => {}
+                }
+            }
+        }
+        Ok(Some(keys))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::blob::key_for;
+    use crate::test::async_runtime;
+
+    #[test]
+    fn stores_and_removes_blobs() {
+        let rt = async_runtime();
+        let root = std::env::temp_dir().join(format!("clipshare-blobs-{}", uuid::Uuid::new_v4()));
+        let store = FsBlobStore::new(root.clone()).unwrap();
+        let data = b"blob content".to_vec();
+        let key = key_for(&data);
+
+        rt.block_on(async {
+            store.put(&key, data.clone()).await.unwrap();
+            store.put(&key, data.clone()).await.unwrap();
+            assert_eq!(store.get(&key).await.unwrap(), data);
+            assert_eq!(store.keys().await.unwrap(), Some(vec![key.clone()]));
+
+            store.delete(&key).await.unwrap();
+            store.delete(&key).await.unwrap();
+            assert!(matches!(store.get(&key).await, Err(BlobError::NotFound(_))));
+            assert!(matches!(
+                store.get("../../etc/passwd").await,
+                Err(BlobError::InvalidKey(_))
+            ));
+        });
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
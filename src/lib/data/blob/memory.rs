@@ -0,0 +1,39 @@
+use super::{validate_key, BlobError, BlobStore};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+/// keeps blobs in process memory, alongside a `MemoryRepository`
+#[derive(Default)]
+pub struct MemoryBlobStore(RwLock<HashMap<String, Vec<u8>>>);
+
+#[rocket::async_trait]
+impl BlobStore for MemoryBlobStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), BlobError> {
+        validate_key(key)?;
+        self.0.write().entry(key.to_owned()).or_insert(data);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        self.0
+            .read()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| BlobError::NotFound(key.to_owned()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        self.0.write().remove(key);
+        Ok(())
+    }
+
+    async fn stored_bytes(&self) -> Result<Option<u64>, BlobError> {
+        Ok(Some(
+            self.0.read().values().map(|data| data.len() as u64).sum(),
+        ))
+    }
+
+    async fn keys(&self) -> Result<Option<Vec<String>>, BlobError> {
+        Ok(Some(self.0.read().keys().cloned().collect()))
+    }
+}
@@ -0,0 +1,167 @@
+// Blob storage keeps clip contents and attachments out of the `clips` rows.
+// Blobs are content addressed: the key is the SHA-256 digest of the data.
+pub mod database;
+pub mod fs;
+pub mod memory;
+pub mod s3;
+
+pub use database::DatabaseBlobStore;
+pub use fs::FsBlobStore;
+pub use memory::MemoryBlobStore;
+pub use s3::{S3BlobStore, S3Config};
+
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("blob not found: {0}")]
+    NotFound(String),
+
+    #[error("invalid blob key: {0}")]
+    InvalidKey(String),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("storage backend error: {0}")]
+    Backend(String),
+}
+
+#[rocket::async_trait]
+pub trait BlobStore: Send + Sync {
+    /// stores `data` under `key`. storing an existing key is not an error
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), BlobError>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, BlobError>;
+
+    /// removes the blob under `key`. removing a missing key is not an error
+    async fn delete(&self, key: &str) -> Result<(), BlobError>;
+
+    /// total size of the stored blobs, when the store can tell without listing every blob
+    async fn stored_bytes(&self) -> Result<Option<u64>, BlobError> {
+        Ok(None)
+    }
+
+    /// keys of every stored blob, when the store can list them
+    async fn keys(&self) -> Result<Option<Vec<String>>, BlobError> {
+        Ok(None)
+    }
+}
+
+pub type AppBlobStore = Arc<dyn BlobStore>;
+
+/// clip contents larger than this many bytes are moved to the blob store
+pub const INLINE_CONTENT_LIMIT: usize = 64 * 1024;
+
+/// content address used as the key for `data`
+pub fn key_for(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// keys are hex SHA-256 digests, which also keeps them safe to use as file names and URL paths
+fn validate_key(key: &str) -> Result<(), BlobError> {
+    if key.len() == 64 && key.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(BlobError::InvalidKey(key.to_owned()))
+    }
+}
+
+/// selects a blob storage backend:
+/// `database`, `fs:<directory>` or `s3:<endpoint>/<bucket>`
+#[derive(Debug, Clone)]
+pub enum BlobStoreConfig {
+    Database,
+    Fs(PathBuf),
+    S3 { endpoint: String, bucket: String },
+}
+
+impl FromStr for BlobStoreConfig {
+    type Err = BlobError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "database" => Ok(Self::Database),
+            Some(("fs", dir)) if !dir.is_empty() => Ok(Self::Fs(PathBuf::from(dir))),
+            Some(("s3", location)) => match location.trim_end_matches('/').rsplit_once('/') {
+                Some((endpoint, bucket)) if endpoint.contains("://") && !bucket.is_empty() => {
+                    Ok(Self::S3 {
+                        endpoint: endpoint.to_owned(),
+                        bucket: bucket.to_owned(),
+                    })
+                }
+                _ => Err(BlobError::Backend(format!(
+                    "expected s3:<endpoint>/<bucket>, got '{}'",
+                    s
+                ))),
+            },
+            _ => Err(BlobError::Backend(format!(
+                "unknown blob store '{}', expected database, fs:<dir> or s3:<endpoint>/<bucket>",
+                s
+            ))),
+        }
+    }
+}
+
+/// credentials and region for the `s3` backend
+#[derive(Debug, Clone, Default)]
+pub struct S3Credentials {
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// opens the configured backend. `pool` is the application database, used by the `database` backend
+pub fn open<D: sqlx::Database>(
+    config: BlobStoreConfig,
+    credentials: S3Credentials,
+    pool: sqlx::Pool<D>,
+) -> Result<AppBlobStore, BlobError>
+where
+    DatabaseBlobStore<D>: BlobStore,
+{
+    Ok(match config {
+        BlobStoreConfig::Database => Arc::new(DatabaseBlobStore::new(pool)),
+        BlobStoreConfig::Fs(root) => Arc::new(FsBlobStore::new(root)?),
+        BlobStoreConfig::S3 { endpoint, bucket } => Arc::new(S3BlobStore::new(S3Config {
+            endpoint,
+            bucket,
+            region: credentials.region,
+            access_key: credentials.access_key,
+            secret_key: credentials.secret_key,
+        })?),
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parses_store_config() {
+        assert!(matches!(
+            "database".parse::<BlobStoreConfig>(),
+            Ok(BlobStoreConfig::Database)
+        ));
+        assert!(matches!(
+            "fs:/var/lib/clipshare".parse::<BlobStoreConfig>(),
+            Ok(BlobStoreConfig::Fs(dir)) if dir == std::path::Path::new("/var/lib/clipshare")
+        ));
+        assert!(matches!(
+            "s3:http://localhost:9000/clips".parse::<BlobStoreConfig>(),
+            Ok(BlobStoreConfig::S3 { endpoint, bucket })
+                if endpoint == "http://localhost:9000" && bucket == "clips"
+        ));
+        assert!("s3:clips".parse::<BlobStoreConfig>().is_err());
+        assert!("ftp:somewhere".parse::<BlobStoreConfig>().is_err());
+    }
+}
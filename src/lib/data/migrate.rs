@@ -0,0 +1,177 @@
+// Migrations are embedded in the binary, so a deployment only needs the executable.
+// SQLite migrations live in `migrations/` and PostgreSQL ones in `migrations/postgres/`.
+use super::Database;
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations");
+
+#[cfg(feature = "postgres")]
+pub static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("migration error: {0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error(
+        "database schema is at version {applied} but this build only knows migrations up to \
+         version {latest}, refusing to use it"
+    )]
+    SchemaTooNew { applied: i64, latest: i64 },
+}
+
+/// database backend with migrations embedded in the binary
+pub trait Migrations: sqlx::Database {
+    fn migrator() -> &'static Migrator;
+}
+
+#[cfg(feature = "sqlite")]
+impl Migrations for sqlx::Sqlite {
+    fn migrator() -> &'static Migrator {
+        &SQLITE_MIGRATIONS
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Migrations for sqlx::Postgres {
+    fn migrator() -> &'static Migrator {
+        &POSTGRES_MIGRATIONS
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// applied to the database, but not known to this build
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+type Result<T> = std::result::Result<T, MigrationError>;
+
+impl<D: Migrations> Database<D>
+where
+    D::Connection: Migrate,
+{
+    async fn applied_versions(&self) -> Result<Vec<i64>> {
+        let mut conn = self.0.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        Ok(conn
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect())
+    }
+
+    /// every migration known to this build or applied to the database, ordered by version
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        let applied = self.applied_versions().await?;
+
+        let mut status = D::migrator()
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .map(|migration| MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state: match applied.contains(&migration.version) {
+                    true => MigrationState::Applied,
+                    false => MigrationState::Pending,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        for version in applied {
+            if !status.iter().any(|migration| migration.version == version) {
+                status.push(MigrationStatus {
+                    version,
+                    description: String::new(),
+                    state: MigrationState::Unknown,
+                });
+            }
+        }
+        status.sort_by_key(|migration| migration.version);
+        Ok(status)
+    }
+
+    /// fails with `SchemaTooNew` when the database has migrations this build does not know about
+    pub async fn check_schema(&self) -> Result<()> {
+        let latest = D::migrator()
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or_default();
+        match self.applied_versions().await?.into_iter().max() {
+            Some(applied) if applied > latest => {
+                Err(MigrationError::SchemaTooNew { applied, latest })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// applies every pending migration
+    pub async fn migrate(&self) -> Result<()> {
+        self.check_schema().await?;
+        Ok(D::migrator().run(&self.0).await?)
+    }
+
+    /// reverts the most recently applied migration, returning its version
+    pub async fn revert(&self) -> Result<Option<i64>> {
+        self.check_schema().await?;
+        let mut applied = self.applied_versions().await?;
+        applied.sort_unstable();
+
+        let Some(last) = applied.pop() else {
+            return Ok(None);
+        };
+        let target = applied.pop().unwrap_or_default();
+        D::migrator().undo(&self.0, target).await?;
+        Ok(Some(last))
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+pub mod test {
+    use super::*;
+    use crate::data::test::new_db;
+    use crate::test::async_runtime;
+
+    #[test]
+    fn reverts_and_refuses_newer_schema() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+
+        rt.block_on(async {
+            let latest = SQLITE_MIGRATIONS.iter().map(|m| m.version).max().unwrap();
+            assert_eq!(db.revert().await.unwrap(), Some(latest));
+            let status = db.migration_status().await.unwrap();
+            assert_eq!(status.last().unwrap().state, MigrationState::Pending);
+            db.migrate().await.unwrap();
+
+            sqlx::query(
+                "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+                VALUES (9999, 'from the future', TRUE, X'00', 0)",
+            )
+            .execute(db.get_pool())
+            .await
+            .unwrap();
+            assert!(matches!(
+                db.migrate().await,
+                Err(MigrationError::SchemaTooNew { applied: 9999, .. })
+            ));
+            let status = db.migration_status().await.unwrap();
+            assert_eq!(status.last().unwrap().state, MigrationState::Unknown);
+        });
+    }
+}
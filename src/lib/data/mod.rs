@@ -1,8 +1,21 @@
+pub mod blob;
+pub mod migrate;
 pub mod model;
+#[cfg(feature = "postgres")]
+pub mod pg_query;
+#[cfg(feature = "sqlite")]
 pub mod query;
+pub mod repository;
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("enable at least one database backend: the `sqlite` or `postgres` feature");
 
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
+use sqlx::migrate::MigrateDatabase;
+#[cfg(feature = "postgres")]
+use sqlx::Postgres;
+#[cfg(feature = "sqlite")]
 use sqlx::Sqlite;
 use std::str::FromStr;
 use uuid::Uuid;
@@ -10,38 +23,84 @@ use uuid::Uuid;
 #[derive(Debug, thiserror::Error)]
 pub enum DataError {
     #[error("database error: {0}")]
-    Database(#[from] sqlx::Error),
+    Database(sqlx::Error),
+
+    #[error("record not found")]
+    NotFound,
+}
+
+impl From<sqlx::Error> for DataError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => Self::NotFound,
+            other => Self::Database(other),
+        }
+    }
 }
 
+#[cfg(feature = "sqlite")]
 pub type AppDatabase = Database<Sqlite>;
+#[cfg(feature = "sqlite")]
 pub type DatabasePool = sqlx::sqlite::SqlitePool;
+#[cfg(feature = "sqlite")]
 pub type Transaction<'t> = sqlx::Transaction<'t, Sqlite>;
+#[cfg(feature = "sqlite")]
 pub type AppDatabaseRow = sqlx::sqlite::SqliteRow;
+#[cfg(feature = "sqlite")]
 pub type AppQueryResult = sqlx::sqlite::SqliteQueryResult;
 
+#[cfg(feature = "postgres")]
+pub type PgDatabase = Database<Postgres>;
+#[cfg(feature = "postgres")]
+pub type PgPool = sqlx::postgres::PgPool;
+
 pub struct Database<D: sqlx::Database>(sqlx::Pool<D>);
 
-impl Database<Sqlite> {
-    pub async fn new(connection_str: &str) -> Self {
-        let pool = sqlx::sqlite::SqlitePoolOptions::new()
-            .connect(connection_str)
-            .await;
-
-        match pool {
-            Ok(pool) => Self(pool),
-            Err(e) => {
-                eprintln!("{}\n", e);
-                eprintln!(
-                    "If the database has not yet been created, run: \n sqlx[.exe] database setup\n"
-                );
-                panic!("database connection error")
-            }
+/// connections of a database pool
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStats {
+    pub open: u32,
+    pub idle: u32,
+    pub max: u32,
+}
+
+impl<D: sqlx::Database + MigrateDatabase> Database<D> {
+    /// connects to the database, creating it first when it does not exist yet
+    pub async fn new(connection_str: &str) -> Result<Self, DataError> {
+        if !D::database_exists(connection_str).await? {
+            D::create_database(connection_str).await?;
         }
+        let pool = sqlx::pool::PoolOptions::<D>::new()
+            .connect(connection_str)
+            .await?;
+        Ok(Self(pool))
     }
+}
 
-    pub fn get_pool(&self) -> &DatabasePool {
+impl<D: sqlx::Database> Database<D> {
+    pub fn get_pool(&self) -> &sqlx::Pool<D> {
         &self.0
     }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            open: self.0.size(),
+            idle: self.0.num_idle() as u32,
+            max: self.0.options().get_max_connections(),
+        }
+    }
+
+    /// checks that a connection can be acquired and answers
+    pub async fn ping(&self) -> Result<(), DataError> {
+        use sqlx::Connection;
+
+        Ok(self.0.acquire().await?.ping().await?)
+    }
+
+    /// waits for checked out connections to be returned, then closes every connection
+    pub async fn close(&self) {
+        self.0.close().await
+    }
 }
 
 #[derive(Clone, Debug, From, Display, Deserialize, Serialize)]
@@ -82,17 +141,78 @@ pub mod test {
     use crate::data::*;
     use tokio::runtime::Handle;
 
+    #[cfg(feature = "sqlite")]
     pub fn new_db(handle: &Handle) -> AppDatabase {
-        use sqlx::migrate::Migrator;
-        use std::path::Path;
-
         handle.block_on(async move {
-            let db = Database::new(":memory:").await;
-            let migrator = Migrator::new(Path::new("./migrations")).await.unwrap();
+            let db = AppDatabase::new(":memory:").await.unwrap();
+            db.migrate().await.unwrap();
+            db
+        })
+    }
 
-            let pool = db.get_pool();
+    /// connection string of the Postgres server used by the tests: `CLIPSHARE_TEST_POSTGRES_URL`,
+    /// or a server on localhost. `None` when the local server cannot be reached, so the tests
+    /// only run on the other backends. a server named in the variable has to be reachable
+    #[cfg(feature = "postgres")]
+    pub fn postgres_url() -> Option<String> {
+        use sqlx::{Connection, PgConnection};
+        use std::sync::OnceLock;
+        use std::time::Duration;
+
+        static URL: OnceLock<Option<String>> = OnceLock::new();
+        URL.get_or_init(|| {
+            let (url, required) = match std::env::var("CLIPSHARE_TEST_POSTGRES_URL") {
+                Ok(url) => (url, true),
+                Err(_) => ("postgres://postgres@localhost/postgres".to_owned(), false),
+            };
+            let connected = crate::test::background_runtime().block_on(async {
+                let connect = PgConnection::connect(&url);
+                match tokio::time::timeout(Duration::from_secs(5), connect).await {
+                    Ok(Ok(connection)) => connection.close().await.map_err(|e| e.to_string()),
+                    Ok(Err(e)) => Err(e.to_string()),
+                    Err(_) => Err("timed out".to_owned()),
+                }
+            });
+            match connected {
+                Ok(()) => Some(url),
+                Err(e) if required => panic!("cannot reach the Postgres server to test on: {}", e),
+                Err(e) => {
+                    eprintln!(
+                        "not testing on Postgres, there is no server on localhost: {}",
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .clone()
+    }
+
+    /// migrated database in a fresh schema, so tests sharing a server do not see each other's rows
+    #[cfg(feature = "postgres")]
+    pub fn new_pg_db(handle: &Handle, url: &str) -> PgDatabase {
+        use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 
-            migrator.run(pool).await.unwrap();
+        let schema = format!("test_{}", Uuid::new_v4().simple());
+        handle.block_on(async move {
+            let options: PgConnectOptions = url.parse().unwrap();
+            let setup = PgPoolOptions::new()
+                .max_connections(1)
+                .connect_with(options.clone())
+                .await
+                .unwrap();
+            sqlx::query(&format!("CREATE SCHEMA {}", schema))
+                .execute(&setup)
+                .await
+                .unwrap();
+            setup.close().await;
+
+            let pool = PgPoolOptions::new()
+                .connect_with(options.options([("search_path", schema.as_str())]))
+                .await
+                .unwrap();
+            let db = Database(pool);
+            db.migrate().await.unwrap();
             db
         })
     }
@@ -1,9 +1,13 @@
+use crate::config::ClipConfig;
 use crate::data::DbId;
+use crate::domain::audit::{AuditEntry, AuditEvent, Outcome};
+use crate::domain::clip::field::{OwnerKey, Password};
+use crate::domain::stats::{DailyViews, VisitorSketch};
 use crate::{ClipError, ShortCode, Time};
-use chrono::{NaiveDateTime, Utc};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
 use std::convert::TryFrom;
 
-#[derive(Debug, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Clip {
     pub(in crate::data) id: String,
     pub(in crate::data) short_code: String,
@@ -13,6 +17,102 @@ pub struct Clip {
     pub(in crate::data) expires_at: Option<NaiveDateTime>,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) views: i64,
+    pub(in crate::data) attachment_name: Option<String>,
+    pub(in crate::data) attachment_mime: Option<String>,
+    pub(in crate::data) attachment_size: Option<i64>,
+    pub(in crate::data) attachment_sha256: Option<String>,
+    pub(in crate::data) content_blob: Option<String>,
+    pub(in crate::data) owner_key: Option<String>,
+    /// when the clip went to the trash, `None` while it is live
+    pub(in crate::data) deleted_at: Option<NaiveDateTime>,
+    /// bytes of content and attachment, wherever they are stored
+    pub(in crate::data) size: i64,
+    /// id of the API key the clip was created with
+    pub(in crate::data) api_key_id: Option<String>,
+}
+
+impl Clip {
+    /// key of the blob holding the content when it is too large to be stored inline
+    pub fn content_blob(&self) -> Option<&str> {
+        self.content_blob.as_deref()
+    }
+
+    pub fn with_content(self, content: String) -> Self {
+        Self { content, ..self }
+    }
+
+    /// whether `password` opens the clip, which is always the case without a password
+    pub fn is_unlocked_by(&self, password: &Password) -> bool {
+        match &self.password {
+            Some(stored) => password.as_str() == Some(stored.as_str()),
+            None => true,
+        }
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// whether `key` is the owner key handed out when the clip was created
+    pub fn is_owned_by(&self, key: &OwnerKey) -> bool {
+        self.owner_key.as_deref() == Some(key.hash().as_str())
+    }
+
+    /// whether the clip was created by whoever presents `owner`, the key handed out by the web
+    /// form, or the API key with the id `api_key`. only they may delete or restore it and see
+    /// its statistics
+    pub fn is_managed_by(&self, owner: Option<&OwnerKey>, api_key: Option<&str>) -> bool {
+        owner.is_some_and(|owner| self.is_owned_by(owner))
+            || api_key.is_some_and(|id| self.api_key_id.as_deref() == Some(id))
+    }
+
+    /// whether the clip expired before `time`
+    pub fn expired_before(&self, time: NaiveDateTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at < time)
+    }
+
+    /// whether the clip went to the trash before `cutoff`, closing its restore window
+    pub fn trashed_before(&self, cutoff: NaiveDateTime) -> bool {
+        self.deleted_at
+            .is_some_and(|deleted_at| deleted_at < cutoff)
+    }
+
+    pub fn blob_refs(&self) -> BlobRefs {
+        BlobRefs {
+            attachment_sha256: self.attachment_sha256.clone(),
+            content_blob: self.content_blob.clone(),
+        }
+    }
+}
+
+/// blobs referenced by a single clip
+#[derive(Debug, Default, sqlx::FromRow)]
+pub struct BlobRefs {
+    pub(in crate::data) attachment_sha256: Option<String>,
+    pub(in crate::data) content_blob: Option<String>,
+}
+
+impl BlobRefs {
+    pub fn into_keys(self) -> impl Iterator<Item = String> {
+        self.attachment_sha256.into_iter().chain(self.content_blob)
+    }
+}
+
+/// data that must be written to the blob store before the row referencing it is saved
+pub struct PendingBlob {
+    pub key: String,
+    pub data: Vec<u8>,
+}
+
+/// moves content above the inline limit into a pending blob, leaving an empty inline content
+fn offload_content(content: String, inline_limit: usize) -> (String, Option<PendingBlob>) {
+    if content.len() > inline_limit {
+        let data = content.into_bytes();
+        let key = super::blob::key_for(&data);
+        (String::new(), Some(PendingBlob { key, data }))
+    } else {
+        (content, None)
+    }
 }
 
 impl TryFrom<Clip> for crate::domain::Clip {
@@ -22,6 +122,21 @@ impl TryFrom<Clip> for crate::domain::Clip {
         use crate::domain::clip::field;
         use std::str::FromStr;
 
+        let attachment = match (
+            clip.attachment_name,
+            clip.attachment_mime,
+            clip.attachment_size,
+            clip.attachment_sha256,
+        ) {
+            (Some(file_name), Some(mime_type), Some(size), Some(sha256)) => Some(field::FileMeta {
+                file_name,
+                mime_type,
+                size: u64::try_from(size)?,
+                sha256,
+            }),
+            _ => None,
+        };
+
         Ok(Self {
             id: field::Id::new(DbId::from_str(clip.id.as_str())?),
             short_code: field::ShortCode::from(clip.short_code),
@@ -31,10 +146,16 @@ impl TryFrom<Clip> for crate::domain::Clip {
             expires_at: field::ExpiresAt::new(clip.expires_at.map(Time::from_naive_utc)),
             password: field::Password::new(clip.password.unwrap_or_default())?,
             views: field::Views::new(u64::try_from(clip.views)?),
+            attachment: field::Attachment::new(attachment),
         })
     }
 }
 
+pub enum RevocationStatus {
+    Revoked,
+    NotFound,
+}
+
 pub struct GetClip {
     pub(in crate::data) short_code: String,
 }
@@ -65,42 +186,266 @@ pub struct NewClip {
     pub(in crate::data) id: String,
     pub(in crate::data) short_code: String,
     pub(in crate::data) content: String,
+    pub(in crate::data) content_blob: Option<String>,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) created_at: i64,
     pub(in crate::data) expires_at: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) attachment: Option<NewAttachment>,
+    pub(in crate::data) owner_key: Option<String>,
+    pub(in crate::data) size: i64,
+    pub(in crate::data) api_key_id: Option<String>,
+    pub(in crate::data) pending_blobs: Vec<PendingBlob>,
+}
+
+pub struct NewAttachment {
+    pub(in crate::data) file_name: String,
+    pub(in crate::data) mime_type: String,
+    pub(in crate::data) size: i64,
+    pub(in crate::data) sha256: String,
+}
+
+impl NewAttachment {
+    fn from_upload(upload: crate::service::ask::Upload) -> (Self, PendingBlob) {
+        use crate::domain::clip::field::FileMeta;
+
+        let meta = FileMeta::describe(&upload.file_name, &upload.mime_type, &upload.data);
+        let attachment = Self {
+            file_name: meta.file_name,
+            mime_type: meta.mime_type,
+            size: upload.data.len() as i64,
+            sha256: meta.sha256.clone(),
+        };
+        let blob = PendingBlob {
+            key: meta.sha256,
+            data: upload.data,
+        };
+        (attachment, blob)
+    }
 }
 
 impl From<crate::service::ask::NewClip> for NewClip {
     fn from(req: crate::service::ask::NewClip) -> Self {
+        Self::with_config(req, &ClipConfig::default())
+    }
+}
+
+impl NewClip {
+    pub fn with_config(req: crate::service::ask::NewClip, config: &ClipConfig) -> Self {
+        let mut pending_blobs = vec![];
+        let size = req.content.as_str().len()
+            + req
+                .attachment
+                .as_ref()
+                .map_or(0, |upload| upload.data.len());
+
+        let (content, content_blob) =
+            offload_content(req.content.into_inner(), config.inline_content_limit);
+        let content_blob = content_blob.map(|blob| {
+            let key = blob.key.clone();
+            pending_blobs.push(blob);
+            key
+        });
+
+        let attachment = req.attachment.map(|upload| {
+            let (attachment, blob) = NewAttachment::from_upload(upload);
+            pending_blobs.push(blob);
+            attachment
+        });
+
         Self {
+            attachment,
             id: DbId::new().into(),
-            content: req.content.into_inner(),
+            content,
+            content_blob,
             title: req.title.into_inner(),
             expires_at: req.exprires_at.into_inner().map(|time| time.timestamp()),
             password: req.password.into_inner(),
-            short_code: ShortCode::default().into(),
+            short_code: ShortCode::generate(config.short_code_length, &config.short_code_chars())
+                .into(),
             created_at: Utc::now().timestamp(),
+            owner_key: req.owner.map(|key| key.hash()),
+            size: size as i64,
+            api_key_id: req.api_key,
+            pending_blobs,
         }
     }
+
+    pub fn take_pending_blobs(&mut self) -> Vec<PendingBlob> {
+        std::mem::take(&mut self.pending_blobs)
+    }
 }
 
 pub struct UpdateClip {
     pub(in crate::data) short_code: String,
     pub(in crate::data) content: String,
+    pub(in crate::data) content_blob: Option<String>,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) expires_at: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    /// bytes of the new content, the attachment is kept
+    pub(in crate::data) content_size: i64,
+    pub(in crate::data) pending_blob: Option<PendingBlob>,
 }
 
 impl From<crate::service::ask::UpdateClip> for UpdateClip {
     fn from(req: crate::service::ask::UpdateClip) -> Self {
+        Self::with_config(req, &ClipConfig::default())
+    }
+}
+
+impl UpdateClip {
+    pub fn with_config(req: crate::service::ask::UpdateClip, config: &ClipConfig) -> Self {
+        let content_size = req.content.as_str().len() as i64;
+        let (content, pending_blob) =
+            offload_content(req.content.into_inner(), config.inline_content_limit);
+
         Self {
-            content: req.content.into_inner(),
+            content,
+            content_blob: pending_blob.as_ref().map(|blob| blob.key.clone()),
             title: req.title.into_inner(),
             expires_at: req.exprires_at.into_inner().map(|time| time.timestamp()),
             password: req.password.into_inner(),
-            short_code: ShortCode::default().into(),
+            short_code: req.short_code.into_inner(),
+            content_size,
+            pending_blob,
+        }
+    }
+
+    pub fn take_pending_blobs(&mut self) -> Vec<PendingBlob> {
+        self.pending_blob.take().into_iter().collect()
+    }
+}
+
+/// what retention policies need to know about a live clip
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ClipUsage {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) created_at: NaiveDateTime,
+    pub(in crate::data) expires_at: Option<NaiveDateTime>,
+    pub(in crate::data) views: i64,
+    pub(in crate::data) size: i64,
+    pub(in crate::data) api_key_id: Option<String>,
+    /// last day with recorded views
+    pub(in crate::data) last_viewed: Option<NaiveDate>,
+}
+
+impl From<ClipUsage> for crate::domain::retention::ClipUsage {
+    fn from(usage: ClipUsage) -> Self {
+        Self {
+            short_code: ShortCode::from(usage.short_code),
+            created_at: usage.created_at,
+            expires_at: usage.expires_at,
+            views: u64::try_from(usage.views).unwrap_or_default(),
+            size: u64::try_from(usage.size).unwrap_or_default(),
+            api_key: usage.api_key_id,
+            last_viewed: usage.last_viewed,
+        }
+    }
+}
+
+/// views of one clip on one day, a row of `clip_view_events`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ViewStats {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) day: NaiveDate,
+    pub(in crate::data) web_views: i64,
+    pub(in crate::data) raw_views: i64,
+    pub(in crate::data) api_views: i64,
+    pub(in crate::data) visitors: Vec<u8>,
+    /// JSON object of views by referring host
+    pub(in crate::data) referrers: String,
+}
+
+impl ViewStats {
+    pub fn merge(self, other: ViewStats) -> Self {
+        let mut views = DailyViews::from(self);
+        views.merge(other.into());
+        views.into()
+    }
+}
+
+impl From<DailyViews> for ViewStats {
+    fn from(views: DailyViews) -> Self {
+        Self {
+            short_code: views.short_code.into_inner(),
+            day: views.day,
+            web_views: views.web as i64,
+            raw_views: views.raw as i64,
+            api_views: views.api as i64,
+            visitors: views.visitors.into_bytes(),
+            referrers: serde_json::to_string(&views.referrers).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<ViewStats> for DailyViews {
+    fn from(stats: ViewStats) -> Self {
+        Self {
+            short_code: ShortCode::from(stats.short_code),
+            day: stats.day,
+            web: u64::try_from(stats.web_views).unwrap_or_default(),
+            raw: u64::try_from(stats.raw_views).unwrap_or_default(),
+            api: u64::try_from(stats.api_views).unwrap_or_default(),
+            visitors: VisitorSketch::from_bytes(stats.visitors),
+            referrers: serde_json::from_str(&stats.referrers).unwrap_or_default(),
+        }
+    }
+}
+
+/// a row of `audit_log`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct AuditRecord {
+    pub(in crate::data) id: i64,
+    pub(in crate::data) at: NaiveDateTime,
+    pub(in crate::data) event: String,
+    pub(in crate::data) actor: String,
+    pub(in crate::data) short_code: Option<String>,
+    pub(in crate::data) outcome: String,
+    pub(in crate::data) detail: Option<String>,
+}
+
+impl From<AuditRecord> for AuditEntry {
+    fn from(record: AuditRecord) -> Self {
+        Self {
+            id: record.id,
+            at: record.at,
+            event: record.event,
+            actor: record.actor,
+            short_code: record.short_code,
+            outcome: record.outcome,
+            detail: record.detail,
+        }
+    }
+}
+
+/// an entry to append to the audit log
+#[derive(Debug, Clone)]
+pub struct NewAuditRecord {
+    pub(in crate::data) at: NaiveDateTime,
+    pub(in crate::data) event: String,
+    pub(in crate::data) actor: String,
+    pub(in crate::data) short_code: Option<String>,
+    pub(in crate::data) outcome: String,
+    pub(in crate::data) detail: Option<String>,
+}
+
+impl NewAuditRecord {
+    /// `actor` as returned by [`Actor::to_record`](crate::domain::audit::Actor::to_record)
+    pub fn new(
+        event: AuditEvent,
+        actor: String,
+        short_code: Option<&ShortCode>,
+        outcome: Outcome,
+        detail: Option<String>,
+    ) -> Self {
+        Self {
+            at: Utc::now().naive_utc(),
+            event: event.to_string(),
+            actor,
+            short_code: short_code.map(|short_code| short_code.as_str().to_owned()),
+            outcome: outcome.to_string(),
+            detail,
         }
     }
 }
@@ -0,0 +1,538 @@
+//! query layer for `Database<Postgres>`. these use the unchecked query functions because the
+//! `query!` macros are checked against the SQLite database named by `DATABASE_URL`
+use super::model;
+use crate::{
+    data::{DataError, PgPool},
+    domain::audit::AuditFilter,
+    web::api::ApiKey,
+    ShortCode,
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+type Result<T> = std::result::Result<T, DataError>;
+
+fn to_datetime(timestamp: i64) -> NaiveDateTime {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+/// adds every count in `views` to its clip with a single UPDATE
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn increase_views(views: &[(ShortCode, u32)], pool: &PgPool) -> Result<()> {
+    if views.is_empty() {
+        return Ok(());
+    }
+    let (short_codes, counts): (Vec<_>, Vec<_>) = views
+        .iter()
+        .map(|(short_code, count)| (short_code.as_str(), i64::from(*count)))
+        .unzip();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query(
+        r#"UPDATE clips SET views = clips.views + batch.views
+        FROM UNNEST($1::text[], $2::bigint[]) AS batch (short_code, views)
+        WHERE clips.short_code = batch.short_code"#,
+    )
+    .bind(short_codes)
+    .bind(counts)
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn get_clip<M: Into<model::GetClip>>(model: M, pool: &PgPool) -> Result<model::Clip> {
+    let model = model.into();
+
+    Ok(sqlx::query_as::<_, model::Clip>(
+        "SELECT * FROM clips WHERE short_code = $1 AND deleted_at IS NULL",
+    )
+    .bind(model.short_code)
+    .fetch_one(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn new_clip<M: Into<model::NewClip>>(model: M, pool: &PgPool) -> Result<model::Clip> {
+    let model = model.into();
+
+    let (attachment_name, attachment_mime, attachment_size, attachment_sha256) =
+        match model.attachment {
+            Some(attachment) => (
+                Some(attachment.file_name),
+                Some(attachment.mime_type),
+                Some(attachment.size),
+                Some(attachment.sha256),
+            ),
+            None => (None, None, None, None),
+        };
+
+    let _ = sqlx::query(
+        r#"INSERT INTO clips (
+            id, short_code, content, content_blob, title, created_at, expires_at, password, views,
+            attachment_name, attachment_mime, attachment_size, attachment_sha256, owner_key,
+            size, api_key_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 0, $9, $10, $11, $12, $13, $14, $15)"#,
+    )
+    .bind(model.id)
+    .bind(&model.short_code)
+    .bind(model.content)
+    .bind(model.content_blob)
+    .bind(model.title)
+    .bind(to_datetime(model.created_at))
+    .bind(model.expires_at.map(to_datetime))
+    .bind(model.password)
+    .bind(attachment_name)
+    .bind(attachment_mime)
+    .bind(attachment_size)
+    .bind(attachment_sha256)
+    .bind(model.owner_key)
+    .bind(model.size)
+    .bind(model.api_key_id)
+    .execute(pool)
+    .await?;
+
+    get_clip(model.short_code, pool).await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn update_clip<M: Into<model::UpdateClip>>(
+    model: M,
+    pool: &PgPool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let _ = sqlx::query(
+        r#"UPDATE clips SET
+            content = $1,
+            content_blob = $2,
+            expires_at = $3,
+            password = $4,
+            title = $5,
+            size = $7 + COALESCE(attachment_size, 0)
+        WHERE short_code = $6 AND deleted_at IS NULL"#,
+    )
+    .bind(model.content)
+    .bind(model.content_blob)
+    .bind(model.expires_at.map(to_datetime))
+    .bind(model.password)
+    .bind(model.title)
+    .bind(&model.short_code)
+    .bind(model.content_size)
+    .execute(pool)
+    .await?;
+
+    get_clip(model.short_code, pool).await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn generate_api_key(
+    api_key: ApiKey,
+    expires_at: Option<NaiveDateTime>,
+    pool: &PgPool,
+) -> Result<ApiKey> {
+    let bytes = api_key.clone().into_inner();
+    sqlx::query("INSERT INTO api_keys (api_key, expires_at) VALUES ($1, $2)")
+        .bind(bytes)
+        .bind(expires_at)
+        .execute(pool)
+        .await
+        .map(|_| ())?;
+    Ok(api_key)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn revoke_api_key(api_key: ApiKey, pool: &PgPool) -> Result<model::RevocationStatus> {
+    let bytes = api_key.clone().into_inner();
+    Ok(sqlx::query("DELETE FROM api_keys WHERE api_key = $1")
+        .bind(bytes)
+        .execute(pool)
+        .await
+        .map(|result| match result.rows_affected() {
+            0 => model::RevocationStatus::NotFound,
+            _ => model::RevocationStatus::Revoked,
+        })?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn api_key_is_valid(api_key: ApiKey, pool: &PgPool) -> Result<bool> {
+    let bytes = api_key.clone().into_inner();
+    let count: i64 = sqlx::query_scalar(
+        r#"SELECT COUNT(api_key) FROM api_keys
+        WHERE api_key = $1 AND (expires_at IS NULL OR expires_at > (now() AT TIME ZONE 'UTC'))"#,
+    )
+    .bind(bytes)
+    .fetch_one(pool)
+    .await?;
+    Ok(count > 0)
+}
+
+/// deletes the keys that have expired and returns them
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn delete_expired_api_keys(pool: &PgPool) -> Result<Vec<ApiKey>> {
+    let keys: Vec<Vec<u8>> = sqlx::query_scalar(
+        r#"DELETE FROM api_keys WHERE expires_at <= (now() AT TIME ZONE 'UTC')
+        RETURNING api_key"#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(keys.into_iter().map(ApiKey::from).collect())
+}
+
+/// takes the lock `name` for `owner` for `lease`, or extends it when `owner` already holds it.
+/// false while another owner holds a lease that has not lapsed. the database clock decides, so
+/// the clocks of the instances do not need to agree
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn acquire_lock(
+    name: &str,
+    owner: &str,
+    lease: std::time::Duration,
+    pool: &PgPool,
+) -> Result<bool> {
+    let result = sqlx::query(
+        r#"INSERT INTO locks (name, owner, expires_at)
+        VALUES ($1, $2, (now() AT TIME ZONE 'UTC') + make_interval(secs => $3))
+        ON CONFLICT (name) DO UPDATE SET owner = excluded.owner, expires_at = excluded.expires_at
+        WHERE locks.owner = excluded.owner OR locks.expires_at <= (now() AT TIME ZONE 'UTC')"#,
+    )
+    .bind(name)
+    .bind(owner)
+    .bind(lease.as_secs_f64())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn release_lock(name: &str, owner: &str, pool: &PgPool) -> Result<()> {
+    sqlx::query("DELETE FROM locks WHERE name = $1 AND owner = $2")
+        .bind(name)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// refreshes the statistics of the query planner. vacuuming is left to autovacuum
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn optimize(pool: &PgPool) -> Result<()> {
+    sqlx::query("ANALYZE").execute(pool).await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn trash_clip(short_code: &ShortCode, pool: &PgPool) -> Result<()> {
+    let result = sqlx::query(
+        r#"UPDATE clips SET deleted_at = (now() AT TIME ZONE 'UTC')
+        WHERE short_code = $1 AND deleted_at IS NULL"#,
+    )
+    .bind(short_code.as_str())
+    .execute(pool)
+    .await?;
+    match result.rows_affected() {
+        0 => Err(DataError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn trash_expired(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query(
+        r#"UPDATE clips SET deleted_at = (now() AT TIME ZONE 'UTC')
+        WHERE deleted_at IS NULL AND expires_at < (now() AT TIME ZONE 'UTC')"#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn get_trashed_clip(short_code: &ShortCode, pool: &PgPool) -> Result<model::Clip> {
+    Ok(sqlx::query_as::<_, model::Clip>(
+        "SELECT * FROM clips WHERE short_code = $1 AND deleted_at IS NOT NULL",
+    )
+    .bind(short_code.as_str())
+    .fetch_one(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn restore_clip(
+    short_code: &ShortCode,
+    expires_at: Option<NaiveDateTime>,
+    pool: &PgPool,
+) -> Result<model::Clip> {
+    Ok(sqlx::query_as::<_, model::Clip>(
+        r#"UPDATE clips SET
+            deleted_at = NULL,
+            expires_at = COALESCE($2, expires_at)
+        WHERE short_code = $1 AND deleted_at IS NOT NULL
+        RETURNING *"#,
+    )
+    .bind(short_code.as_str())
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn purge_trash(before: NaiveDateTime, pool: &PgPool) -> Result<Vec<model::BlobRefs>> {
+    Ok(sqlx::query_as::<_, model::BlobRefs>(
+        r#"DELETE FROM clips WHERE deleted_at < $1
+        RETURNING attachment_sha256, content_blob"#,
+    )
+    .bind(before)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// every live clip with the last day it was viewed on
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn clip_usage(pool: &PgPool) -> Result<Vec<model::ClipUsage>> {
+    Ok(sqlx::query_as::<_, model::ClipUsage>(
+        r#"SELECT clips.short_code, clips.created_at, clips.expires_at, clips.views, clips.size,
+            clips.api_key_id, MAX(clip_view_events.day) AS last_viewed
+        FROM clips LEFT JOIN clip_view_events ON clip_view_events.clip_id = clips.id
+        WHERE clips.deleted_at IS NULL
+        GROUP BY clips.id"#,
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn blob_is_referenced(key: &str, pool: &PgPool) -> Result<bool> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM clips WHERE attachment_sha256 = $1 OR content_blob = $1",
+    )
+    .bind(key)
+    .fetch_one(pool)
+    .await?;
+    Ok(count > 0)
+}
+
+/// merges each day of views into the stored row, all in one transaction. the row is created
+/// empty first and then locked, so concurrent writers do not overwrite each other
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn record_view_stats(stats: Vec<model::ViewStats>, pool: &PgPool) -> Result<()> {
+    use crate::domain::stats::DailyViews;
+
+    let mut transaction = pool.begin().await?;
+
+    for stats in stats {
+        let empty = model::ViewStats::from(DailyViews::new(
+            ShortCode::from(stats.short_code.as_str()),
+            stats.day,
+        ));
+        sqlx::query(
+            r#"INSERT INTO clip_view_events
+                (clip_id, day, web_views, raw_views, api_views, visitors, referrers)
+            SELECT id, $1, 0, 0, 0, $2, $3 FROM clips WHERE short_code = $4
+            ON CONFLICT DO NOTHING"#,
+        )
+        .bind(stats.day)
+        .bind(empty.visitors)
+        .bind(empty.referrers)
+        .bind(&stats.short_code)
+        .execute(&mut *transaction)
+        .await?;
+
+        let stored = sqlx::query_as::<_, model::ViewStats>(
+            r#"SELECT clips.short_code, day, web_views, raw_views, api_views, visitors, referrers
+            FROM clip_view_events JOIN clips ON clips.id = clip_view_events.clip_id
+            WHERE clips.short_code = $1 AND day = $2
+            FOR UPDATE OF clip_view_events"#,
+        )
+        .bind(&stats.short_code)
+        .bind(stats.day)
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(stored) = stored else {
+            continue;
+        };
+        let stats = stored.merge(stats);
+
+        sqlx::query(
+            r#"UPDATE clip_view_events SET
+                web_views = $1, raw_views = $2, api_views = $3, visitors = $4, referrers = $5
+            FROM clips
+            WHERE clips.id = clip_view_events.clip_id AND clips.short_code = $6 AND day = $7"#,
+        )
+        .bind(stats.web_views)
+        .bind(stats.raw_views)
+        .bind(stats.api_views)
+        .bind(stats.visitors)
+        .bind(stats.referrers)
+        .bind(stats.short_code)
+        .bind(stats.day)
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn view_stats(
+    short_code: &ShortCode,
+    since: NaiveDate,
+    pool: &PgPool,
+) -> Result<Vec<model::ViewStats>> {
+    Ok(sqlx::query_as::<_, model::ViewStats>(
+        r#"SELECT clips.short_code, day, web_views, raw_views, api_views, visitors, referrers
+        FROM clip_view_events JOIN clips ON clips.id = clip_view_events.clip_id
+        WHERE clips.short_code = $1 AND day >= $2
+        ORDER BY day"#,
+    )
+    .bind(short_code.as_str())
+    .bind(since)
+    .fetch_all(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn view_salt(day: NaiveDate, pool: &PgPool) -> Result<Vec<u8>> {
+    let salt = rand::random::<[u8; 32]>().to_vec();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query("DELETE FROM view_salts WHERE day < $1")
+        .bind(day)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query("INSERT INTO view_salts (day, salt) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(day)
+        .bind(salt)
+        .execute(&mut *transaction)
+        .await?;
+    let salt = sqlx::query_scalar("SELECT salt FROM view_salts WHERE day = $1")
+        .bind(day)
+        .fetch_one(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+    Ok(salt)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn append_audit(record: model::NewAuditRecord, pool: &PgPool) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO audit_log (at, event, actor, short_code, outcome, detail)
+        VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(record.at)
+    .bind(record.event)
+    .bind(record.actor)
+    .bind(record.short_code)
+    .bind(record.outcome)
+    .bind(record.detail)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn audit_log(filter: &AuditFilter, pool: &PgPool) -> Result<Vec<model::AuditRecord>> {
+    let mut query = sqlx::QueryBuilder::new("SELECT * FROM audit_log WHERE 1 = 1");
+    if let Some(event) = filter.event {
+        query.push(" AND event = ").push_bind(event.to_string());
+    }
+    if let Some(actor) = &filter.actor {
+        query.push(" AND actor = ").push_bind(actor);
+    }
+    if let Some(short_code) = &filter.short_code {
+        query.push(" AND short_code = ").push_bind(short_code);
+    }
+    if let Some(outcome) = filter.outcome {
+        query.push(" AND outcome = ").push_bind(outcome.to_string());
+    }
+    if let Some(since) = filter.since {
+        query.push(" AND at >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        query.push(" AND at < ").push_bind(until);
+    }
+    if let Some(after) = filter.after {
+        query.push(" AND id > ").push_bind(after);
+    }
+    query
+        .push(" ORDER BY id LIMIT ")
+        .push_bind(i64::from(filter.limit()));
+
+    Ok(query
+        .build_query_as::<model::AuditRecord>()
+        .fetch_all(pool)
+        .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn audit_salt(pool: &PgPool) -> Result<Vec<u8>> {
+    let salt = rand::random::<[u8; 32]>().to_vec();
+    sqlx::query("INSERT INTO audit_salt (id, salt) VALUES (1, $1) ON CONFLICT DO NOTHING")
+        .bind(salt)
+        .execute(pool)
+        .await?;
+    Ok(
+        sqlx::query_scalar("SELECT salt FROM audit_salt WHERE id = 1")
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn content_bytes(pool: &PgPool) -> Result<u64> {
+    let bytes: Option<i64> =
+        sqlx::query_scalar("SELECT SUM(OCTET_LENGTH(content))::BIGINT FROM clips")
+            .fetch_one(pool)
+            .await?;
+    Ok(bytes.unwrap_or_default() as u64)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn blob_bytes(pool: &PgPool) -> Result<u64> {
+    let bytes: Option<i64> =
+        sqlx::query_scalar("SELECT SUM(OCTET_LENGTH(data))::BIGINT FROM blobs")
+            .fetch_one(pool)
+            .await?;
+    Ok(bytes.unwrap_or_default() as u64)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn blob_keys(pool: &PgPool) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar("SELECT sha256 FROM blobs")
+        .fetch_all(pool)
+        .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn put_blob(key: &str, data: Vec<u8>, pool: &PgPool) -> Result<()> {
+    sqlx::query("INSERT INTO blobs (sha256, data) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(key)
+        .bind(data)
+        .execute(pool)
+        .await
+        .map(|_| ())?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn get_blob(key: &str, pool: &PgPool) -> Result<Vec<u8>> {
+    Ok(
+        sqlx::query_scalar("SELECT data FROM blobs WHERE sha256 = $1")
+            .bind(key)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn delete_blob(key: &str, pool: &PgPool) -> Result<()> {
+    sqlx::query("DELETE FROM blobs WHERE sha256 = $1")
+        .bind(key)
+        .execute(pool)
+        .await
+        .map(|_| ())?;
+    Ok(())
+}
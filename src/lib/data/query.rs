@@ -1,24 +1,38 @@
 use super::model;
 use crate::{
     data::{DataError, DatabasePool},
+    domain::audit::AuditFilter,
     web::api::ApiKey,
     ShortCode,
 };
+use chrono::{NaiveDate, NaiveDateTime, Utc};
 
 type Result<T> = std::result::Result<T, DataError>;
 
-pub async fn increase_views(short_code: &ShortCode, views: u32, pool: &DatabasePool) -> Result<()> {
-    let short_code = short_code.as_str();
-    Ok(sqlx::query!(
-        "UPDATE clips SET views = views + ? WHERE short_code = ?",
-        views,
-        short_code
-    )
-    .execute(pool)
-    .await
-    .map(|_| ())?)
+/// adds every count in `views` to its clip with a single UPDATE
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn increase_views(views: &[(ShortCode, u32)], pool: &DatabasePool) -> Result<()> {
+    if views.is_empty() {
+        return Ok(());
+    }
+    let mut transaction = pool.begin().await?;
+
+    let mut query = sqlx::QueryBuilder::new("WITH batch (short_code, views) AS (");
+    query.push_values(views, |mut row, (short_code, count)| {
+        row.push_bind(short_code.as_str()).push_bind(*count);
+    });
+    query.push(
+        r#") UPDATE clips
+        SET views = views + (SELECT batch.views FROM batch WHERE batch.short_code = clips.short_code)
+        WHERE short_code IN (SELECT short_code FROM batch)"#,
+    );
+    query.build().execute(&mut *transaction).await?;
+
+    transaction.commit().await?;
+    Ok(())
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub async fn get_clip<M: Into<model::GetClip>>(
     model: M,
     pool: &DatabasePool,
@@ -28,31 +42,53 @@ pub async fn get_clip<M: Into<model::GetClip>>(
 
     Ok(sqlx::query_as!(
         model::Clip,
-        "SELECT * FROM clips WHERE short_code = ?",
+        "SELECT * FROM clips WHERE short_code = ? AND deleted_at IS NULL",
         short_code
     )
     .fetch_one(pool)
     .await?)
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub async fn new_clip<M: Into<model::NewClip>>(
     model: M,
     pool: &DatabasePool,
 ) -> Result<model::Clip> {
     let model = model.into();
 
+    let (attachment_name, attachment_mime, attachment_size, attachment_sha256) =
+        match model.attachment {
+            Some(attachment) => (
+                Some(attachment.file_name),
+                Some(attachment.mime_type),
+                Some(attachment.size),
+                Some(attachment.sha256),
+            ),
+            None => (None, None, None, None),
+        };
+
     let _ = sqlx::query!(
         r#"INSERT INTO clips (
-            id, short_code, content, title, created_at, expires_at, password, views
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+            id, short_code, content, content_blob, title, created_at, expires_at, password, views,
+            attachment_name, attachment_mime, attachment_size, attachment_sha256, owner_key,
+            size, api_key_id
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
         model.id,
         model.short_code,
         model.content,
+        model.content_blob,
         model.title,
         model.created_at,
         model.expires_at,
         model.password,
-        0
+        0,
+        attachment_name,
+        attachment_mime,
+        attachment_size,
+        attachment_sha256,
+        model.owner_key,
+        model.size,
+        model.api_key_id
     )
     .execute(pool)
     .await?;
@@ -60,6 +96,7 @@ pub async fn new_clip<M: Into<model::NewClip>>(
     get_clip(model.short_code, pool).await
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub async fn update_clip<M: Into<model::UpdateClip>>(
     model: M,
     pool: &DatabasePool,
@@ -68,14 +105,18 @@ pub async fn update_clip<M: Into<model::UpdateClip>>(
     let _ = sqlx::query!(
         r#"UPDATE clips SET
             content = ?,
+            content_blob = ?,
             expires_at = ?,
             password = ?,
-            title = ?
-        WHERE short_code = ?"#,
+            title = ?,
+            size = ? + COALESCE(attachment_size, 0)
+        WHERE short_code = ? AND deleted_at IS NULL"#,
         model.content,
+        model.content_blob,
         model.expires_at,
         model.password,
         model.title,
+        model.content_size,
         model.short_code,
     )
     .execute(pool)
@@ -84,57 +125,457 @@ pub async fn update_clip<M: Into<model::UpdateClip>>(
     get_clip(model.short_code, pool).await
 }
 
-pub async fn generate_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<ApiKey> {
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn generate_api_key(
+    api_key: ApiKey,
+    expires_at: Option<NaiveDateTime>,
+    pool: &DatabasePool,
+) -> Result<ApiKey> {
     let bytes = api_key.clone().into_inner();
-    sqlx::query!("INSERT INTO api_keys (api_key) VALUES (?)", bytes)
-        .execute(pool)
-        .await
-        .map(|_| ())?;
+    let expires_at = expires_at.map(|time| time.and_utc().timestamp());
+    sqlx::query!(
+        "INSERT INTO api_keys (api_key, expires_at) VALUES (?, ?)",
+        bytes,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
     Ok(api_key)
 }
 
-pub enum RevocationStatus {
-    Revoked,
-    NotFound,
-}
-
-pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<RevocationStatus> {
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn revoke_api_key(
+    api_key: ApiKey,
+    pool: &DatabasePool,
+) -> Result<model::RevocationStatus> {
     let bytes = api_key.clone().into_inner();
     Ok(
         sqlx::query!("DELETE FROM api_keys WHERE api_key == ?", bytes)
             .execute(pool)
             .await
             .map(|result| match result.rows_affected() {
-                0 => RevocationStatus::NotFound,
-                _ => RevocationStatus::Revoked,
+                0 => model::RevocationStatus::NotFound,
+                _ => model::RevocationStatus::Revoked,
             })?,
     )
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool> {
     use sqlx::Row;
     let bytes = api_key.clone().into_inner();
+    Ok(sqlx::query(
+        "SELECT COUNT(api_key) FROM api_keys
+            WHERE api_key = ? AND (expires_at IS NULL OR expires_at > ?)",
+    )
+    .bind(bytes)
+    .bind(Utc::now().timestamp())
+    .fetch_one(pool)
+    .await
+    .map(|row| {
+        let count: u32 = row.get(0);
+        count > 0
+    })?)
+}
+
+/// deletes the keys that have expired and returns them
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn delete_expired_api_keys(pool: &DatabasePool) -> Result<Vec<ApiKey>> {
+    let now = Utc::now().timestamp();
+    let keys = sqlx::query_scalar!(
+        "DELETE FROM api_keys WHERE expires_at <= ? RETURNING api_key",
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(keys.into_iter().flatten().map(ApiKey::from).collect())
+}
+
+/// takes the lock `name` for `owner` for `lease`, or extends it when `owner` already holds it.
+/// false while another owner holds a lease that has not lapsed
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn acquire_lock(
+    name: &str,
+    owner: &str,
+    lease: std::time::Duration,
+    pool: &DatabasePool,
+) -> Result<bool> {
+    // leases last seconds, so they are kept in milliseconds rather than whole seconds
+    let now = Utc::now().timestamp_millis();
+    let expires_at = now.saturating_add(lease.as_millis().try_into().unwrap_or(i64::MAX));
+    let result = sqlx::query!(
+        "INSERT INTO locks (name, owner, expires_at) VALUES (?, ?, ?)
+        ON CONFLICT (name) DO UPDATE SET owner = excluded.owner, expires_at = excluded.expires_at
+        WHERE locks.owner = excluded.owner OR locks.expires_at <= ?",
+        name,
+        owner,
+        expires_at,
+        now
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn release_lock(name: &str, owner: &str, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!(
+        "DELETE FROM locks WHERE name = ? AND owner = ?",
+        name,
+        owner
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// rebuilds the database file to reclaim the space of deleted rows and refreshes the
+/// statistics of the query planner
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn optimize(pool: &DatabasePool) -> Result<()> {
+    sqlx::query("VACUUM").execute(pool).await?;
+    sqlx::query("ANALYZE").execute(pool).await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn trash_clip(short_code: &ShortCode, pool: &DatabasePool) -> Result<()> {
+    let short_code = short_code.as_str();
+    let now = Utc::now().timestamp();
+    let result = sqlx::query!(
+        "UPDATE clips SET deleted_at = ? WHERE short_code = ? AND deleted_at IS NULL",
+        now,
+        short_code
+    )
+    .execute(pool)
+    .await?;
+    match result.rows_affected() {
+        0 => Err(DataError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn trash_expired(pool: &DatabasePool) -> Result<u64> {
+    let now = Utc::now().timestamp();
+    let result = sqlx::query!(
+        "UPDATE clips SET deleted_at = ? WHERE deleted_at IS NULL AND expires_at < ?",
+        now,
+        now
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn get_trashed_clip(short_code: &ShortCode, pool: &DatabasePool) -> Result<model::Clip> {
+    let short_code = short_code.as_str();
+    Ok(sqlx::query_as!(
+        model::Clip,
+        "SELECT * FROM clips WHERE short_code = ? AND deleted_at IS NOT NULL",
+        short_code
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn restore_clip(
+    short_code: &ShortCode,
+    expires_at: Option<NaiveDateTime>,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let code = short_code.as_str();
+    let expires_at = expires_at.map(|time| time.and_utc().timestamp());
+    let result = sqlx::query!(
+        r#"UPDATE clips SET
+            deleted_at = NULL,
+            expires_at = COALESCE(?, expires_at)
+        WHERE short_code = ? AND deleted_at IS NOT NULL"#,
+        expires_at,
+        code
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(DataError::NotFound);
+    }
+    get_clip(short_code.clone(), pool).await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn purge_trash(
+    before: NaiveDateTime,
+    pool: &DatabasePool,
+) -> Result<Vec<model::BlobRefs>> {
+    let before = before.and_utc().timestamp();
+    let mut transaction = pool.begin().await?;
+
+    let refs = sqlx::query_as!(
+        model::BlobRefs,
+        "SELECT attachment_sha256, content_blob FROM clips WHERE deleted_at < ?",
+        before
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    sqlx::query!("DELETE FROM clips WHERE deleted_at < ?", before)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+    Ok(refs)
+}
+
+/// every live clip with the last day it was viewed on
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn clip_usage(pool: &DatabasePool) -> Result<Vec<model::ClipUsage>> {
+    Ok(sqlx::query_as!(
+        model::ClipUsage,
+        r#"SELECT clips.short_code AS "short_code!", clips.created_at AS "created_at!",
+            clips.expires_at, clips.views AS "views!", clips.size AS "size!", clips.api_key_id,
+            MAX(clip_view_events.day) AS "last_viewed?: NaiveDate"
+        FROM clips LEFT JOIN clip_view_events ON clip_view_events.clip_id = clips.id
+        WHERE clips.deleted_at IS NULL
+        GROUP BY clips.id"#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn blob_is_referenced(key: &str, pool: &DatabasePool) -> Result<bool> {
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM clips WHERE attachment_sha256 = ? OR content_blob = ?",
+        key,
+        key
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(count > 0)
+}
+
+/// merges each day of views into the stored row, all in one transaction
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn record_view_stats(stats: Vec<model::ViewStats>, pool: &DatabasePool) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+
+    for stats in stats {
+        let stored = sqlx::query_as!(
+            model::ViewStats,
+            r#"SELECT clips.short_code, day AS "day: NaiveDate", web_views, raw_views, api_views,
+                visitors, referrers
+            FROM clip_view_events JOIN clips ON clips.id = clip_view_events.clip_id
+            WHERE clips.short_code = ? AND day = ?"#,
+            stats.short_code,
+            stats.day
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let stats = match stored {
+            Some(stored) => stored.merge(stats),
+            None => stats,
+        };
+
+        sqlx::query!(
+            r#"INSERT INTO clip_view_events
+                (clip_id, day, web_views, raw_views, api_views, visitors, referrers)
+            SELECT id, ?, ?, ?, ?, ?, ? FROM clips WHERE short_code = ?
+            ON CONFLICT (clip_id, day) DO UPDATE SET
+                web_views = excluded.web_views,
+                raw_views = excluded.raw_views,
+                api_views = excluded.api_views,
+                visitors = excluded.visitors,
+                referrers = excluded.referrers"#,
+            stats.day,
+            stats.web_views,
+            stats.raw_views,
+            stats.api_views,
+            stats.visitors,
+            stats.referrers,
+            stats.short_code
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn view_stats(
+    short_code: &ShortCode,
+    since: NaiveDate,
+    pool: &DatabasePool,
+) -> Result<Vec<model::ViewStats>> {
+    let short_code = short_code.as_str();
+    Ok(sqlx::query_as!(
+        model::ViewStats,
+        r#"SELECT clips.short_code, day AS "day: NaiveDate", web_views, raw_views, api_views,
+            visitors, referrers
+        FROM clip_view_events JOIN clips ON clips.id = clip_view_events.clip_id
+        WHERE clips.short_code = ? AND day >= ?
+        ORDER BY day"#,
+        short_code,
+        since
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn view_salt(day: NaiveDate, pool: &DatabasePool) -> Result<Vec<u8>> {
+    let salt = rand::random::<[u8; 32]>().to_vec();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!("DELETE FROM view_salts WHERE day < ?", day)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        "INSERT OR IGNORE INTO view_salts (day, salt) VALUES (?, ?)",
+        day,
+        salt
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let salt = sqlx::query_scalar!("SELECT salt FROM view_salts WHERE day = ?", day)
+        .fetch_one(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+    Ok(salt)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn append_audit(record: model::NewAuditRecord, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!(
+        "INSERT INTO audit_log (at, event, actor, short_code, outcome, detail)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        record.at,
+        record.event,
+        record.actor,
+        record.short_code,
+        record.outcome,
+        record.detail
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn audit_log(
+    filter: &AuditFilter,
+    pool: &DatabasePool,
+) -> Result<Vec<model::AuditRecord>> {
+    let mut query = sqlx::QueryBuilder::new("SELECT * FROM audit_log WHERE 1 = 1");
+    if let Some(event) = filter.event {
+        query.push(" AND event = ").push_bind(event.to_string());
+    }
+    if let Some(actor) = &filter.actor {
+        query.push(" AND actor = ").push_bind(actor);
+    }
+    if let Some(short_code) = &filter.short_code {
+        query.push(" AND short_code = ").push_bind(short_code);
+    }
+    if let Some(outcome) = filter.outcome {
+        query.push(" AND outcome = ").push_bind(outcome.to_string());
+    }
+    if let Some(since) = filter.since {
+        query.push(" AND at >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        query.push(" AND at < ").push_bind(until);
+    }
+    if let Some(after) = filter.after {
+        query.push(" AND id > ").push_bind(after);
+    }
+    query
+        .push(" ORDER BY id LIMIT ")
+        .push_bind(i64::from(filter.limit()));
+
+    Ok(query
+        .build_query_as::<model::AuditRecord>()
+        .fetch_all(pool)
+        .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn audit_salt(pool: &DatabasePool) -> Result<Vec<u8>> {
+    let salt = rand::random::<[u8; 32]>().to_vec();
+    sqlx::query!(
+        "INSERT OR IGNORE INTO audit_salt (id, salt) VALUES (1, ?)",
+        salt
+    )
+    .execute(pool)
+    .await?;
     Ok(
-        sqlx::query("SELECT COUNT(api_key) FROM api_keys WHERE api_key = ?")
-            .bind(bytes)
+        sqlx::query_scalar!("SELECT salt FROM audit_salt WHERE id = 1")
             .fetch_one(pool)
-            .await
-            .map(|row| {
-                let count: u32 = row.get(0);
-                count > 0
-            })?,
+            .await?,
     )
 }
 
-pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn content_bytes(pool: &DatabasePool) -> Result<u64> {
+    // LENGTH counts the characters of text
+    let bytes = sqlx::query_scalar!(
+        r#"SELECT SUM(LENGTH(CAST(content AS BLOB))) AS "bytes: i64" FROM clips"#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(bytes.unwrap_or_default() as u64)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn blob_bytes(pool: &DatabasePool) -> Result<u64> {
+    let bytes = sqlx::query_scalar!(r#"SELECT SUM(LENGTH(data)) AS "bytes: i64" FROM blobs"#)
+        .fetch_one(pool)
+        .await?;
+    Ok(bytes.unwrap_or_default() as u64)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn blob_keys(pool: &DatabasePool) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar!("SELECT sha256 FROM blobs")
+        .fetch_all(pool)
+        .await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn put_blob(key: &str, data: Vec<u8>, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!(
+        "INSERT OR IGNORE INTO blobs (sha256, data) VALUES (?, ?)",
+        key,
+        data
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn get_blob(key: &str, pool: &DatabasePool) -> Result<Vec<u8>> {
     Ok(
-        sqlx::query!(r#"DELETE FROM clips WHERE strftime('%s', 'now') > expires_at"#)
-            .execute(pool)
-            .await?
-            .rows_affected(),
+        sqlx::query_scalar!("SELECT data FROM blobs WHERE sha256 = ?", key)
+            .fetch_one(pool)
+            .await?,
     )
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn delete_blob(key: &str, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!("DELETE FROM blobs WHERE sha256 = ?", key)
+        .execute(pool)
+        .await
+        .map(|_| ())?;
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::data::test::*;
@@ -148,16 +589,20 @@ pub mod test {
     }
 
     fn model_new_clip(short_code: &str) -> model::NewClip {
-        use chrono::Utc;
-        model::NewClip {
-            id: DbId::new().into(),
-            content: format!("content for clip '{}'", short_code),
-            title: None,
-            short_code: short_code.into(),
-            created_at: Utc::now().timestamp(),
-            expires_at: None,
-            password: None,
-        }
+        use crate::domain::clip::field::Content;
+        use crate::service::ask;
+
+        let mut model = model::NewClip::from(ask::NewClip {
+            content: Content::new(&format!("content for clip '{}'", short_code)).unwrap(),
+            title: Default::default(),
+            exprires_at: Default::default(),
+            password: Default::default(),
+            attachment: None,
+            owner: None,
+            api_key: None,
+        });
+        model.short_code = short_code.into();
+        model
     }
 
     #[test]
@@ -176,4 +621,56 @@ pub mod test {
         assert!(clip.short_code == "1");
         assert!(clip.content == "content for clip '1'");
     }
+
+    #[test]
+    fn purges_trashed_clip_and_returns_blob_refs() {
+        use crate::domain::clip::field::Content;
+        use crate::service::ask;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        let mut model = model::NewClip::from(ask::NewClip {
+            content: Content::new(&"x".repeat(blob::INLINE_CONTENT_LIMIT + 1)).unwrap(),
+            title: Default::default(),
+            exprires_at: Default::default(),
+            password: Default::default(),
+            attachment: Some(ask::Upload {
+                file_name: "data.bin".to_owned(),
+                mime_type: "application/octet-stream".to_owned(),
+                data: vec![0, 1, 2, 3],
+            }),
+            owner: None,
+            api_key: None,
+        });
+        model.short_code = "2".into();
+        let pending = model.take_pending_blobs();
+        assert_eq!(pending.len(), 2);
+
+        let clip = rt.block_on(async move { super::new_clip(model, pool).await.unwrap() });
+        assert!(clip.content.is_empty());
+        assert_eq!(clip.attachment_name.as_deref(), Some("data.bin"));
+        assert_eq!(clip.attachment_size, Some(4));
+
+        let keys = rt.block_on(async move {
+            let key = pending[0].key.clone();
+            assert!(super::blob_is_referenced(&key, pool).await.unwrap());
+
+            super::trash_clip(&"2".into(), pool).await.unwrap();
+            assert!(super::get_clip(model_get_clip("2"), pool).await.is_err());
+            // the trash keeps its blobs for a restore
+            assert!(super::blob_is_referenced(&key, pool).await.unwrap());
+
+            let later = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(1);
+            let refs = super::purge_trash(later, pool).await.unwrap();
+            assert_eq!(refs.len(), 1);
+            assert!(!super::blob_is_referenced(&key, pool).await.unwrap());
+            assert!(super::get_trashed_clip(&"2".into(), pool).await.is_err());
+            refs.into_iter()
+                .flat_map(model::BlobRefs::into_keys)
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(keys.len(), 2);
+    }
 }
@@ -0,0 +1,396 @@
+use super::{
+    ApiKeyRepository, AuditRepository, ClipRepository, LockRepository, Repository, Result,
+    StatsRepository,
+};
+use crate::data::{model, model::RevocationStatus, DataError};
+use crate::domain::audit::AuditFilter;
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// repository that keeps clips and API keys in process memory. nothing survives a restart
+#[derive(Default)]
+pub struct MemoryRepository {
+    clips: RwLock<HashMap<String, model::Clip>>,
+    /// keys with the time they expire at
+    api_keys: RwLock<HashMap<Vec<u8>, Option<NaiveDateTime>>>,
+    view_stats: RwLock<HashMap<(String, NaiveDate), model::ViewStats>>,
+    view_salts: Mutex<HashMap<NaiveDate, Vec<u8>>>,
+    audit_log: RwLock<Vec<model::AuditRecord>>,
+    audit_salt: Mutex<Option<Vec<u8>>>,
+    /// owner of each lock and when its lease lapses
+    locks: Mutex<HashMap<String, (String, DateTime<Utc>)>>,
+}
+
+impl MemoryRepository {
+    fn forget_view_stats(&self, short_code: &str) {
+        self.view_stats
+            .write()
+            .retain(|(stored, _), _| stored != short_code);
+    }
+}
+
+fn to_datetime(timestamp: i64) -> NaiveDateTime {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+#[rocket::async_trait]
+impl ClipRepository for MemoryRepository {
+    async fn get_clip(&self, short_code: &ShortCode) -> Result<model::Clip> {
+        self.clips
+            .read()
+            .get(short_code.as_str())
+            .filter(|clip| clip.deleted_at.is_none())
+            .cloned()
+            .ok_or(DataError::NotFound)
+    }
+
+    async fn new_clip(&self, clip: model::NewClip) -> Result<model::Clip> {
+        let (attachment_name, attachment_mime, attachment_size, attachment_sha256) =
+            match clip.attachment {
+                Some(attachment) => (
+                    Some(attachment.file_name),
+                    Some(attachment.mime_type),
+                    Some(attachment.size),
+                    Some(attachment.sha256),
+                ),
+                None => (None, None, None, None),
+            };
+
+        let stored = model::Clip {
+            id: clip.id,
+            short_code: clip.short_code.clone(),
+            content: clip.content,
+            title: clip.title,
+            created_at: to_datetime(clip.created_at),
+            expires_at: clip.expires_at.map(to_datetime),
+            password: clip.password,
+            views: 0,
+            attachment_name,
+            attachment_mime,
+            attachment_size,
+            attachment_sha256,
+            content_blob: clip.content_blob,
+            owner_key: clip.owner_key,
+            deleted_at: None,
+            size: clip.size,
+            api_key_id: clip.api_key_id,
+        };
+
+        self.clips.write().insert(clip.short_code, stored.clone());
+        Ok(stored)
+    }
+
+    async fn update_clip(&self, clip: model::UpdateClip) -> Result<model::Clip> {
+        let mut clips = self.clips.write();
+        let stored = clips
+            .get_mut(&clip.short_code)
+            .filter(|stored| stored.deleted_at.is_none())
+            .ok_or(DataError::NotFound)?;
+
+        stored.content = clip.content;
+        stored.content_blob = clip.content_blob;
+        stored.title = clip.title;
+        stored.expires_at = clip.expires_at.map(to_datetime);
+        stored.password = clip.password;
+        stored.size = clip.content_size + stored.attachment_size.unwrap_or_default();
+        Ok(stored.clone())
+    }
+
+    async fn trash_clip(&self, short_code: &ShortCode) -> Result<()> {
+        let mut clips = self.clips.write();
+        let clip = clips
+            .get_mut(short_code.as_str())
+            .filter(|clip| clip.deleted_at.is_none())
+            .ok_or(DataError::NotFound)?;
+        clip.deleted_at = Some(Utc::now().naive_utc());
+        Ok(())
+    }
+
+    async fn trash_expired(&self) -> Result<u64> {
+        let now = Utc::now().naive_utc();
+        let mut trashed = 0;
+        for clip in self.clips.write().values_mut() {
+            match (clip.expires_at, clip.deleted_at) {
+                (Some(expires_at), None) if expires_at < now => {
+                    clip.deleted_at = Some(now);
+                    trashed += 1;
+                }
+                _ => {}
+            }
+        }
+        Ok(trashed)
+    }
+
+    async fn get_trashed_clip(&self, short_code: &ShortCode) -> Result<model::Clip> {
+        self.clips
+            .read()
+            .get(short_code.as_str())
+            .filter(|clip| clip.deleted_at.is_some())
+            .cloned()
+            .ok_or(DataError::NotFound)
+    }
+
+    async fn restore_clip(
+        &self,
+        short_code: &ShortCode,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<model::Clip> {
+        let mut clips = self.clips.write();
+        let clip = clips
+            .get_mut(short_code.as_str())
+            .filter(|clip| clip.deleted_at.is_some())
+            .ok_or(DataError::NotFound)?;
+        clip.deleted_at = None;
+        if expires_at.is_some() {
+            clip.expires_at = expires_at;
+        }
+        Ok(clip.clone())
+    }
+
+    async fn purge_trash(&self, before: NaiveDateTime) -> Result<Vec<model::BlobRefs>> {
+        let mut purged = vec![];
+        let mut short_codes = vec![];
+        self.clips.write().retain(|short_code, clip| {
+            if clip.trashed_before(before) {
+                purged.push(clip.blob_refs());
+                short_codes.push(short_code.clone());
+                false
+            } else {
+                true
+            }
+        });
+        for short_code in short_codes {
+            self.forget_view_stats(&short_code);
+        }
+        Ok(purged)
+    }
+
+    async fn increase_views(&self, views: &[(ShortCode, u32)]) -> Result<()> {
+        let mut clips = self.clips.write();
+        for (short_code, count) in views {
+            if let Some(clip) = clips.get_mut(short_code.as_str()) {
+                clip.views += i64::from(*count);
+            }
+        }
+        Ok(())
+    }
+
+    async fn clip_usage(&self) -> Result<Vec<model::ClipUsage>> {
+        let view_stats = self.view_stats.read();
+        let mut last_viewed: HashMap<&str, NaiveDate> = HashMap::new();
+        for (short_code, day) in view_stats.keys() {
+            let last = last_viewed.entry(short_code.as_str()).or_insert(*day);
+            *last = (*last).max(*day);
+        }
+        Ok(self
+            .clips
+            .read()
+            .values()
+            .filter(|clip| clip.deleted_at.is_none())
+            .map(|clip| model::ClipUsage {
+                short_code: clip.short_code.clone(),
+                created_at: clip.created_at,
+                expires_at: clip.expires_at,
+                views: clip.views,
+                size: clip.size,
+                api_key_id: clip.api_key_id.clone(),
+                last_viewed: last_viewed.get(clip.short_code.as_str()).copied(),
+            })
+            .collect())
+    }
+
+    async fn blob_is_referenced(&self, key: &str) -> Result<bool> {
+        Ok(self.clips.read().values().any(|clip| {
+            clip.attachment_sha256.as_deref() == Some(key)
+                || clip.content_blob.as_deref() == Some(key)
+        }))
+    }
+
+    async fn content_bytes(&self) -> Result<u64> {
+        Ok(self
+            .clips
+            .read()
+            .values()
+            .map(|clip| clip.content.len() as u64)
+            .sum())
+    }
+}
+
+#[rocket::async_trait]
+impl ApiKeyRepository for MemoryRepository {
+    async fn save_api_key(
+        &self,
+        api_key: ApiKey,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<ApiKey> {
+        self.api_keys
+            .write()
+            .insert(api_key.clone().into_inner(), expires_at);
+        Ok(api_key)
+    }
+
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus> {
+        Ok(match self.api_keys.write().remove(&api_key.into_inner()) {
+            Some(_) => RevocationStatus::Revoked,
+            None => RevocationStatus::NotFound,
+        })
+    }
+
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool> {
+        let now = Utc::now().naive_utc();
+        Ok(match self.api_keys.read().get(&api_key.into_inner()) {
+            Some(expires_at) => expires_at.is_none_or(|expires_at| expires_at > now),
+            None => false,
+        })
+    }
+
+    async fn delete_expired_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let now = Utc::now().naive_utc();
+        let mut expired = vec![];
+        self.api_keys.write().retain(|key, expires_at| {
+            let keep = expires_at.is_none_or(|expires_at| expires_at > now);
+            if !keep {
+                expired.push(ApiKey::from(key.clone()));
+            }
+            keep
+        });
+        Ok(expired)
+    }
+}
+
+#[rocket::async_trait]
+impl StatsRepository for MemoryRepository {
+    async fn record_view_stats(&self, stats: Vec<model::ViewStats>) -> Result<()> {
+        let clips = self.clips.read();
+        let mut view_stats = self.view_stats.write();
+        for stats in stats.into_iter() {
+            if !clips.contains_key(&stats.short_code) {
+                continue;
+            }
+            let key = (stats.short_code.clone(), stats.day);
+            let stats = match view_stats.remove(&key) {
+                Some(stored) => stored.merge(stats),
+                None => stats,
+            };
+            view_stats.insert(key, stats);
+        }
+        Ok(())
+    }
+
+    async fn view_stats(
+        &self,
+        short_code: &ShortCode,
+        since: NaiveDate,
+    ) -> Result<Vec<model::ViewStats>> {
+        let mut stats = self
+            .view_stats
+            .read()
+            .values()
+            .filter(|stats| stats.short_code == short_code.as_str() && stats.day >= since)
+            .cloned()
+            .collect::<Vec<_>>();
+        stats.sort_by_key(|stats| stats.day);
+        Ok(stats)
+    }
+
+    async fn view_salt(&self, day: NaiveDate) -> Result<Vec<u8>> {
+        let mut salts = self.view_salts.lock();
+        salts.retain(|stored, _| *stored >= day);
+        Ok(salts
+            .entry(day)
+            .or_insert_with(|| rand::random::<[u8; 32]>().to_vec())
+            .clone())
+    }
+}
+
+fn audit_matches(filter: &AuditFilter, record: &model::AuditRecord) -> bool {
+    filter
+        .event
+        .is_none_or(|event| record.event == event.as_ref())
+        && filter
+            .actor
+            .as_ref()
+            .is_none_or(|actor| &record.actor == actor)
+        && filter
+            .short_code
+            .as_ref()
+            .is_none_or(|short_code| record.short_code.as_ref() == Some(short_code))
+        && filter
+            .outcome
+            .is_none_or(|outcome| record.outcome == outcome.as_ref())
+        && filter.since.is_none_or(|since| record.at >= since)
+        && filter.until.is_none_or(|until| record.at < until)
+        && filter.after.is_none_or(|after| record.id > after)
+}
+
+#[rocket::async_trait]
+impl AuditRepository for MemoryRepository {
+    async fn append_audit(&self, record: model::NewAuditRecord) -> Result<()> {
+        let mut log = self.audit_log.write();
+        let id = log.len() as i64 + 1;
+        log.push(model::AuditRecord {
+            id,
+            at: record.at,
+            event: record.event,
+            actor: record.actor,
+            short_code: record.short_code,
+            outcome: record.outcome,
+            detail: record.detail,
+        });
+        Ok(())
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<Vec<model::AuditRecord>> {
+        Ok(self
+            .audit_log
+            .read()
+            .iter()
+            .filter(|record| audit_matches(filter, record))
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn audit_salt(&self) -> Result<Vec<u8>> {
+        Ok(self
+            .audit_salt
+            .lock()
+            .get_or_insert_with(|| rand::random::<[u8; 32]>().to_vec())
+            .clone())
+    }
+}
+
+#[rocket::async_trait]
+impl LockRepository for MemoryRepository {
+    async fn acquire_lock(&self, name: &str, owner: &str, lease: Duration) -> Result<bool> {
+        let now = Utc::now();
+        let mut locks = self.locks.lock();
+        if let Some((holder, expires_at)) = locks.get(name) {
+            if holder != owner && *expires_at > now {
+                return Ok(false);
+            }
+        }
+        let expires_at = chrono::Duration::from_std(lease)
+            .ok()
+            .and_then(|lease| now.checked_add_signed(lease))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        locks.insert(name.to_owned(), (owner.to_owned(), expires_at));
+        Ok(true)
+    }
+
+    async fn release_lock(&self, name: &str, owner: &str) -> Result<()> {
+        let mut locks = self.locks.lock();
+        if locks.get(name).is_some_and(|(holder, _)| holder == owner) {
+            locks.remove(name);
+        }
+        Ok(())
+    }
+}
+
+impl Repository for MemoryRepository {}
@@ -0,0 +1,560 @@
+// Repositories are the storage boundary the service layer depends on.
+// `SqliteRepository` and `PostgresRepository` are backed by a database, selected by cargo
+// feature, and `MemoryRepository` keeps everything in process, for tests and for embedding
+// without a database file.
+pub mod memory;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use memory::MemoryRepository;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresRepository;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteRepository;
+
+use crate::data::migrate::{MigrationError, MigrationStatus};
+use crate::data::{model, model::RevocationStatus, DataError, PoolStats};
+use crate::domain::audit::AuditFilter;
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::sync::Arc;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+#[rocket::async_trait]
+pub trait ClipRepository: Send + Sync {
+    /// a live clip. clips in the trash are not found
+    async fn get_clip(&self, short_code: &ShortCode) -> Result<model::Clip>;
+
+    async fn new_clip(&self, clip: model::NewClip) -> Result<model::Clip>;
+
+    async fn update_clip(&self, clip: model::UpdateClip) -> Result<model::Clip>;
+
+    /// moves a live clip to the trash
+    async fn trash_clip(&self, short_code: &ShortCode) -> Result<()>;
+
+    /// moves every expired clip to the trash, returning how many there were
+    async fn trash_expired(&self) -> Result<u64>;
+
+    /// a clip in the trash
+    async fn get_trashed_clip(&self, short_code: &ShortCode) -> Result<model::Clip>;
+
+    /// takes a clip out of the trash, expiring at `expires_at` or else when it did before. the
+    /// service asks for a new expiry when that is in the past, or it would be trashed again
+    async fn restore_clip(
+        &self,
+        short_code: &ShortCode,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<model::Clip>;
+
+    /// deletes the clips that went to the trash before `before` for good, returning the blobs
+    /// they referenced
+    async fn purge_trash(&self, before: NaiveDateTime) -> Result<Vec<model::BlobRefs>>;
+
+    /// adds each count to the views of its clip, as one write
+    async fn increase_views(&self, views: &[(ShortCode, u32)]) -> Result<()>;
+
+    /// every live clip, as seen by the retention policies
+    async fn clip_usage(&self) -> Result<Vec<model::ClipUsage>>;
+
+    /// whether any clip still references the blob stored under `key`
+    async fn blob_is_referenced(&self, key: &str) -> Result<bool>;
+
+    /// bytes of content stored inline in clips, not counting blobs. clips in the trash are
+    /// counted, as they take up space until they are purged
+    async fn content_bytes(&self) -> Result<u64>;
+}
+
+#[rocket::async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    /// stores a key that is valid until `expires_at`, or for good without one
+    async fn save_api_key(
+        &self,
+        api_key: ApiKey,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<ApiKey>;
+
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus>;
+
+    /// whether the key is stored and has not expired
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool>;
+
+    /// deletes the keys that have expired and returns them
+    async fn delete_expired_api_keys(&self) -> Result<Vec<ApiKey>>;
+}
+
+#[rocket::async_trait]
+pub trait StatsRepository: Send + Sync {
+    /// adds each day of views to the statistics stored for its clip. views of clips that no
+    /// longer exist are dropped
+    async fn record_view_stats(&self, stats: Vec<model::ViewStats>) -> Result<()>;
+
+    /// statistics of a clip from `since` on, oldest first
+    async fn view_stats(
+        &self,
+        short_code: &ShortCode,
+        since: NaiveDate,
+    ) -> Result<Vec<model::ViewStats>>;
+
+    /// salt for hashing the visitors of `day`, created on first use. salts of earlier days are
+    /// deleted, so their visitor hashes can no longer be linked to anyone
+    async fn view_salt(&self, day: NaiveDate) -> Result<Vec<u8>>;
+}
+
+#[rocket::async_trait]
+pub trait AuditRepository: Send + Sync {
+    /// appends an entry. entries are never changed or removed
+    async fn append_audit(&self, record: model::NewAuditRecord) -> Result<()>;
+
+    /// entries matching `filter`, oldest first
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<Vec<model::AuditRecord>>;
+
+    /// salt for hashing the addresses of actors, created on first use and kept from then on
+    async fn audit_salt(&self) -> Result<Vec<u8>>;
+}
+
+#[rocket::async_trait]
+pub trait LockRepository: Send + Sync {
+    /// takes the lock `name` for `owner` for `lease`, or extends it when `owner` already holds
+    /// it. false while another owner holds it and its lease has not lapsed
+    async fn acquire_lock(&self, name: &str, owner: &str, lease: Duration) -> Result<bool>;
+
+    /// gives up the lock, if `owner` holds it
+    async fn release_lock(&self, name: &str, owner: &str) -> Result<()>;
+}
+
+/// everything the application needs from its storage
+#[rocket::async_trait]
+pub trait Repository:
+    ClipRepository + ApiKeyRepository + StatsRepository + AuditRepository + LockRepository
+{
+    /// releases the connections held by the repository, once the application shuts down
+    async fn close(&self) {}
+
+    /// connections of the database pool, for repositories that have one
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+
+    /// checks that the storage can be reached
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// reclaims space and refreshes planner statistics, for repositories with a database
+    async fn optimize(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// migrations of the database schema, for repositories that have one
+    async fn migration_status(&self) -> std::result::Result<Vec<MigrationStatus>, MigrationError> {
+        Ok(vec![])
+    }
+}
+
+pub type AppRepository = Arc<dyn Repository>;
+
+#[cfg(test)]
+pub mod test {
+    //! behaviour every repository implementation must share
+
+    use super::*;
+    use crate::domain::clip::field::Content;
+    use crate::service::ask;
+    use crate::test::async_runtime;
+
+    fn new_clip(content: &str) -> model::NewClip {
+        model::NewClip::from(ask::NewClip {
+            content: Content::new(content).unwrap(),
+            title: Default::default(),
+            exprires_at: Default::default(),
+            password: Default::default(),
+            attachment: None,
+            owner: None,
+            api_key: None,
+        })
+    }
+
+    pub async fn clips_round_trip(repository: &dyn Repository) {
+        let clip = repository.new_clip(new_clip("first")).await.unwrap();
+        let short_code = ShortCode::from(clip.short_code.clone());
+
+        let other = repository.new_clip(new_clip("öther")).await.unwrap();
+        let other = ShortCode::from(other.short_code);
+        repository
+            .increase_views(&[(short_code.clone(), 3), (other.clone(), 1)])
+            .await
+            .unwrap();
+        repository.increase_views(&[]).await.unwrap();
+        let clip = repository.get_clip(&short_code).await.unwrap();
+        assert_eq!(clip.content, "first");
+        assert_eq!(clip.views, 3);
+        assert_eq!(repository.get_clip(&other).await.unwrap().views, 1);
+        assert_eq!(
+            repository.content_bytes().await.unwrap(),
+            11,
+            "bytes, not characters"
+        );
+
+        let updated = repository
+            .update_clip(model::UpdateClip::from(ask::UpdateClip {
+                content: Content::new("second").unwrap(),
+                title: Default::default(),
+                exprires_at: Default::default(),
+                password: Default::default(),
+                short_code: short_code.clone(),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(updated.content, "second");
+
+        repository.trash_clip(&short_code).await.unwrap();
+        assert!(matches!(
+            repository.get_clip(&short_code).await,
+            Err(DataError::NotFound)
+        ));
+        assert!(matches!(
+            repository.trash_clip(&short_code).await,
+            Err(DataError::NotFound)
+        ));
+    }
+
+    pub async fn trashes_and_restores_clips(repository: &dyn Repository) {
+        let now = chrono::Utc::now();
+        let mut expired = new_clip("expired");
+        expired.expires_at = Some((now - chrono::Duration::days(1)).timestamp());
+        let expired = ShortCode::from(repository.new_clip(expired).await.unwrap().short_code);
+        let kept = ShortCode::from(
+            repository
+                .new_clip(new_clip("kept"))
+                .await
+                .unwrap()
+                .short_code,
+        );
+
+        assert_eq!(repository.trash_expired().await.unwrap(), 1);
+        assert_eq!(repository.trash_expired().await.unwrap(), 0);
+        assert!(repository.get_clip(&expired).await.is_err());
+        assert!(repository.get_clip(&kept).await.is_ok());
+        assert!(repository.get_trashed_clip(&kept).await.is_err());
+        let trashed = repository.get_trashed_clip(&expired).await.unwrap();
+        assert!(trashed.deleted_at.is_some());
+
+        let tomorrow = chrono::DateTime::from_timestamp(now.timestamp() + 24 * 60 * 60, 0)
+            .unwrap()
+            .naive_utc();
+        let restored = repository
+            .restore_clip(&expired, Some(tomorrow))
+            .await
+            .unwrap();
+        assert_eq!(restored.expires_at, Some(tomorrow));
+        assert!(repository.get_clip(&expired).await.is_ok());
+        assert!(repository.restore_clip(&expired, None).await.is_err());
+
+        // without a new expiry the clip keeps its own
+        repository.trash_clip(&expired).await.unwrap();
+        let restored = repository.restore_clip(&expired, None).await.unwrap();
+        assert_eq!(restored.expires_at, Some(tomorrow));
+
+        repository.trash_clip(&kept).await.unwrap();
+        let hour_ago = (now - chrono::Duration::hours(1)).naive_utc();
+        assert!(repository.purge_trash(hour_ago).await.unwrap().is_empty());
+        assert!(repository.get_trashed_clip(&kept).await.is_ok());
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(1)).naive_utc();
+        assert!(!repository.purge_trash(later).await.unwrap().is_empty());
+        assert!(repository.get_trashed_clip(&kept).await.is_err());
+        assert!(repository.restore_clip(&kept, None).await.is_err());
+        assert!(repository.get_clip(&expired).await.is_ok());
+    }
+
+    pub async fn api_keys(repository: &dyn Repository) {
+        let api_key = repository
+            .save_api_key(ApiKey::default(), None)
+            .await
+            .unwrap();
+        assert!(repository.api_key_is_valid(api_key.clone()).await.unwrap());
+        assert!(!repository
+            .api_key_is_valid(ApiKey::default())
+            .await
+            .unwrap());
+
+        assert!(matches!(
+            repository.revoke_api_key(api_key.clone()).await.unwrap(),
+            RevocationStatus::Revoked
+        ));
+        assert!(matches!(
+            repository.revoke_api_key(api_key.clone()).await.unwrap(),
+            RevocationStatus::NotFound
+        ));
+        assert!(!repository.api_key_is_valid(api_key).await.unwrap());
+
+        let now = chrono::Utc::now().naive_utc();
+        let lasting = repository
+            .save_api_key(ApiKey::default(), Some(now + chrono::Duration::hours(1)))
+            .await
+            .unwrap();
+        let expired = repository
+            .save_api_key(ApiKey::default(), Some(now - chrono::Duration::seconds(1)))
+            .await
+            .unwrap();
+        assert!(repository.api_key_is_valid(lasting.clone()).await.unwrap());
+        assert!(!repository.api_key_is_valid(expired.clone()).await.unwrap());
+        let deleted = repository.delete_expired_api_keys().await.unwrap();
+        assert_eq!(
+            deleted.iter().map(ApiKey::id).collect::<Vec<_>>(),
+            [expired.id()]
+        );
+        assert!(repository
+            .delete_expired_api_keys()
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(repository.api_key_is_valid(lasting).await.unwrap());
+    }
+
+    pub async fn view_stats(repository: &dyn Repository) {
+        use crate::domain::stats::{DailyViews, ViewSource};
+
+        let clip = repository.new_clip(new_clip("stats")).await.unwrap();
+        let short_code = ShortCode::from(clip.short_code);
+        let today = chrono::Utc::now().date_naive();
+        let yesterday = today.pred_opt().unwrap();
+
+        let views = |day, source, referrer: Option<&str>| {
+            let mut views = DailyViews::new(short_code.clone(), day);
+            views.record(source, Some(7), referrer.map(str::to_owned));
+            model::ViewStats::from(views)
+        };
+        let gone = DailyViews::new(ShortCode::from("gone"), today);
+        repository
+            .record_view_stats(vec![
+                views(yesterday, ViewSource::Api, None),
+                views(today, ViewSource::Web, Some("example.com")),
+                gone.into(),
+            ])
+            .await
+            .unwrap();
+        repository
+            .record_view_stats(vec![views(today, ViewSource::Raw, Some("example.com"))])
+            .await
+            .unwrap();
+
+        let stored = repository.view_stats(&short_code, today).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        let stored = DailyViews::from(stored[0].clone());
+        assert_eq!((stored.web, stored.raw, stored.api), (1, 1, 0));
+        assert_eq!(stored.visitors.estimate(), 1);
+        assert_eq!(stored.referrers.get("example.com"), Some(&2));
+        let stored = repository.view_stats(&short_code, yesterday).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].day, yesterday);
+
+        let salt = repository.view_salt(yesterday).await.unwrap();
+        assert_eq!(repository.view_salt(yesterday).await.unwrap(), salt);
+        assert_ne!(repository.view_salt(today).await.unwrap(), salt);
+        assert_ne!(repository.view_salt(yesterday).await.unwrap(), salt);
+
+        repository.trash_clip(&short_code).await.unwrap();
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(1)).naive_utc();
+        repository.purge_trash(later).await.unwrap();
+        assert!(repository
+            .view_stats(&short_code, yesterday)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    pub async fn clip_usage(repository: &dyn Repository) {
+        use crate::domain::retention::ClipUsage;
+        use crate::domain::stats::{DailyViews, ViewSource};
+
+        let mut clip = new_clip("usage");
+        clip.api_key_id = Some("key".to_owned());
+        let short_code = ShortCode::from(repository.new_clip(clip).await.unwrap().short_code);
+        let usage = |short_code: ShortCode| async move {
+            repository
+                .clip_usage()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(ClipUsage::from)
+                .find(|usage| usage.short_code == short_code)
+        };
+
+        let stored = usage(short_code.clone()).await.unwrap();
+        assert_eq!(stored.size, 5);
+        assert_eq!(stored.api_key.as_deref(), Some("key"));
+        assert_eq!((stored.views, stored.last_viewed), (0, None));
+
+        let today = chrono::Utc::now().date_naive();
+        let mut views = DailyViews::new(short_code.clone(), today);
+        views.record(ViewSource::Web, None, None);
+        repository
+            .record_view_stats(vec![views.into()])
+            .await
+            .unwrap();
+        repository
+            .increase_views(&[(short_code.clone(), 1)])
+            .await
+            .unwrap();
+        let stored = usage(short_code.clone()).await.unwrap();
+        assert_eq!((stored.views, stored.last_viewed), (1, Some(today)));
+
+        repository.trash_clip(&short_code).await.unwrap();
+        assert!(usage(short_code).await.is_none());
+    }
+
+    pub async fn locks(repository: &dyn Repository) {
+        let lease = Duration::from_secs(60);
+        assert!(repository.acquire_lock("jobs", "a", lease).await.unwrap());
+        assert!(!repository.acquire_lock("jobs", "b", lease).await.unwrap());
+        assert!(repository.acquire_lock("jobs", "a", lease).await.unwrap());
+        assert!(repository.acquire_lock("other", "b", lease).await.unwrap());
+
+        // a holder that stops renewing is taken over once its lease lapsed
+        assert!(repository
+            .acquire_lock("jobs", "a", Duration::ZERO)
+            .await
+            .unwrap());
+        assert!(repository.acquire_lock("jobs", "b", lease).await.unwrap());
+        assert!(!repository.acquire_lock("jobs", "a", lease).await.unwrap());
+
+        repository.release_lock("jobs", "a").await.unwrap();
+        assert!(!repository.acquire_lock("jobs", "a", lease).await.unwrap());
+        repository.release_lock("jobs", "b").await.unwrap();
+        assert!(repository.acquire_lock("jobs", "a", lease).await.unwrap());
+    }
+
+    pub async fn audit_log(repository: &dyn Repository) {
+        use crate::domain::audit::{AuditEvent, AuditFilter, Outcome};
+
+        let short_code = ShortCode::from("audited");
+        let entries = [
+            (AuditEvent::ClipCreated, "system", Outcome::Success),
+            (AuditEvent::PasswordFailed, "ip:01", Outcome::Failure),
+            (AuditEvent::ClipUnlocked, "ip:01", Outcome::Success),
+        ];
+        for (event, actor, outcome) in entries {
+            let record = model::NewAuditRecord::new(
+                event,
+                actor.to_owned(),
+                Some(&short_code),
+                outcome,
+                None,
+            );
+            repository.append_audit(record).await.unwrap();
+        }
+        repository
+            .append_audit(model::NewAuditRecord::new(
+                AuditEvent::ApiKeyCreated,
+                "system".to_owned(),
+                None,
+                Outcome::Success,
+                Some("key 0123".to_owned()),
+            ))
+            .await
+            .unwrap();
+
+        let all = repository.audit_log(&AuditFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 4, "without a limit a whole page is returned");
+        let first = repository
+            .audit_log(&AuditFilter {
+                limit: 1,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].id, all[0].id);
+        let filter = AuditFilter {
+            limit: 10,
+            ..Default::default()
+        };
+        let all = repository.audit_log(&filter).await.unwrap();
+        assert_eq!(all.len(), 4);
+        assert!(all.windows(2).all(|pair| pair[0].id < pair[1].id));
+        assert_eq!(all[3].detail.as_deref(), Some("key 0123"));
+
+        let by_actor = repository
+            .audit_log(&AuditFilter {
+                actor: Some("ip:01".to_owned()),
+                outcome: Some(Outcome::Success),
+                ..filter.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(by_actor.len(), 1);
+        assert_eq!(by_actor[0].event, "clip_unlocked");
+
+        let for_clip = repository
+            .audit_log(&AuditFilter {
+                short_code: Some("audited".to_owned()),
+                after: Some(all[0].id),
+                ..filter.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(for_clip.len(), 2);
+
+        let since = all[0].at - chrono::Duration::hours(1);
+        let until = all[0].at + chrono::Duration::hours(1);
+        let window = |since, until| AuditFilter {
+            since,
+            until,
+            ..filter.clone()
+        };
+        let within = repository
+            .audit_log(&window(Some(since), Some(until)))
+            .await;
+        assert_eq!(within.unwrap().len(), 4);
+        let later = repository.audit_log(&window(Some(until), None)).await;
+        assert!(later.unwrap().is_empty());
+        let earlier = repository.audit_log(&window(None, Some(since))).await;
+        assert!(earlier.unwrap().is_empty());
+
+        let salt = repository.audit_salt().await.unwrap();
+        assert!(!salt.is_empty());
+        assert_eq!(repository.audit_salt().await.unwrap(), salt);
+    }
+
+    pub fn run_suite(repository: &dyn Repository) {
+        let rt = async_runtime();
+        rt.block_on(async {
+            clips_round_trip(repository).await;
+            trashes_and_restores_clips(repository).await;
+            api_keys(repository).await;
+            view_stats(repository).await;
+            clip_usage(repository).await;
+            locks(repository).await;
+            audit_log(repository).await;
+        });
+    }
+
+    #[test]
+    fn memory_repository() {
+        run_suite(&MemoryRepository::default());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_repository() {
+        let rt = async_runtime();
+        let db = crate::data::test::new_db(rt.handle());
+        run_suite(&SqliteRepository::new(db));
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_repository() {
+        let Some(url) = crate::data::test::postgres_url() else {
+            return;
+        };
+        let rt = async_runtime();
+        let db = crate::data::test::new_pg_db(rt.handle(), &url);
+        run_suite(&PostgresRepository::new(db));
+    }
+}
@@ -0,0 +1,170 @@
+use super::{
+    ApiKeyRepository, AuditRepository, ClipRepository, LockRepository, Repository, Result,
+    StatsRepository,
+};
+use crate::data::migrate::{MigrationError, MigrationStatus};
+use crate::data::{model, model::RevocationStatus, pg_query, PgDatabase, PoolStats};
+use crate::domain::audit::AuditFilter;
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::time::Duration;
+
+/// repository backed by a PostgreSQL database
+pub struct PostgresRepository(PgDatabase);
+
+impl PostgresRepository {
+    pub fn new(database: PgDatabase) -> Self {
+        Self(database)
+    }
+
+    pub fn database(&self) -> &PgDatabase {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl ClipRepository for PostgresRepository {
+    async fn get_clip(&self, short_code: &ShortCode) -> Result<model::Clip> {
+        pg_query::get_clip(short_code.clone(), self.0.get_pool()).await
+    }
+
+    async fn new_clip(&self, clip: model::NewClip) -> Result<model::Clip> {
+        pg_query::new_clip(clip, self.0.get_pool()).await
+    }
+
+    async fn update_clip(&self, clip: model::UpdateClip) -> Result<model::Clip> {
+        pg_query::update_clip(clip, self.0.get_pool()).await
+    }
+
+    async fn trash_clip(&self, short_code: &ShortCode) -> Result<()> {
+        pg_query::trash_clip(short_code, self.0.get_pool()).await
+    }
+
+    async fn trash_expired(&self) -> Result<u64> {
+        pg_query::trash_expired(self.0.get_pool()).await
+    }
+
+    async fn get_trashed_clip(&self, short_code: &ShortCode) -> Result<model::Clip> {
+        pg_query::get_trashed_clip(short_code, self.0.get_pool()).await
+    }
+
+    async fn restore_clip(
+        &self,
+        short_code: &ShortCode,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<model::Clip> {
+        pg_query::restore_clip(short_code, expires_at, self.0.get_pool()).await
+    }
+
+    async fn purge_trash(&self, before: NaiveDateTime) -> Result<Vec<model::BlobRefs>> {
+        pg_query::purge_trash(before, self.0.get_pool()).await
+    }
+
+    async fn increase_views(&self, views: &[(ShortCode, u32)]) -> Result<()> {
+        pg_query::increase_views(views, self.0.get_pool()).await
+    }
+
+    async fn clip_usage(&self) -> Result<Vec<model::ClipUsage>> {
+        pg_query::clip_usage(self.0.get_pool()).await
+    }
+
+    async fn blob_is_referenced(&self, key: &str) -> Result<bool> {
+        pg_query::blob_is_referenced(key, self.0.get_pool()).await
+    }
+
+    async fn content_bytes(&self) -> Result<u64> {
+        pg_query::content_bytes(self.0.get_pool()).await
+    }
+}
+
+#[rocket::async_trait]
+impl ApiKeyRepository for PostgresRepository {
+    async fn save_api_key(
+        &self,
+        api_key: ApiKey,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<ApiKey> {
+        pg_query::generate_api_key(api_key, expires_at, self.0.get_pool()).await
+    }
+
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus> {
+        pg_query::revoke_api_key(api_key, self.0.get_pool()).await
+    }
+
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool> {
+        pg_query::api_key_is_valid(api_key, self.0.get_pool()).await
+    }
+
+    async fn delete_expired_api_keys(&self) -> Result<Vec<ApiKey>> {
+        pg_query::delete_expired_api_keys(self.0.get_pool()).await
+    }
+}
+
+#[rocket::async_trait]
+impl StatsRepository for PostgresRepository {
+    async fn record_view_stats(&self, stats: Vec<model::ViewStats>) -> Result<()> {
+        pg_query::record_view_stats(stats, self.0.get_pool()).await
+    }
+
+    async fn view_stats(
+        &self,
+        short_code: &ShortCode,
+        since: NaiveDate,
+    ) -> Result<Vec<model::ViewStats>> {
+        pg_query::view_stats(short_code, since, self.0.get_pool()).await
+    }
+
+    async fn view_salt(&self, day: NaiveDate) -> Result<Vec<u8>> {
+        pg_query::view_salt(day, self.0.get_pool()).await
+    }
+}
+
+#[rocket::async_trait]
+impl AuditRepository for PostgresRepository {
+    async fn append_audit(&self, record: model::NewAuditRecord) -> Result<()> {
+        pg_query::append_audit(record, self.0.get_pool()).await
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<Vec<model::AuditRecord>> {
+        pg_query::audit_log(filter, self.0.get_pool()).await
+    }
+
+    async fn audit_salt(&self) -> Result<Vec<u8>> {
+        pg_query::audit_salt(self.0.get_pool()).await
+    }
+}
+
+#[rocket::async_trait]
+impl LockRepository for PostgresRepository {
+    async fn acquire_lock(&self, name: &str, owner: &str, lease: Duration) -> Result<bool> {
+        pg_query::acquire_lock(name, owner, lease, self.0.get_pool()).await
+    }
+
+    async fn release_lock(&self, name: &str, owner: &str) -> Result<()> {
+        pg_query::release_lock(name, owner, self.0.get_pool()).await
+    }
+}
+
+#[rocket::async_trait]
+impl Repository for PostgresRepository {
+    async fn close(&self) {
+        self.0.close().await
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(self.0.pool_stats())
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.0.ping().await
+    }
+
+    async fn optimize(&self) -> Result<()> {
+        pg_query::optimize(self.0.get_pool()).await
+    }
+
+    async fn migration_status(&self) -> std::result::Result<Vec<MigrationStatus>, MigrationError> {
+        self.0.migration_status().await
+    }
+}
@@ -0,0 +1,176 @@
+//! trail of security-relevant events. entries are only ever appended; visitors appear as a
+//! keyed hash of their address and API keys by an id derived from the key, never as themselves
+use crate::Time;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// most entries returned by one query
+pub const MAX_ENTRIES: u32 = 1000;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    strum::Display,
+    strum::EnumString,
+    strum::AsRefStr,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditEvent {
+    ClipCreated,
+    ClipUpdated,
+    /// a clip went to the trash
+    ClipDeleted,
+    /// a clip was taken out of the trash, `failure` when it was not by its owner
+    ClipRestored,
+    /// a wrong password was given for a protected clip
+    PasswordFailed,
+    /// a protected clip was opened with its password
+    ClipUnlocked,
+    ApiKeyCreated,
+    ApiKeyRevoked,
+    /// a key reached the end of its lifetime and was deleted
+    ApiKeyExpired,
+    /// a request presented an API key, `failure` when it is not known
+    ApiKeyUsed,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    strum::Display,
+    strum::EnumString,
+    strum::AsRefStr,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// who caused an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Actor {
+    /// a client presenting an API key, by the id of the key
+    ApiKey(String),
+    /// a visitor of the web pages
+    Address(IpAddr),
+    /// the server itself, e.g. a maintenance job
+    System,
+    Anonymous,
+}
+
+impl Actor {
+    /// how the actor is stored. addresses are hashed with `salt`, which is kept by the server
+    pub fn to_record(&self, salt: &[u8]) -> String {
+        match self {
+            Self::ApiKey(id) => format!("api_key:{}", id),
+            Self::Address(address) => {
+                let digest = Sha256::new()
+                    .chain_update(salt)
+                    .chain_update(address.to_string())
+                    .finalize();
+                format!("ip:{}", hex_prefix(&digest))
+            }
+            Self::System => "system".to_owned(),
+            Self::Anonymous => "anonymous".to_owned(),
+        }
+    }
+
+    pub fn needs_salt(&self) -> bool {
+        matches!(self, Self::Address(_))
+    }
+}
+
+/// first 16 hex digits of a digest, enough to tell actors apart
+pub fn hex_prefix(digest: &[u8]) -> String {
+    digest[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// one entry of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub at: NaiveDateTime,
+    pub event: String,
+    pub actor: String,
+    pub short_code: Option<String>,
+    pub outcome: String,
+    pub detail: Option<String>,
+}
+
+/// selects audit entries. every condition that is set must hold
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub event: Option<AuditEvent>,
+    pub actor: Option<String>,
+    pub short_code: Option<String>,
+    pub outcome: Option<Outcome>,
+    pub since: Option<NaiveDateTime>,
+    /// exclusive
+    pub until: Option<NaiveDateTime>,
+    /// only entries after this id, for paging through the log
+    pub after: Option<i64>,
+    /// at most this many entries, oldest first, capped at [`MAX_ENTRIES`]. 0, the default,
+    /// means a page of [`MAX_ENTRIES`]
+    pub limit: u32,
+}
+
+impl AuditFilter {
+    pub fn limit(&self) -> u32 {
+        match self.limit {
+            0 => MAX_ENTRIES,
+            limit => limit.min(MAX_ENTRIES),
+        }
+    }
+}
+
+/// a point in time given as RFC 3339 or as a date, which means its midnight in UTC
+pub fn parse_time(time: &str) -> Option<NaiveDateTime> {
+    time.parse::<DateTime<Utc>>()
+        .ok()
+        .or_else(|| Time::from_str(time).ok().map(Time::into_inner))
+        .map(|time| time.naive_utc())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn records_actors_without_revealing_them() {
+        let address = Actor::Address("10.1.2.3".parse().unwrap());
+        let record = address.to_record(b"salt");
+        assert!(record.starts_with("ip:"));
+        assert!(!record.contains("10.1.2.3"));
+        assert_eq!(record, address.to_record(b"salt"));
+        assert_ne!(record, address.to_record(b"other salt"));
+        assert_eq!(
+            Actor::ApiKey("ab12".to_owned()).to_record(b""),
+            "api_key:ab12"
+        );
+
+        assert_eq!(AuditEvent::PasswordFailed.as_ref(), "password_failed");
+        assert_eq!("api_key_used".parse(), Ok(AuditEvent::ApiKeyUsed));
+        assert!(parse_time("2024-03-10").is_some());
+        assert!(parse_time("2024-03-10T12:00:00Z").is_some());
+        assert!(parse_time("yesterday").is_none());
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FileMeta {
+    pub file_name: String,
+    pub mime_type: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl FileMeta {
+    /// describes an uploaded file by its (sanitized) name, mime type, size and SHA-256 digest
+    pub fn describe(file_name: &str, mime_type: &str, data: &[u8]) -> Self {
+        let mime_type = match mime_type.trim() {
+            "" => DEFAULT_MIME_TYPE.to_owned(),
+            mime_type => mime_type.to_owned(),
+        };
+
+        Self {
+            file_name: sanitize_file_name(file_name),
+            mime_type,
+            size: data.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(data)),
+        }
+    }
+}
+
+/// keeps only the last path component and strips characters that would break
+/// a `Content-Disposition` header
+fn sanitize_file_name(file_name: &str) -> String {
+    let name = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .collect::<String>();
+
+    match name.trim() {
+        "" | "." | ".." => "attachment".to_owned(),
+        name => name.to_owned(),
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Attachment(Option<FileMeta>);
+
+impl Attachment {
+    pub fn new<T: Into<Option<FileMeta>>>(attachment: T) -> Self {
+        Self(attachment.into())
+    }
+
+    pub fn into_inner(self) -> Option<FileMeta> {
+        self.0
+    }
+
+    pub fn as_ref(&self) -> Option<&FileMeta> {
+        self.0.as_ref()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::FileMeta;
+
+    #[test]
+    fn describes_upload() {
+        let meta = FileMeta::describe("../../etc/pass\"wd", "", b"hello");
+        assert_eq!(meta.file_name, "passwd");
+        assert_eq!(meta.mime_type, "application/octet-stream");
+        assert_eq!(meta.size, 5);
+        assert_eq!(
+            meta.sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+}
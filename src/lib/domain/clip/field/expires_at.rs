@@ -38,12 +38,15 @@ impl FromStr for ExpiresAt {
 
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for ExpiresAt {
+    fn default() -> Option<Self> {
+        Some(Default::default())
+    }
+
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
         if field.value.trim().is_empty() {
             Ok(Self(None))
         } else {
-            Ok(Self::from_str(field.value)
-                .map_err(|e| form::Error::validation(format!("{}", e)))?)
+            Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(e.message_id()))?)
         }
     }
 }
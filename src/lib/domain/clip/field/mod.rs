@@ -21,3 +21,9 @@ pub use password::Password;
 
 mod views;
 pub use views::Views;
+
+mod attachment;
+pub use attachment::{Attachment, FileMeta};
+
+mod owner_key;
+pub use owner_key::OwnerKey;
@@ -0,0 +1,36 @@
+use crate::logging::Redacted;
+use base64::{engine::general_purpose, Engine as _};
+use sha2::{Digest, Sha256};
+
+/// secret handed to whoever creates a clip through the web form. only its hash is stored, and
+/// presenting the key unlocks owner-only pages such as the clip statistics
+#[derive(Clone, PartialEq, Eq)]
+pub struct OwnerKey(String);
+
+impl std::fmt::Debug for OwnerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OwnerKey").field(&Redacted(())).finish()
+    }
+}
+
+impl OwnerKey {
+    pub fn new<T: Into<String>>(key: T) -> Self {
+        Self(key.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// hex SHA-256 digest, as stored with the clip
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl Default for OwnerKey {
+    fn default() -> Self {
+        let key: [u8; 24] = rand::random();
+        Self(general_purpose::URL_SAFE_NO_PAD.encode(key))
+    }
+}
@@ -1,11 +1,21 @@
 use crate::domain::clip::ClipError;
+use crate::logging::Redacted;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd, Default)]
+#[derive(Clone, Deserialize, Serialize, PartialEq, PartialOrd, Default)]
 pub struct Password(Option<String>);
 
+/// shows whether a password is set, never the password itself
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Password")
+            .field(&self.0.as_ref().map(Redacted))
+            .finish()
+    }
+}
+
 impl Password {
     pub fn new<T: Into<Option<String>>>(password: T) -> Result<Self, ClipError> {
         //    this 👆 allows function to accept both String and Option<String>
@@ -27,6 +37,10 @@ impl Password {
         self.0
     }
 
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
     pub fn has_password(&self) -> bool {
         self.0.is_some()
     }
@@ -41,8 +55,12 @@ impl FromStr for Password {
 
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Password {
+    fn default() -> Option<Self> {
+        Some(Default::default())
+    }
+
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
         Ok(Self::new(field.value.to_owned())
-            .map_err(|e| form::Error::validation(format!("{}", e)))?)
+            .map_err(|e| form::Error::validation(e.message_id()))?)
     }
 }
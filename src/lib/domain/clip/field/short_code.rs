@@ -12,14 +12,17 @@ pub struct ShortCode(String);
 
 impl ShortCode {
     pub fn new() -> Self {
-        use rand::prelude::*;
+        Self::generate(10, &['a', 'b', 'c', 'd', '1', '2', '3', '4'])
+    }
 
-        let allowed_chars = ['a', 'b', 'c', 'd', '1', '2', '3', '4'];
+    /// random short code of `length` characters picked from `allowed_chars`
+    pub fn generate(length: usize, allowed_chars: &[char]) -> Self {
+        use rand::prelude::*;
 
         let mut rng = thread_rng();
-        let mut short_code = String::with_capacity(10);
+        let mut short_code = String::with_capacity(length);
 
-        for _ in 0..10 {
+        for _ in 0..length {
             short_code.push(
                 *allowed_chars
                     .choose(&mut rng)
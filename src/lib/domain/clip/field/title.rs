@@ -42,6 +42,10 @@ impl FromStr for Title {
 
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Title {
+    fn default() -> Option<Self> {
+        Some(Default::default())
+    }
+
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
         if field.value.trim().is_empty() {
             Ok(Self(None))
@@ -27,6 +27,20 @@ pub enum ClipError {
     Views(#[from] std::num::TryFromIntError),
 }
 
+impl ClipError {
+    /// the id of the message shown to users for this error, in `locales/*.ftl`
+    pub fn message_id(&self) -> &'static str {
+        match self {
+            Self::InvalidPassword(_) => "clip-error-invalid-password",
+            Self::InvalidTitle(_) => "clip-error-invalid-title",
+            Self::EmptyContent => "clip-error-empty-content",
+            Self::InvalidDate(_) | Self::DateParse(_) => "clip-error-invalid-date",
+            Self::Id(_) => "clip-error-invalid-id",
+            Self::Views(_) => "clip-error-invalid-views",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Clip {
     pub id: field::Id,
@@ -37,4 +51,6 @@ pub struct Clip {
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
     pub views: field::Views,
+    #[serde(default)]
+    pub attachment: field::Attachment,
 }
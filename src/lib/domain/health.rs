@@ -0,0 +1,163 @@
+//! health of the parts of the application that run on their own, reported by `/readyz`
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    /// working, but something needs attention
+    Degraded,
+    /// not able to serve requests
+    Down,
+}
+
+/// health of one component, as reported to the orchestrator
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// seconds since the component was last seen working
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_success_secs: Option<u64>,
+    /// failures since the last success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failures: Option<u32>,
+}
+
+impl ComponentHealth {
+    pub fn ok() -> Self {
+        Self {
+            status: HealthStatus::Ok,
+            message: None,
+            last_success_secs: None,
+            failures: None,
+        }
+    }
+
+    pub fn degraded<T: Into<String>>(message: T) -> Self {
+        Self {
+            status: HealthStatus::Degraded,
+            message: Some(message.into()),
+            ..Self::ok()
+        }
+    }
+
+    pub fn down<T: Into<String>>(message: T) -> Self {
+        Self {
+            status: HealthStatus::Down,
+            message: Some(message.into()),
+            ..Self::ok()
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HeartbeatState {
+    last_beat: Option<Instant>,
+    last_success: Option<Instant>,
+    failures: u32,
+    last_error: Option<String>,
+}
+
+/// kept by a background task: it beats whenever the task runs and records how its work went
+#[derive(Debug)]
+pub struct Heartbeat {
+    /// how often the task is expected to run
+    period: Duration,
+    /// stands in for the last beat until there is one
+    created: Instant,
+    state: Mutex<HeartbeatState>,
+}
+
+impl Heartbeat {
+    /// periods that may pass without a beat before the task counts as stalled
+    const MISSED_BEATS: u32 = 3;
+
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            created: Instant::now(),
+            state: Mutex::new(HeartbeatState {
+                last_beat: None,
+                last_success: None,
+                failures: 0,
+                last_error: None,
+            }),
+        }
+    }
+
+    /// the task is alive, whether or not it had anything to do
+    pub fn beat(&self) {
+        self.state.lock().last_beat = Some(Instant::now());
+    }
+
+    pub fn succeeded(&self) {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        state.last_beat = Some(now);
+        state.last_success = Some(now);
+        state.failures = 0;
+        state.last_error = None;
+    }
+
+    pub fn failed<E: std::fmt::Display>(&self, error: E) {
+        let mut state = self.state.lock();
+        state.last_beat = Some(Instant::now());
+        state.failures += 1;
+        state.last_error = Some(error.to_string());
+    }
+
+    /// degraded once the task stalls or its last run failed
+    pub fn health(&self) -> ComponentHealth {
+        let state = self.state.lock();
+        let now = Instant::now();
+        let stalled_after = self.period * Self::MISSED_BEATS;
+
+        let mut health =
+            if now.duration_since(state.last_beat.unwrap_or(self.created)) > stalled_after {
+                ComponentHealth::degraded(format!(
+                    "no heartbeat for more than {}s",
+                    stalled_after.as_secs()
+                ))
+            } else if state.failures > 0 {
+                ComponentHealth::degraded(state.last_error.clone().unwrap_or_default())
+            } else {
+                ComponentHealth::ok()
+            };
+        health.last_success_secs = state
+            .last_success
+            .map(|success| now.duration_since(success).as_secs());
+        health.failures = Some(state.failures);
+        health
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn degrades_on_failures_and_stalls() {
+        let heartbeat = Heartbeat::new(Duration::from_secs(60));
+        assert_eq!(heartbeat.health().status, HealthStatus::Ok);
+
+        heartbeat.failed("database is locked");
+        heartbeat.failed("database is locked");
+        let health = heartbeat.health();
+        assert_eq!(health.status, HealthStatus::Degraded);
+        assert_eq!(health.failures, Some(2));
+        assert_eq!(health.message.as_deref(), Some("database is locked"));
+
+        heartbeat.succeeded();
+        let health = heartbeat.health();
+        assert_eq!(health.status, HealthStatus::Ok);
+        assert_eq!(health.last_success_secs, Some(0));
+
+        let stalled = Heartbeat::new(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(stalled.health().status, HealthStatus::Degraded);
+    }
+}
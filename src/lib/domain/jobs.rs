@@ -0,0 +1,505 @@
+//! background jobs. each runs on its own schedule, with a random delay added to every interval
+//! so that instances started together do not do their work at the same moment, and keeps the
+//! outcome of its last run for the admin API and the readiness probe. when several instances
+//! share a database, only the one holding the jobs [`Lease`] runs them
+use crate::data::blob::AppBlobStore;
+use crate::data::repository::AppRepository;
+use crate::domain::health::{ComponentHealth, Heartbeat};
+use crate::domain::lease::Lease;
+use crate::domain::retention::RetentionConfig;
+use crate::metrics::AppMetrics;
+use crate::service;
+use crate::service::audit::Audit;
+use crate::ServiceError;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+use tokio::runtime::Handle;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Deserialize,
+    Serialize,
+    strum::Display,
+    strum::EnumString,
+    strum::AsRefStr,
+    strum::EnumIter,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum JobName {
+    /// moves expired clips to the trash
+    ExpiredCleanup,
+    /// deletes clips whose time in the trash is over
+    TrashPurge,
+    /// applies the retention policies, when any are set
+    Retention,
+    /// reclaims database space and refreshes planner statistics
+    Optimize,
+    /// deletes blobs no clip references
+    OrphanedBlobs,
+    /// deletes API keys whose lifetime is over
+    ApiKeyExpiry,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// when a job runs. a job configured in the `[jobs]` section replaces its default schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Schedule {
+    /// seconds between runs
+    pub interval: u64,
+    /// up to this many seconds are added to each interval at random
+    #[serde(default)]
+    pub jitter: u64,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+impl Schedule {
+    pub const fn every(interval: u64, jitter: u64) -> Self {
+        Self {
+            interval,
+            jitter,
+            enabled: true,
+        }
+    }
+
+    /// how long to wait for the next run
+    fn next_delay(&self) -> Duration {
+        let jitter = rand::thread_rng().gen_range(0..=self.jitter);
+        Duration::from_secs(self.interval.saturating_add(jitter))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct JobsConfig {
+    /// seconds the lease on running the jobs lasts. when its holder stops renewing it, another
+    /// instance takes over once it lapsed
+    pub lease: u64,
+    pub expired_cleanup: Schedule,
+    pub trash_purge: Schedule,
+    pub retention: Schedule,
+    pub optimize: Schedule,
+    pub orphaned_blobs: Schedule,
+    pub api_key_expiry: Schedule,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            lease: 30,
+            expired_cleanup: Schedule::every(10, 0),
+            trash_purge: Schedule::every(10 * 60, 60),
+            retention: Schedule::every(60 * 60, 5 * 60),
+            optimize: Schedule::every(24 * 60 * 60, 60 * 60),
+            orphaned_blobs: Schedule::every(24 * 60 * 60, 60 * 60),
+            api_key_expiry: Schedule::every(60 * 60, 5 * 60),
+        }
+    }
+}
+
+impl JobsConfig {
+    pub fn lease(&self) -> Duration {
+        Duration::from_secs(self.lease)
+    }
+
+    pub fn schedule(&self, job: JobName) -> Schedule {
+        match job {
+            JobName::ExpiredCleanup => self.expired_cleanup,
+            JobName::TrashPurge => self.trash_purge,
+            JobName::Retention => self.retention,
+            JobName::Optimize => self.optimize,
+            JobName::OrphanedBlobs => self.orphaned_blobs,
+            JobName::ApiKeyExpiry => self.api_key_expiry,
+        }
+    }
+}
+
+/// a job as reported by the admin API
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JobStatus {
+    pub name: JobName,
+    /// whether the job runs on its schedule. disabled jobs can still be run by hand
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub jitter_secs: u64,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    /// when the last run started
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    /// error of the last run, when it failed
+    pub last_error: Option<String>,
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error("job {0} is already running")]
+    Running(JobName),
+
+    #[error("another instance runs the jobs")]
+    Standby,
+}
+
+/// what the jobs work on
+pub struct JobContext {
+    pub repository: AppRepository,
+    pub blobs: AppBlobStore,
+    pub metrics: AppMetrics,
+    /// how long clips stay in the trash
+    pub trash_retention: Duration,
+    pub retention: RetentionConfig,
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    runs: u64,
+    failures: u64,
+    last_run: Option<DateTime<Utc>>,
+    last_duration: Option<Duration>,
+    last_error: Option<String>,
+    next_run: Option<DateTime<Utc>>,
+}
+
+struct Job {
+    name: JobName,
+    schedule: Schedule,
+    /// held while the job runs, so scheduled and manual runs never overlap
+    running: tokio::sync::Mutex<()>,
+    state: Mutex<JobState>,
+    heartbeat: Heartbeat,
+}
+
+impl Job {
+    fn new(name: JobName, schedule: Schedule) -> Self {
+        let period = Duration::from_secs(schedule.interval.saturating_add(schedule.jitter));
+        Self {
+            name,
+            schedule,
+            running: tokio::sync::Mutex::new(()),
+            state: Mutex::new(JobState::default()),
+            heartbeat: Heartbeat::new(period),
+        }
+    }
+
+    fn status(&self) -> JobStatus {
+        let state = self.state.lock();
+        JobStatus {
+            name: self.name,
+            enabled: self.schedule.enabled,
+            interval_secs: self.schedule.interval,
+            jitter_secs: self.schedule.jitter,
+            running: self.running.try_lock().is_err(),
+            runs: state.runs,
+            failures: state.failures,
+            last_run: state.last_run,
+            last_duration_ms: state
+                .last_duration
+                .map(|duration| duration.as_millis() as u64),
+            last_error: state.last_error.clone(),
+            next_run: state.next_run,
+        }
+    }
+}
+
+struct Jobs {
+    context: JobContext,
+    jobs: Vec<Job>,
+    lease: Lease,
+    /// blobs found unreferenced by the last run of [`JobName::OrphanedBlobs`]
+    orphan_suspects: Mutex<HashSet<String>>,
+}
+
+impl Jobs {
+    fn job(&self, name: JobName) -> &Job {
+        self.jobs
+            .iter()
+            .find(|job| job.name == name)
+            .expect("every job is registered")
+    }
+
+    /// runs the job now, unless it is already running
+    async fn run(&self, job: &Job) -> Result<(), JobError> {
+        let Ok(_running) = job.running.try_lock() else {
+            return Err(JobError::Running(job.name));
+        };
+        let started = Utc::now();
+        let timer = Instant::now();
+        let result = self
+            .execute(job.name)
+            .instrument(tracing::info_span!("job", name = %job.name))
+            .await;
+        let duration = timer.elapsed();
+
+        let metrics = &self.context.metrics;
+        metrics
+            .job_duration
+            .with_label_values(&[job.name.as_ref()])
+            .observe(duration.as_secs_f64());
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        metrics
+            .job_runs
+            .with_label_values(&[job.name.as_ref(), outcome])
+            .inc();
+
+        let mut state = job.state.lock();
+        state.runs += 1;
+        state.last_run = Some(started);
+        state.last_duration = Some(duration);
+        match result {
+            Ok(()) => {
+                state.last_error = None;
+                job.heartbeat.succeeded();
+            }
+            Err(e) => {
+                tracing::error!(job = %job.name, error = %e, "job failed");
+                state.failures += 1;
+                state.last_error = Some(e.to_string());
+                job.heartbeat.failed(e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, name: JobName) -> Result<(), ServiceError> {
+        let JobContext {
+            repository,
+            blobs,
+            metrics,
+            trash_retention,
+            retention,
+        } = &self.context;
+        match name {
+            JobName::ExpiredCleanup => {
+                let trashed = service::action::trash_expired(repository.as_ref()).await?;
+                if trashed > 0 {
+                    tracing::info!(trashed, "expired clips moved to the trash");
+                }
+                metrics.expired_deleted.inc_by(trashed);
+            }
+            JobName::TrashPurge => {
+                let purged =
+                    service::action::purge_trash(*trash_retention, repository.as_ref()).await?;
+                if purged > 0 {
+                    tracing::info!(purged, "clips purged from the trash");
+                }
+                metrics.trash_purged.inc_by(purged);
+            }
+            JobName::Retention if retention.is_enabled() => {
+                apply_retention(repository, metrics, retention).await?
+            }
+            JobName::Retention => {}
+            JobName::Optimize => service::action::optimize_database(repository.as_ref()).await?,
+            JobName::OrphanedBlobs => {
+                let mut suspects = std::mem::take(&mut *self.orphan_suspects.lock());
+                let deleted = service::action::delete_orphaned_blobs(
+                    &mut suspects,
+                    repository.as_ref(),
+                    blobs.as_ref(),
+                )
+                .await;
+                *self.orphan_suspects.lock() = suspects;
+                match deleted? {
+                    Some(0) => {}
+                    Some(deleted) => tracing::info!(deleted, "orphaned blobs deleted"),
+                    None => tracing::debug!("the blob store cannot list its blobs"),
+                }
+            }
+            JobName::ApiKeyExpiry => {
+                let audit = Audit::system(repository.as_ref());
+                let expired = service::action::expire_api_keys(repository.as_ref(), &audit).await?;
+                if expired > 0 {
+                    tracing::info!(expired, "expired api keys deleted");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// runs every enabled job on its schedule
+pub struct Scheduler {
+    jobs: Arc<Jobs>,
+    stop: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    /// renewing the lease stops only once the jobs did, so no other instance starts a job
+    /// that is still running here
+    stop_lease: watch::Sender<bool>,
+    lease_task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Scheduler {
+    pub fn spawn(context: JobContext, config: &JobsConfig, handle: Handle) -> Self {
+        let jobs = Arc::new(Jobs {
+            context,
+            jobs: JobName::iter()
+                .map(|name| Job::new(name, config.schedule(name)))
+                .collect(),
+            lease: Lease::new("jobs", config.lease()),
+            orphan_suspects: Mutex::new(HashSet::new()),
+        });
+        let (stop, stopped) = watch::channel(false);
+        let (stop_lease, mut lease_stopped) = watch::channel(false);
+
+        let lease_task = {
+            let jobs = jobs.clone();
+            handle.spawn(async move {
+                loop {
+                    jobs.lease.renew(jobs.context.repository.as_ref()).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(jobs.lease.renew_interval()) => {}
+                        _ = lease_stopped.changed() => break,
+                    }
+                }
+                jobs.lease.release(jobs.context.repository.as_ref()).await;
+            })
+        };
+
+        let tasks = jobs
+            .jobs
+            .iter()
+            .filter(|job| job.schedule.enabled)
+            .map(|job| {
+                let name = job.name;
+                let jobs = jobs.clone();
+                let mut stopped = stopped.clone();
+                handle.spawn(async move {
+                    let job = jobs.job(name);
+                    loop {
+                        let delay = job.schedule.next_delay();
+                        job.state.lock().next_run = chrono::Duration::from_std(delay)
+                            .ok()
+                            .and_then(|delay| Utc::now().checked_add_signed(delay));
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {}
+                            _ = stopped.changed() => break,
+                        }
+                        if !jobs.lease.is_held() {
+                            job.heartbeat.beat();
+                            continue;
+                        }
+                        if let Err(e) = jobs.run(job).await {
+                            tracing::debug!(error = %e, "skipping scheduled run");
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs,
+            stop,
+            tasks: Mutex::new(tasks),
+            stop_lease,
+            lease_task: Mutex::new(Some(lease_task)),
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs.jobs.iter().map(Job::status).collect()
+    }
+
+    /// runs a job right away and returns its status once it finished. refused while another
+    /// instance holds the lease
+    pub async fn run_now(&self, name: JobName) -> Result<JobStatus, JobError> {
+        let repository = self.jobs.context.repository.as_ref();
+        if !self.jobs.lease.is_held() && !self.jobs.lease.renew(repository).await {
+            return Err(JobError::Standby);
+        }
+        let job = self.jobs.job(name);
+        self.jobs.run(job).await?;
+        Ok(job.status())
+    }
+
+    /// the health of the enabled job that is worst off. an instance on standby is fine
+    pub fn health(&self) -> ComponentHealth {
+        if !self.jobs.lease.is_held() {
+            return ComponentHealth {
+                message: Some(JobError::Standby.to_string()),
+                ..ComponentHealth::ok()
+            };
+        }
+        self.jobs
+            .jobs
+            .iter()
+            .filter(|job| job.schedule.enabled)
+            .map(|job| {
+                let mut health = job.heartbeat.health();
+                health.message = health
+                    .message
+                    .map(|message| format!("{}: {}", job.name, message));
+                health
+            })
+            .max_by_key(|health| health.status)
+            .unwrap_or_else(ComponentHealth::ok)
+    }
+
+    /// stops scheduling jobs, letting runs that are in progress finish first, then hands the
+    /// lease over
+    pub async fn shutdown(&self) {
+        let _ = self.stop.send(true);
+        let tasks = std::mem::take(&mut *self.tasks.lock());
+        for task in tasks {
+            let _ = task.await;
+        }
+        let _ = self.stop_lease.send(true);
+        let lease_task = self.lease_task.lock().take();
+        if let Some(task) = lease_task {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn apply_retention(
+    repository: &AppRepository,
+    metrics: &AppMetrics,
+    config: &RetentionConfig,
+) -> Result<(), ServiceError> {
+    let audit = Audit::system(repository.as_ref());
+    let removals = service::action::apply_retention(config, repository.as_ref(), &audit).await?;
+    if config.dry_run {
+        for removal in &removals {
+            tracing::info!(
+                short_code = removal.short_code.as_str(),
+                reason = %removal.reason,
+                bytes = removal.size,
+                "retention would remove clip"
+            );
+        }
+        return Ok(());
+    }
+    if !removals.is_empty() {
+        let bytes: u64 = removals.iter().map(|removal| removal.size).sum();
+        tracing::info!(
+            removed = removals.len(),
+            bytes,
+            "clips moved to the trash by retention policies"
+        );
+    }
+    for removal in &removals {
+        metrics
+            .retention_removed
+            .with_label_values(&[removal.reason.as_ref()])
+            .inc();
+    }
+    Ok(())
+}
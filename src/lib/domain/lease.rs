@@ -0,0 +1,68 @@
+//! a lease in the database that lets one instance at a time do some work, when several serve
+//! the same database. the holder renews it well before it lapses; once it stops, because it
+//! shut down or hung, another instance takes over when the lease has lapsed
+use crate::data::repository::LockRepository;
+use crate::service;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+pub struct Lease {
+    name: &'static str,
+    /// identifies this instance as the holder
+    owner: String,
+    duration: Duration,
+    held: AtomicBool,
+}
+
+impl Lease {
+    pub fn new(name: &'static str, duration: Duration) -> Self {
+        Self {
+            name,
+            owner: uuid::Uuid::new_v4().to_string(),
+            duration,
+            held: AtomicBool::new(false),
+        }
+    }
+
+    /// whether this instance held the lease when it was last renewed
+    pub fn is_held(&self) -> bool {
+        self.held.load(Ordering::Acquire)
+    }
+
+    /// how often to renew, leaving room for two failed attempts before the lease lapses
+    pub fn renew_interval(&self) -> Duration {
+        self.duration / 3
+    }
+
+    /// takes the lease, or extends it when this instance holds it. returns whether it does
+    pub async fn renew(&self, locks: &dyn LockRepository) -> bool {
+        let held = match service::action::acquire_lock(self.name, &self.owner, self.duration, locks)
+            .await
+        {
+            Ok(held) => held,
+            Err(e) => {
+                // without the database there is no telling who holds it, so stand back
+                tracing::error!(lease = self.name, error = %e, "failed to renew lease");
+                false
+            }
+        };
+        if self.held.swap(held, Ordering::AcqRel) != held {
+            match held {
+                true => tracing::info!(lease = self.name, "lease acquired"),
+                false => tracing::info!(lease = self.name, "lease held by another instance"),
+            }
+        }
+        held
+    }
+
+    /// gives the lease up, so another instance can take over without waiting for it to lapse
+    pub async fn release(&self, locks: &dyn LockRepository) {
+        if !self.held.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        match service::action::release_lock(self.name, &self.owner, locks).await {
+            Ok(()) => tracing::info!(lease = self.name, "lease released"),
+            Err(e) => tracing::error!(lease = self.name, error = %e, "failed to release lease"),
+        }
+    }
+}
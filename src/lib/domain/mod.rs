@@ -1,5 +1,10 @@
+pub mod audit;
 pub mod clip;
-pub mod maintenance;
+pub mod health;
+pub mod jobs;
+pub mod lease;
+pub mod retention;
+pub mod stats;
 pub mod time;
 
 pub use clip::Clip;
@@ -0,0 +1,231 @@
+//! policies that keep storage bounded beyond the expiry chosen for each clip. the retention job
+//! moves the clips they select to the trash, or only reports them when running dry
+use crate::ShortCode;
+use chrono::{Days, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// which clips go first when storage is over the cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Eviction {
+    /// fewest views first, the oldest among equals
+    #[default]
+    LeastViewed,
+    Oldest,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// only report what would be removed
+    pub dry_run: bool,
+    /// days after which clips without an expiry are removed
+    pub max_age_days: Option<u32>,
+    /// days without a view after which clips are removed
+    pub idle_days: Option<u32>,
+    /// bytes live clips may take together before some are evicted
+    pub max_storage_bytes: Option<u64>,
+    pub eviction: Eviction,
+    /// clips one API key may keep, its oldest beyond that are removed
+    pub key_max_clips: Option<u64>,
+    /// bytes the clips of one API key may take
+    pub key_max_bytes: Option<u64>,
+}
+
+impl RetentionConfig {
+    /// whether any policy is set
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_days.is_some()
+            || self.idle_days.is_some()
+            || self.max_storage_bytes.is_some()
+            || self.key_max_clips.is_some()
+            || self.key_max_bytes.is_some()
+    }
+}
+
+/// what the policies know about a live clip
+#[derive(Debug, Clone)]
+pub struct ClipUsage {
+    pub short_code: ShortCode,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub views: u64,
+    /// bytes of content and attachment
+    pub size: u64,
+    /// id of the API key the clip was created with
+    pub api_key: Option<String>,
+    pub last_viewed: Option<NaiveDate>,
+}
+
+impl ClipUsage {
+    /// the day the clip was last viewed, or created when it never was
+    fn last_active(&self) -> NaiveDate {
+        let created = self.created_at.date();
+        self.last_viewed
+            .map_or(created, |viewed| viewed.max(created))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, strum::Display, strum::AsRefStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Reason {
+    MaxAge,
+    Idle,
+    KeyQuota,
+    StorageCap,
+}
+
+/// a clip selected by a policy
+#[derive(Debug, Clone, Serialize)]
+pub struct Removal {
+    pub short_code: ShortCode,
+    pub reason: Reason,
+    pub size: u64,
+}
+
+impl Removal {
+    fn of(clip: &ClipUsage, reason: Reason) -> Self {
+        Self {
+            short_code: clip.short_code.clone(),
+            reason,
+            size: clip.size,
+        }
+    }
+}
+
+/// the clips the policies remove at `now`. age and idleness are checked first, then the quota
+/// of each API key and last the storage cap, each only counting the clips still kept
+pub fn plan(clips: Vec<ClipUsage>, config: &RetentionConfig, now: NaiveDateTime) -> Vec<Removal> {
+    let mut removals = vec![];
+
+    let max_age = config
+        .max_age_days
+        .and_then(|days| now.checked_sub_days(Days::new(days.into())));
+    let idle_since = config
+        .idle_days
+        .and_then(|days| now.date().checked_sub_days(Days::new(days.into())));
+    let mut kept = vec![];
+    for clip in clips {
+        if clip.expires_at.is_none() && max_age.is_some_and(|max_age| clip.created_at < max_age) {
+            removals.push(Removal::of(&clip, Reason::MaxAge));
+        } else if idle_since.is_some_and(|since| clip.last_active() < since) {
+            removals.push(Removal::of(&clip, Reason::Idle));
+        } else {
+            kept.push(clip);
+        }
+    }
+
+    kept.sort_by_key(|clip| clip.created_at);
+    if config.key_max_clips.is_some() || config.key_max_bytes.is_some() {
+        let mut usage: HashMap<String, (u64, u64)> = HashMap::new();
+        for clip in &kept {
+            if let Some(key) = &clip.api_key {
+                let (count, bytes) = usage.entry(key.clone()).or_default();
+                *count += 1;
+                *bytes += clip.size;
+            }
+        }
+        kept.retain(|clip| {
+            let Some((count, bytes)) = clip.api_key.as_ref().and_then(|key| usage.get_mut(key))
+            else {
+                return true;
+            };
+            let over = config.key_max_clips.is_some_and(|max| *count > max)
+                || config.key_max_bytes.is_some_and(|max| *bytes > max);
+            if over {
+                *count -= 1;
+                *bytes -= clip.size;
+                removals.push(Removal::of(clip, Reason::KeyQuota));
+            }
+            !over
+        });
+    }
+
+    if let Some(cap) = config.max_storage_bytes {
+        let mut total: u64 = kept.iter().map(|clip| clip.size).sum();
+        if config.eviction == Eviction::LeastViewed {
+            // stable, so the oldest go first among clips with as many views
+            kept.sort_by_key(|clip| clip.views);
+        }
+        for clip in &kept {
+            if total <= cap {
+                break;
+            }
+            total -= clip.size;
+            removals.push(Removal::of(clip, Reason::StorageCap));
+        }
+    }
+
+    removals
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn selects_clips_by_policy() {
+        let now = "2024-06-01T12:00:00".parse::<NaiveDateTime>().unwrap();
+        let clip =
+            |short_code: &str, age_days: u64, views: u64, size: u64, key: Option<&str>| ClipUsage {
+                short_code: ShortCode::from(short_code),
+                created_at: now - Days::new(age_days),
+                expires_at: None,
+                views,
+                size,
+                api_key: key.map(str::to_owned),
+                last_viewed: (views > 0).then(|| now.date()),
+            };
+        let clips = vec![
+            clip("ancient", 400, 5, 10, None),
+            clip("idle", 40, 0, 10, None),
+            clip("key-old", 20, 3, 10, Some("k")),
+            clip("key-new", 10, 3, 10, Some("k")),
+            clip("popular", 20, 9, 50, None),
+            clip("quiet", 5, 1, 50, None),
+        ];
+        let selected = |config: &RetentionConfig| {
+            plan(clips.clone(), config, now)
+                .into_iter()
+                .map(|removal| format!("{}:{}", removal.short_code.as_str(), removal.reason))
+                .collect::<Vec<_>>()
+        };
+
+        let mut config = RetentionConfig::default();
+        assert!(!config.is_enabled());
+        assert!(selected(&config).is_empty());
+
+        config.max_age_days = Some(365);
+        config.idle_days = Some(30);
+        assert_eq!(selected(&config), ["ancient:max_age", "idle:idle"]);
+
+        config.key_max_clips = Some(1);
+        assert_eq!(
+            selected(&config),
+            ["ancient:max_age", "idle:idle", "key-old:key_quota"]
+        );
+
+        config.max_storage_bytes = Some(70);
+        assert_eq!(
+            selected(&config),
+            [
+                "ancient:max_age",
+                "idle:idle",
+                "key-old:key_quota",
+                "quiet:storage_cap"
+            ]
+        );
+        config.eviction = Eviction::Oldest;
+        assert_eq!(
+            selected(&config),
+            [
+                "ancient:max_age",
+                "idle:idle",
+                "key-old:key_quota",
+                "popular:storage_cap"
+            ]
+        );
+    }
+}
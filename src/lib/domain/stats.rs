@@ -0,0 +1,354 @@
+//! per-day view statistics. visitors are never stored: a view is reduced to a hash of the client
+//! address and user agent under a salt that is replaced every day, and those hashes only feed a
+//! fixed-size sketch estimating how many distinct visitors a clip had on that day
+use crate::ShortCode;
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// bits of a visitor hash that select a sketch register
+const SKETCH_BITS: u32 = 8;
+pub const SKETCH_SIZE: usize = 1 << SKETCH_BITS;
+
+/// referrers kept per clip and day, less frequent ones are dropped beyond this
+pub const MAX_REFERRERS: usize = 20;
+
+/// user agent fragments of crawlers and link previewers, whose requests are not counted
+const BOT_MARKERS: &[&str] = &[
+    "bot",
+    "crawl",
+    "spider",
+    "slurp",
+    "facebookexternalhit",
+    "embedly",
+    "preview",
+    "headless",
+    "lighthouse",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewSource {
+    /// the clip page
+    Web,
+    /// `/clip/raw`, including attachment downloads
+    Raw,
+    Api,
+}
+
+pub fn is_bot(user_agent: &str) -> bool {
+    let user_agent = user_agent.to_ascii_lowercase();
+    BOT_MARKERS.iter().any(|marker| user_agent.contains(marker))
+}
+
+/// identifies a visitor for the day `salt` belongs to, and only for that day
+pub fn visitor_hash(salt: &[u8], address: &str, user_agent: &str) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(salt)
+        .chain_update(address)
+        .chain_update([0])
+        .chain_update(user_agent)
+        .finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// host of a `Referer` header value, the only part of it that is kept
+pub fn referrer_host(referer: &str) -> Option<String> {
+    let uri = rocket::http::uri::Absolute::parse(referer).ok()?;
+    match uri.scheme() {
+        "http" | "https" => Some(uri.authority()?.host().to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+/// HyperLogLog sketch of visitor hashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisitorSketch(Vec<u8>);
+
+impl Default for VisitorSketch {
+    fn default() -> Self {
+        Self(vec![0; SKETCH_SIZE])
+    }
+}
+
+impl VisitorSketch {
+    /// restores a stored sketch. one of the wrong size is treated as empty
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        match bytes.len() {
+            SKETCH_SIZE => Self(bytes),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn insert(&mut self, hash: u64) {
+        let register = (hash >> (64 - SKETCH_BITS)) as usize;
+        // the marker bit caps the run of zeros at the bits left after the register index
+        let rank = ((hash << SKETCH_BITS) | (1 << (SKETCH_BITS - 1))).leading_zeros() + 1;
+        self.0[register] = self.0[register].max(rank as u8);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (register, other) in self.0.iter_mut().zip(&other.0) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    /// estimated number of distinct hashes inserted, within a few percent
+    pub fn estimate(&self) -> u64 {
+        let size = SKETCH_SIZE as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / size);
+        let sum: f64 = self.0.iter().map(|rank| (-f64::from(*rank)).exp2()).sum();
+        let estimate = alpha * size * size / sum;
+
+        let empty = self.0.iter().filter(|rank| **rank == 0).count();
+        if estimate <= 2.5 * size && empty > 0 {
+            (size * (size / empty as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// views of one clip on one day
+#[derive(Debug, Clone)]
+pub struct DailyViews {
+    pub short_code: ShortCode,
+    pub day: NaiveDate,
+    pub web: u64,
+    pub raw: u64,
+    pub api: u64,
+    pub visitors: VisitorSketch,
+    /// views by referring host
+    pub referrers: HashMap<String, u64>,
+}
+
+impl DailyViews {
+    pub fn new(short_code: ShortCode, day: NaiveDate) -> Self {
+        Self {
+            short_code,
+            day,
+            web: 0,
+            raw: 0,
+            api: 0,
+            visitors: VisitorSketch::default(),
+            referrers: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, source: ViewSource, visitor: Option<u64>, referrer: Option<String>) {
+        match source {
+            ViewSource::Web => self.web += 1,
+            ViewSource::Raw => self.raw += 1,
+            ViewSource::Api => self.api += 1,
+        }
+        if let Some(visitor) = visitor {
+            self.visitors.insert(visitor);
+        }
+        if let Some(referrer) = referrer {
+            *self.referrers.entry(referrer).or_insert(0) += 1;
+            self.trim_referrers();
+        }
+    }
+
+    pub fn merge(&mut self, other: DailyViews) {
+        self.web += other.web;
+        self.raw += other.raw;
+        self.api += other.api;
+        self.visitors.merge(&other.visitors);
+        for (host, views) in other.referrers {
+            *self.referrers.entry(host).or_insert(0) += views;
+        }
+        self.trim_referrers();
+    }
+
+    pub fn total(&self) -> u64 {
+        self.web + self.raw + self.api
+    }
+
+    fn trim_referrers(&mut self) {
+        if self.referrers.len() > MAX_REFERRERS {
+            let mut referrers = self.referrers.drain().collect::<Vec<_>>();
+            referrers.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            referrers.truncate(MAX_REFERRERS);
+            self.referrers = referrers.into_iter().collect();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DayStats {
+    pub day: NaiveDate,
+    pub views: u64,
+    pub web: u64,
+    pub raw: u64,
+    pub api: u64,
+    pub visitors: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Referrer {
+    pub host: String,
+    pub views: u64,
+}
+
+/// views of a clip over a range of days
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipStats {
+    pub short_code: ShortCode,
+    pub views: u64,
+    pub web: u64,
+    pub raw: u64,
+    pub api: u64,
+    /// sum of the daily estimates, so a visitor returning on another day is counted again
+    pub visitors: u64,
+    /// every day of the range, oldest first
+    pub days: Vec<DayStats>,
+    /// most frequent referring hosts first
+    pub referrers: Vec<Referrer>,
+}
+
+impl ClipStats {
+    /// number of referrers listed in the summary
+    pub const TOP_REFERRERS: usize = 10;
+
+    /// most days a summary covers
+    pub const MAX_DAYS: u32 = 366;
+
+    /// first of the `days` days up to and including `until`
+    pub fn first_day(until: NaiveDate, days: u32) -> NaiveDate {
+        let days = days.clamp(1, Self::MAX_DAYS);
+        until
+            .checked_sub_days(Days::new(u64::from(days - 1)))
+            .unwrap_or(NaiveDate::MIN)
+    }
+
+    /// summarizes `daily` over the `days` days up to and including `until`
+    pub fn summarize(
+        short_code: ShortCode,
+        until: NaiveDate,
+        days: u32,
+        daily: Vec<DailyViews>,
+    ) -> Self {
+        let first = Self::first_day(until, days);
+        let mut by_day: HashMap<NaiveDate, DailyViews> =
+            daily.into_iter().map(|views| (views.day, views)).collect();
+
+        let mut referrers: HashMap<String, u64> = HashMap::new();
+        let days = first
+            .iter_days()
+            .take_while(|day| *day <= until)
+            .map(|day| match by_day.remove(&day) {
+                Some(views) => {
+                    for (host, count) in &views.referrers {
+                        *referrers.entry(host.clone()).or_insert(0) += count;
+                    }
+                    DayStats {
+                        day,
+                        views: views.total(),
+                        web: views.web,
+                        raw: views.raw,
+                        api: views.api,
+                        visitors: views.visitors.estimate(),
+                    }
+                }
+                None => DayStats {
+                    day,
+                    views: 0,
+                    web: 0,
+                    raw: 0,
+                    api: 0,
+                    visitors: 0,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let mut referrers = referrers
+            .into_iter()
+            .map(|(host, views)| Referrer { host, views })
+            .collect::<Vec<_>>();
+        referrers.sort_by(|a, b| b.views.cmp(&a.views).then_with(|| a.host.cmp(&b.host)));
+        referrers.truncate(Self::TOP_REFERRERS);
+
+        Self {
+            short_code,
+            views: days.iter().map(|day| day.views).sum(),
+            web: days.iter().map(|day| day.web).sum(),
+            raw: days.iter().map(|day| day.raw).sum(),
+            api: days.iter().map(|day| day.api).sum(),
+            visitors: days.iter().map(|day| day.visitors).sum(),
+            days,
+            referrers,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn estimates_visitors_and_summarizes_days() {
+        let salt = b"salt";
+        let mut sketch = VisitorSketch::default();
+        for visitor in 0..5 {
+            // repeated views by the same visitor are counted once
+            for _ in 0..3 {
+                sketch.insert(visitor_hash(
+                    salt,
+                    &format!("10.0.0.{}", visitor),
+                    "firefox",
+                ));
+            }
+        }
+        assert_eq!(sketch.estimate(), 5);
+
+        let mut large = VisitorSketch::default();
+        for visitor in 0..10_000 {
+            large.insert(visitor_hash(salt, &visitor.to_string(), "firefox"));
+        }
+        let estimate = large.estimate() as f64;
+        assert!((8_500.0..11_500.0).contains(&estimate), "{}", estimate);
+
+        assert!(is_bot("Mozilla/5.0 (compatible; Googlebot/2.1)"));
+        assert!(!is_bot(
+            "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Firefox/120.0"
+        ));
+        assert_eq!(
+            referrer_host("https://News.example.com/item?id=1").as_deref(),
+            Some("news.example.com")
+        );
+        assert_eq!(referrer_host("android-app://com.example"), None);
+
+        let short_code = ShortCode::from("stats");
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let (first, second) = (
+            visitor_hash(salt, "10.0.0.1", "firefox"),
+            visitor_hash(salt, "10.0.0.2", "firefox"),
+        );
+        let mut views = DailyViews::new(short_code.clone(), today);
+        views.record(ViewSource::Web, Some(first), Some("example.com".to_owned()));
+        views.record(ViewSource::Api, Some(first), None);
+        let mut raw = DailyViews::new(short_code.clone(), today);
+        raw.record(
+            ViewSource::Raw,
+            Some(second),
+            Some("example.com".to_owned()),
+        );
+        views.merge(raw);
+
+        let stats = ClipStats::summarize(short_code, today, 7, vec![views]);
+        assert_eq!(stats.days.len(), 7);
+        assert_eq!(stats.days.last().unwrap().day, today);
+        assert_eq!((stats.views, stats.web, stats.raw, stats.api), (3, 1, 1, 1));
+        assert_eq!(stats.visitors, 2);
+        assert_eq!(stats.referrers[0].host, "example.com");
+        assert_eq!(stats.referrers[0].views, 2);
+    }
+}
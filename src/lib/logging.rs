@@ -0,0 +1,92 @@
+// Logging goes through `tracing`. Rocket's and sqlx's `log` records are forwarded into it, so
+// everything ends up in one stream, as text or as one JSON object per line.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// settings for the log output
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// filter directives such as `info,clipshare=debug`. `RUST_LOG` takes precedence
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            filter: "info,sqlx=warn".to_owned(),
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn env_filter(&self) -> Result<EnvFilter, String> {
+        match std::env::var(EnvFilter::DEFAULT_ENV) {
+            Ok(filter) if !filter.trim().is_empty() => EnvFilter::try_new(filter),
+            _ => EnvFilter::try_new(&self.filter),
+        }
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// installs the global subscriber. does nothing when one is installed already
+pub fn init(config: &LogConfig) -> Result<(), String> {
+    let builder = tracing_subscriber::fmt().with_env_filter(config.env_filter()?);
+    let installed = match config.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().flatten_event(true).try_init(),
+    };
+    if let Err(e) = installed {
+        tracing::debug!("log subscriber already installed: {}", e);
+    }
+    Ok(())
+}
+
+/// wraps a secret so it can be part of a log event, or a type deriving `Debug`, without its
+/// value being written out
+#[derive(Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::domain::clip::field::{OwnerKey, Password};
+
+    #[test]
+    fn redacts_secrets() {
+        let password = Password::new("hunter22".to_owned()).unwrap();
+        assert_eq!(format!("{:?}", password), "Password(Some([redacted]))");
+        assert!(!format!("{:?}", OwnerKey::new("owner-secret")).contains("owner-secret"));
+
+        let config = AppConfig {
+            admin_key: Some(Redacted("0123456789abcdef".to_owned())),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", config).contains("0123456789abcdef"));
+    }
+}
@@ -0,0 +1,175 @@
+// Prometheus metrics. Every server has its own registry, so tests and embedders running several
+// servers in one process do not share counters. The web layer serves them at `/metrics`.
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::Arc;
+
+pub type AppMetrics = Arc<Metrics>;
+
+pub struct Metrics {
+    registry: Registry,
+    /// by method, route and status
+    pub http_requests: IntCounterVec,
+    /// seconds spent handling a request, by method and route
+    pub http_duration: HistogramVec,
+    pub clips_created: IntCounter,
+    /// counted views, by source
+    pub clip_views: IntCounterVec,
+    /// wrong passwords for protected clips
+    pub password_failures: IntCounter,
+    /// expired clips moved to the trash
+    pub expired_deleted: IntCounter,
+    /// clips deleted for good once their time in the trash was over
+    pub trash_purged: IntCounter,
+    /// clips moved to the trash by retention policies, by reason
+    pub retention_removed: IntCounterVec,
+    /// runs of background jobs, by job and outcome
+    pub job_runs: IntCounterVec,
+    /// seconds a background job ran, by job
+    pub job_duration: HistogramVec,
+    /// clips written by one flush of the view counts
+    pub views_flush_size: Histogram,
+    pub views_flush_duration: Histogram,
+    /// connections of the database pool, by state
+    pub db_connections: IntGaugeVec,
+    pub db_max_connections: IntGauge,
+    /// bytes of clip content and blobs, by store
+    pub stored_bytes: IntGaugeVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("clipshare".to_owned()), None)
+            .expect("metric prefix is valid");
+
+        let metrics = Self {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests handled"),
+                &["method", "route", "status"],
+            )
+            .expect("valid metric"),
+            http_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "time spent handling HTTP requests",
+                ),
+                &["method", "route"],
+            )
+            .expect("valid metric"),
+            clips_created: IntCounter::new("clips_created_total", "clips created")
+                .expect("valid metric"),
+            clip_views: IntCounterVec::new(
+                Opts::new("clip_views_total", "clip views, without bots"),
+                &["source"],
+            )
+            .expect("valid metric"),
+            password_failures: IntCounter::new(
+                "password_failures_total",
+                "wrong passwords given for protected clips",
+            )
+            .expect("valid metric"),
+            expired_deleted: IntCounter::new(
+                "expired_clips_deleted_total",
+                "expired clips moved to the trash by maintenance",
+            )
+            .expect("valid metric"),
+            trash_purged: IntCounter::new(
+                "trash_purged_total",
+                "clips purged from the trash by maintenance",
+            )
+            .expect("valid metric"),
+            retention_removed: IntCounterVec::new(
+                Opts::new(
+                    "retention_removed_total",
+                    "clips moved to the trash by retention policies",
+                ),
+                &["reason"],
+            )
+            .expect("valid metric"),
+            job_runs: IntCounterVec::new(
+                Opts::new("job_runs_total", "runs of background jobs"),
+                &["job", "outcome"],
+            )
+            .expect("valid metric"),
+            job_duration: HistogramVec::new(
+                HistogramOpts::new("job_duration_seconds", "time background jobs ran"),
+                &["job"],
+            )
+            .expect("valid metric"),
+            views_flush_size: Histogram::with_opts(
+                HistogramOpts::new(
+                    "views_flush_clips",
+                    "clips written per flush of view counts",
+                )
+                .buckets(vec![1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 1000.0]),
+            )
+            .expect("valid metric"),
+            views_flush_duration: Histogram::with_opts(HistogramOpts::new(
+                "views_flush_duration_seconds",
+                "time spent writing view counts",
+            ))
+            .expect("valid metric"),
+            db_connections: IntGaugeVec::new(
+                Opts::new("db_connections", "connections of the database pool"),
+                &["state"],
+            )
+            .expect("valid metric"),
+            db_max_connections: IntGauge::new(
+                "db_max_connections",
+                "most connections the database pool opens",
+            )
+            .expect("valid metric"),
+            stored_bytes: IntGaugeVec::new(
+                Opts::new(
+                    "stored_bytes",
+                    "bytes of stored clip content, the trash included",
+                ),
+                &["store"],
+            )
+            .expect("valid metric"),
+            registry,
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 15] = [
+            Box::new(metrics.http_requests.clone()),
+            Box::new(metrics.http_duration.clone()),
+            Box::new(metrics.clips_created.clone()),
+            Box::new(metrics.clip_views.clone()),
+            Box::new(metrics.password_failures.clone()),
+            Box::new(metrics.expired_deleted.clone()),
+            Box::new(metrics.trash_purged.clone()),
+            Box::new(metrics.retention_removed.clone()),
+            Box::new(metrics.job_runs.clone()),
+            Box::new(metrics.job_duration.clone()),
+            Box::new(metrics.views_flush_size.clone()),
+            Box::new(metrics.views_flush_duration.clone()),
+            Box::new(metrics.db_connections.clone()),
+            Box::new(metrics.db_max_connections.clone()),
+            Box::new(metrics.stored_bytes.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+        metrics
+    }
+
+    /// every metric in the Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics encode to text");
+        String::from_utf8(buffer).expect("text format is UTF-8")
+    }
+}
@@ -1,44 +1,112 @@
+pub mod config;
 pub mod data;
 pub mod domain;
+pub mod logging;
+pub mod metrics;
 pub mod service;
 pub mod web;
 
 pub use data::DataError;
 pub use domain::clip::field::ShortCode;
 pub use domain::clip::{Clip, ClipError};
-use domain::maintenance::Maintenance;
+use domain::jobs::Scheduler;
 pub use domain::time::Time;
 pub use service::ServiceError;
 
-use data::AppDatabase;
-use rocket::fs::FileServer;
-use rocket::{Build, Rocket};
+use config::AppConfig;
+use data::blob::AppBlobStore;
+use data::repository::AppRepository;
+use metrics::AppMetrics;
+use rocket::figment::Figment;
+use rocket::{Build, Phase, Rocket};
+use web::assets::Assets;
 use web::renderer::Renderer;
+use web::trace::traced;
 use web::views::Views;
 
 pub struct RocketConfig {
+    /// Rocket's own settings, usually from [`config::figment`]
+    pub figment: Figment,
+    pub config: AppConfig,
     pub renderer: Renderer<'static>,
-    pub database: AppDatabase,
+    /// served under `/static`, and linked by the templates through `renderer`
+    pub assets: Assets,
+    pub repository: AppRepository,
+    pub blobs: AppBlobStore,
+    /// shared with `views` and `scheduler`, which record into it
+    pub metrics: AppMetrics,
     pub views: Views,
-    pub maintenance: Maintenance,
+    pub scheduler: Scheduler,
 }
 
-pub fn rocket(config: RocketConfig) -> Rocket<Build> {
-    rocket::build()
-        .manage::<AppDatabase>(config.database)
+pub fn rocket(mut config: RocketConfig) -> Rocket<Build> {
+    config.renderer.set_assets(&config.assets);
+    rocket::custom(config.figment)
+        .manage::<AppConfig>(config.config)
+        .manage::<AppRepository>(config.repository)
+        .manage::<AppBlobStore>(config.blobs)
         .manage::<Renderer>(config.renderer)
+        .manage::<Assets>(config.assets)
+        .manage::<AppMetrics>(config.metrics)
         .manage::<Views>(config.views)
-        .manage::<Maintenance>(config.maintenance)
-        .mount("/", web::http::routes())
-        .mount("/api/clip", web::api::routes())
-        .mount("/static", FileServer::from("static"))
+        .manage::<Scheduler>(config.scheduler)
+        .mount("/", traced(web::http::routes()))
+        .mount("/api/clip", traced(web::api::routes()))
+        .mount("/", traced(web::metrics::routes()))
+        .mount("/", traced(web::health::routes()))
+        .mount("/api/admin", traced(web::audit::routes()))
+        .mount("/api/admin", traced(web::jobs::routes()))
+        .mount("/static", web::assets::routes())
         .register("/", web::http::catcher::catchers())
-        .register("/api/clip", web::api::catcher::catchers())
+        .register("/api", web::api::catcher::catchers())
+        .attach(web::trace::RequestTracing)
+        .attach(web::metrics::RequestMetrics)
+}
+
+/// stops the background jobs, commits buffered views and closes the database, within
+/// `shutdown_timeout`. to be called once `launch` returned: Rocket's shutdown fairings run while
+/// requests may still be served during its grace period, and those need the database and count
+/// views
+pub async fn shutdown<P: Phase>(rocket: &Rocket<P>) {
+    let (Some(config), Some(scheduler), Some(views), Some(repository)) = (
+        rocket.state::<AppConfig>(),
+        rocket.state::<Scheduler>(),
+        rocket.state::<Views>(),
+        rocket.state::<AppRepository>(),
+    ) else {
+        return;
+    };
+
+    let deadline = tokio::time::Instant::now() + config.shutdown_timeout();
+    let steps = async {
+        tracing::info!("shutdown: stopping background jobs");
+        scheduler.shutdown().await;
+        tracing::info!("shutdown: committing buffered views");
+        views.shutdown().await;
+        tracing::info!("shutdown: closing database connections");
+        repository.close().await;
+    };
+    match tokio::time::timeout_at(deadline, steps).await {
+        Ok(()) => tracing::info!("shutdown: complete"),
+        Err(_) => tracing::error!(
+            timeout = config.shutdown_timeout,
+            "shutdown: gave up, buffered work may be lost"
+        ),
+    }
 }
 
 #[cfg(test)]
 pub mod test {
+    use std::sync::OnceLock;
+
     pub fn async_runtime() -> tokio::runtime::Runtime {
         tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime")
     }
+
+    /// runtime shared by the background tasks of test servers. it is never dropped, so tasks
+    /// holding a pooled connection are not cancelled halfway through a query
+    pub fn background_runtime() -> &'static tokio::runtime::Runtime {
+        static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(async_runtime)
+    }
 }
@@ -1,63 +1,501 @@
-use crate::data::{query, DatabasePool, Transaction};
+use crate::config::ClipConfig;
+use crate::data::blob::BlobStore;
+use crate::data::model;
+use crate::data::model::RevocationStatus;
+use crate::data::repository::{
+    ApiKeyRepository, AuditRepository, ClipRepository, LockRepository, Repository, StatsRepository,
+};
+use crate::data::DataError;
+use crate::domain::audit::{AuditEntry, AuditEvent, AuditFilter, Outcome};
+use crate::domain::clip::field::OwnerKey;
+use crate::domain::retention::{self, Removal, RetentionConfig};
+use crate::domain::stats::{ClipStats, DailyViews};
 use crate::service::ask;
+use crate::service::audit::Audit;
 use crate::web::api::ApiKey;
-use crate::{Clip, ServiceError, ShortCode};
+use crate::{Clip, ClipError, ServiceError, ShortCode};
+use std::collections::HashSet;
 use std::convert::TryInto;
+use std::time::Duration;
 
-pub async fn begin_transaction(pool: &DatabasePool) -> Result<Transaction<'_>, ServiceError> {
-    Ok(pool.begin().await?)
+#[tracing::instrument(skip_all)]
+pub async fn increase_views(
+    views: &[(ShortCode, u32)],
+    clips: &dyn ClipRepository,
+) -> Result<(), ServiceError> {
+    Ok(clips.increase_views(views).await?)
 }
 
-pub async fn end_transaction(transaction: Transaction<'_>) -> Result<(), ServiceError> {
-    Ok(transaction.commit().await?)
+#[tracing::instrument(skip_all)]
+pub async fn record_view_stats(
+    stats: Vec<DailyViews>,
+    repository: &dyn StatsRepository,
+) -> Result<(), ServiceError> {
+    let stats = stats.into_iter().map(model::ViewStats::from).collect();
+    Ok(repository.record_view_stats(stats).await?)
 }
 
-pub async fn increase_views(
-    short_code: &ShortCode,
-    views: u32,
-    pool: &DatabasePool,
-) -> Result<(), ServiceError> {
-    Ok(query::increase_views(short_code, views, pool).await?)
+#[tracing::instrument(skip_all)]
+pub async fn view_salt(
+    day: chrono::NaiveDate,
+    repository: &dyn StatsRepository,
+) -> Result<Vec<u8>, ServiceError> {
+    Ok(repository.view_salt(day).await?)
+}
+
+/// statistics of the `days` days up to today
+async fn summarize_stats(
+    short_code: ShortCode,
+    days: u32,
+    repository: &dyn StatsRepository,
+) -> Result<ClipStats, ServiceError> {
+    let today = chrono::Utc::now().date_naive();
+    let since = ClipStats::first_day(today, days);
+    let daily = repository
+        .view_stats(&short_code, since)
+        .await?
+        .into_iter()
+        .map(DailyViews::from)
+        .collect();
+    Ok(ClipStats::summarize(short_code, today, days, daily))
+}
+
+/// view statistics for the creator of the clip only: whoever presents its `owner` key, or the
+/// API key with the id `api_key` it was created with
+#[tracing::instrument(skip_all, fields(short_code = short_code.as_str()))]
+pub async fn clip_stats(
+    short_code: ShortCode,
+    owner: Option<&OwnerKey>,
+    api_key: Option<&str>,
+    days: u32,
+    clips: &dyn ClipRepository,
+    stats: &dyn StatsRepository,
+) -> Result<ClipStats, ServiceError> {
+    let clip = clips.get_clip(&short_code).await?;
+    if !clip.is_managed_by(owner, api_key) {
+        return Err(ServiceError::PermissionError(
+            "Only the creator of a clip can see its statistics".to_owned(),
+        ));
+    }
+    summarize_stats(short_code, days, stats).await
+}
+
+/// loads content that was moved to the blob store before converting into a domain clip
+async fn into_clip(clip: model::Clip, blobs: &dyn BlobStore) -> Result<Clip, ServiceError> {
+    let clip = match clip.content_blob() {
+        Some(key) => {
+            let content = blobs.get(key).await?;
+            clip.with_content(String::from_utf8_lossy(&content).into_owned())
+        }
+        None => clip,
+    };
+    Ok(clip.try_into()?)
 }
 
-pub async fn new_clip(req: ask::NewClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    Ok(query::new_clip(req, pool).await?.try_into()?)
+#[tracing::instrument(skip_all)]
+pub async fn new_clip(
+    req: ask::NewClip,
+    clips: &dyn ClipRepository,
+    blobs: &dyn BlobStore,
+    config: &ClipConfig,
+    audit: &Audit<'_>,
+) -> Result<Clip, ServiceError> {
+    let mut model = model::NewClip::with_config(req, config);
+    for blob in model.take_pending_blobs() {
+        blobs.put(&blob.key, blob.data).await?;
+    }
+    let clip = into_clip(clips.new_clip(model).await?, blobs).await?;
+    audit
+        .record(
+            AuditEvent::ClipCreated,
+            Some(&clip.short_code),
+            Outcome::Success,
+            None,
+        )
+        .await;
+    Ok(clip)
 }
 
-pub async fn update_clip(req: ask::UpdateClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    Ok(query::update_clip(req, pool).await?.try_into()?)
+#[tracing::instrument(skip_all, fields(short_code = req.short_code.as_str()))]
+pub async fn update_clip(
+    req: ask::UpdateClip,
+    clips: &dyn ClipRepository,
+    blobs: &dyn BlobStore,
+    config: &ClipConfig,
+    audit: &Audit<'_>,
+) -> Result<Clip, ServiceError> {
+    let mut model = model::UpdateClip::with_config(req, config);
+    for blob in model.take_pending_blobs() {
+        blobs.put(&blob.key, blob.data).await?;
+    }
+    // a blob it no longer references is left to the `orphaned_blobs` job, as another clip may
+    // just have stored the same content under the same key
+    let clip = into_clip(clips.update_clip(model).await?, blobs).await?;
+    audit
+        .record(
+            AuditEvent::ClipUpdated,
+            Some(&clip.short_code),
+            Outcome::Success,
+            None,
+        )
+        .await;
+    Ok(clip)
 }
 
-pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    let user_password = req.password.clone();
-    let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
-    if clip.password.has_password() {
-        if clip.password == user_password {
-            Ok(clip)
-        } else {
-            Err(ServiceError::PermissionError("Invalid password".to_owned()))
+#[tracing::instrument(skip_all, fields(short_code = req.short_code.as_str()))]
+pub async fn get_clip(
+    req: ask::GetClip,
+    clips: &dyn ClipRepository,
+    blobs: &dyn BlobStore,
+    audit: &Audit<'_>,
+) -> Result<Clip, ServiceError> {
+    let clip = clips.get_clip(&req.short_code).await?;
+    // checked before the content is loaded from the blob store, which may be slow and large
+    if !clip.is_unlocked_by(&req.password) {
+        // asking without a password is how a visitor learns one is needed, not a failure
+        if req.password.has_password() {
+            audit
+                .record(
+                    AuditEvent::PasswordFailed,
+                    Some(&req.short_code),
+                    Outcome::Failure,
+                    None,
+                )
+                .await;
+        }
+        return Err(ServiceError::PermissionError("Invalid password".to_owned()));
+    }
+
+    let protected = clip.has_password();
+    let clip = into_clip(clip, blobs).await?;
+    if protected {
+        audit
+            .record(
+                AuditEvent::ClipUnlocked,
+                Some(&clip.short_code),
+                Outcome::Success,
+                None,
+            )
+            .await;
+    }
+    Ok(clip)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_attachment(clip: &Clip, blobs: &dyn BlobStore) -> Result<Vec<u8>, ServiceError> {
+    match clip.attachment.as_ref() {
+        Some(attachment) => Ok(blobs.get(&attachment.sha256).await?),
+        None => Err(ServiceError::NotFound),
+    }
+}
+
+/// moves a clip to the trash for whoever created it. it is purged once the trash retention is
+/// over, and the blobs only it referenced are then left to the `orphaned_blobs` job
+#[tracing::instrument(skip_all, fields(short_code = req.short_code.as_str()))]
+pub async fn delete_clip(
+    req: ask::DeleteClip,
+    clips: &dyn ClipRepository,
+    audit: &Audit<'_>,
+) -> Result<(), ServiceError> {
+    let clip = clips.get_clip(&req.short_code).await?;
+    if !clip.is_managed_by(req.owner.as_ref(), req.api_key.as_deref()) {
+        audit
+            .record(
+                AuditEvent::ClipDeleted,
+                Some(&req.short_code),
+                Outcome::Failure,
+                None,
+            )
+            .await;
+        return Err(ServiceError::PermissionError(
+            "Only the creator of a clip can delete it".to_owned(),
+        ));
+    }
+    clips.trash_clip(&req.short_code).await?;
+    audit
+        .record(
+            AuditEvent::ClipDeleted,
+            Some(&req.short_code),
+            Outcome::Success,
+            None,
+        )
+        .await;
+    Ok(())
+}
+
+/// takes a clip out of the trash for its creator, within `retention` of its deletion. it keeps
+/// its expiry unless `req` sets a new one, which a clip that already expired needs
+#[tracing::instrument(skip_all, fields(short_code = req.short_code.as_str()))]
+pub async fn restore_clip(
+    req: ask::RestoreClip,
+    retention: Duration,
+    clips: &dyn ClipRepository,
+    blobs: &dyn BlobStore,
+    audit: &Audit<'_>,
+) -> Result<Clip, ServiceError> {
+    let clip = clips.get_trashed_clip(&req.short_code).await?;
+    // past the window it is only waiting for the next purge
+    if clip.trashed_before(trash_cutoff(retention)) {
+        return Err(ServiceError::NotFound);
+    }
+    if !clip.is_managed_by(req.owner.as_ref(), req.api_key.as_deref()) {
+        audit
+            .record(
+                AuditEvent::ClipRestored,
+                Some(&req.short_code),
+                Outcome::Failure,
+                None,
+            )
+            .await;
+        return Err(ServiceError::PermissionError(
+            "Only the creator of a clip can restore it".to_owned(),
+        ));
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = req
+        .expires_at
+        .into_inner()
+        .map(|time| time.into_inner().naive_utc());
+    match expires_at {
+        Some(expires_at) if expires_at <= now => {
+            return Err(ClipError::InvalidDate("the new expiry is in the past".to_owned()).into())
         }
-    } else {
-        Ok(clip)
+        None if clip.expired_before(now) => {
+            return Err(ClipError::InvalidDate(
+                "the clip has expired, restoring it needs a new expiry".to_owned(),
+            )
+            .into())
+        }
+        _ => {}
     }
+
+    let clip = clips.restore_clip(&req.short_code, expires_at).await?;
+    audit
+        .record(
+            AuditEvent::ClipRestored,
+            Some(&req.short_code),
+            Outcome::Success,
+            None,
+        )
+        .await;
+    into_clip(clip, blobs).await
 }
 
-pub async fn generate_api_key(pool: &DatabasePool) -> Result<ApiKey, ServiceError> {
-    let api_key = ApiKey::default();
-    Ok(query::generate_api_key(api_key, pool).await?)
+/// creates a key that is valid for `lifetime`, or until it is revoked without one
+#[tracing::instrument(skip_all)]
+pub async fn generate_api_key(
+    lifetime: Option<Duration>,
+    api_keys: &dyn ApiKeyRepository,
+    audit: &Audit<'_>,
+) -> Result<ApiKey, ServiceError> {
+    let expires_at = lifetime.map(|lifetime| {
+        let now = chrono::Utc::now().naive_utc();
+        chrono::Duration::from_std(lifetime)
+            .ok()
+            .and_then(|lifetime| now.checked_add_signed(lifetime))
+            .unwrap_or(chrono::NaiveDateTime::MAX)
+    });
+    let api_key = api_keys.save_api_key(ApiKey::default(), expires_at).await?;
+    audit
+        .record(
+            AuditEvent::ApiKeyCreated,
+            None,
+            Outcome::Success,
+            Some(format!("key {}", api_key.id())),
+        )
+        .await;
+    Ok(api_key)
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn revoke_api_key(
     api_key: ApiKey,
-    pool: &DatabasePool,
-) -> Result<query::RevocationStatus, ServiceError> {
-    Ok(query::revoke_api_key(api_key, pool).await?)
+    api_keys: &dyn ApiKeyRepository,
+    audit: &Audit<'_>,
+) -> Result<RevocationStatus, ServiceError> {
+    let id = api_key.id();
+    let status = api_keys.revoke_api_key(api_key).await?;
+    let outcome = match status {
+        RevocationStatus::Revoked => Outcome::Success,
+        RevocationStatus::NotFound => Outcome::Failure,
+    };
+    audit
+        .record(
+            AuditEvent::ApiKeyRevoked,
+            None,
+            outcome,
+            Some(format!("key {}", id)),
+        )
+        .await;
+    Ok(status)
+}
+
+/// deletes the keys whose lifetime is over, recording each. returns how many were deleted
+#[tracing::instrument(skip_all)]
+pub async fn expire_api_keys(
+    api_keys: &dyn ApiKeyRepository,
+    audit: &Audit<'_>,
+) -> Result<u64, ServiceError> {
+    let expired = api_keys.delete_expired_api_keys().await?;
+    for api_key in &expired {
+        audit
+            .record(
+                AuditEvent::ApiKeyExpired,
+                None,
+                Outcome::Success,
+                Some(format!("key {}", api_key.id())),
+            )
+            .await;
+    }
+    Ok(expired.len() as u64)
+}
+
+/// checks a key presented by a client, recording its use
+#[tracing::instrument(skip_all)]
+pub async fn api_key_is_valid(
+    api_key: ApiKey,
+    api_keys: &dyn ApiKeyRepository,
+    audit: &Audit<'_>,
+) -> Result<bool, ServiceError> {
+    let valid = api_keys.api_key_is_valid(api_key).await?;
+    let outcome = match valid {
+        true => Outcome::Success,
+        false => Outcome::Failure,
+    };
+    audit
+        .record(AuditEvent::ApiKeyUsed, None, outcome, None)
+        .await;
+    Ok(valid)
 }
 
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool, ServiceError> {
-    Ok(query::api_key_is_valid(api_key, pool).await?)
+/// entries of the audit log matching `filter`, oldest first
+#[tracing::instrument(skip_all)]
+pub async fn audit_log(
+    filter: &AuditFilter,
+    log: &dyn AuditRepository,
+) -> Result<Vec<AuditEntry>, ServiceError> {
+    Ok(log
+        .audit_log(filter)
+        .await?
+        .into_iter()
+        .map(AuditEntry::from)
+        .collect())
+}
+
+/// moves expired clips to the trash, returning how many there were
+#[tracing::instrument(skip_all)]
+pub async fn trash_expired(clips: &dyn ClipRepository) -> Result<u64, ServiceError> {
+    Ok(clips.trash_expired().await?)
+}
+
+/// the moment before which trashed clips can no longer be restored
+fn trash_cutoff(retention: Duration) -> chrono::NaiveDateTime {
+    let now = chrono::Utc::now().naive_utc();
+    chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| now.checked_sub_signed(retention))
+        .unwrap_or(chrono::NaiveDateTime::MIN)
+}
+
+/// runs the retention policies and moves the clips they select to the trash, unless the config
+/// asks for a dry run. returns what was, or would have been, removed
+#[tracing::instrument(skip_all, fields(dry_run = config.dry_run))]
+pub async fn apply_retention(
+    config: &RetentionConfig,
+    clips: &dyn ClipRepository,
+    audit: &Audit<'_>,
+) -> Result<Vec<Removal>, ServiceError> {
+    let usage = clips.clip_usage().await?.into_iter().map(From::from);
+    let removals = retention::plan(usage.collect(), config, chrono::Utc::now().naive_utc());
+    if config.dry_run {
+        return Ok(removals);
+    }
+
+    let mut removed = Vec::with_capacity(removals.len());
+    for removal in removals {
+        match clips.trash_clip(&removal.short_code).await {
+            Ok(()) => {}
+            // deleted since its usage was read
+            Err(DataError::NotFound) => continue,
+            Err(e) => return Err(e.into()),
+        }
+        audit
+            .record(
+                AuditEvent::ClipDeleted,
+                Some(&removal.short_code),
+                Outcome::Success,
+                Some(format!("retention: {}", removal.reason)),
+            )
+            .await;
+        removed.push(removal);
+    }
+    Ok(removed)
+}
+
+/// deletes clips that have been in the trash for longer than `retention` for good. the blobs
+/// only they referenced are left to the `orphaned_blobs` job, which makes sure no new clip
+/// took them over. returns how many were purged
+#[tracing::instrument(skip_all)]
+pub async fn purge_trash(
+    retention: Duration,
+    clips: &dyn ClipRepository,
+) -> Result<u64, ServiceError> {
+    let purged = clips.purge_trash(trash_cutoff(retention)).await?;
+    Ok(purged.len() as u64)
+}
+
+/// deletes blobs that no clip references. new clips store their blobs before the clip itself,
+/// so a blob is only deleted once it was also found unreferenced by the previous run, whose
+/// findings `suspects` carries over. `None` when the store cannot list its blobs
+#[tracing::instrument(skip_all)]
+pub async fn delete_orphaned_blobs(
+    suspects: &mut HashSet<String>,
+    clips: &dyn ClipRepository,
+    blobs: &dyn BlobStore,
+) -> Result<Option<u64>, ServiceError> {
+    let Some(keys) = blobs.keys().await? else {
+        return Ok(None);
+    };
+    let mut unreferenced = HashSet::new();
+    for key in keys {
+        if !clips.blob_is_referenced(&key).await? {
+            unreferenced.insert(key);
+        }
+    }
+
+    let mut deleted = 0;
+    for key in unreferenced.intersection(suspects) {
+        blobs.delete(key).await?;
+        deleted += 1;
+    }
+    unreferenced.retain(|key| !suspects.contains(key));
+    *suspects = unreferenced;
+    Ok(Some(deleted))
+}
+
+/// takes or renews the lock `name` for `owner`. false while another owner holds it
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn acquire_lock(
+    name: &str,
+    owner: &str,
+    lease: Duration,
+    locks: &dyn LockRepository,
+) -> Result<bool, ServiceError> {
+    Ok(locks.acquire_lock(name, owner, lease).await?)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn release_lock(
+    name: &str,
+    owner: &str,
+    locks: &dyn LockRepository,
+) -> Result<(), ServiceError> {
+    Ok(locks.release_lock(name, owner).await?)
 }
 
-pub async fn delete_expires(pool: &DatabasePool) -> Result<u64, ServiceError> {
-    Ok(query::delete_expired(pool).await?)
+/// lets the database reclaim space and refresh its statistics
+#[tracing::instrument(skip_all)]
+pub async fn optimize_database(repository: &dyn Repository) -> Result<(), ServiceError> {
+    Ok(repository.optimize().await?)
 }
@@ -8,6 +8,22 @@ pub struct NewClip {
     pub title: field::Title,
     pub exprires_at: field::ExpiresAt,
     pub password: field::Password,
+    #[serde(skip)]
+    pub attachment: Option<Upload>,
+    /// set when the creator is handed an owner key, as on the web form
+    #[serde(skip)]
+    pub owner: Option<field::OwnerKey>,
+    /// id of the API key creating the clip, counted against its quota
+    #[serde(skip)]
+    pub api_key: Option<String>,
+}
+
+/// a file uploaded through a multipart form, stored alongside the clip it belongs to
+#[derive(Debug)]
+pub struct Upload {
+    pub file_name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -48,3 +64,22 @@ impl From<&str> for GetClip {
         Self::from_raw(short_code)
     }
 }
+
+/// moves a clip to the trash, proven to be its creator by the owner key of the web form or the
+/// id of the API key it was created with
+#[derive(Debug)]
+pub struct DeleteClip {
+    pub short_code: ShortCode,
+    pub owner: Option<field::OwnerKey>,
+    pub api_key: Option<String>,
+}
+
+/// takes a clip out of the trash, proven to be its creator like for [`DeleteClip`]. a clip that
+/// expired needs a new `expires_at`
+#[derive(Debug)]
+pub struct RestoreClip {
+    pub short_code: ShortCode,
+    pub owner: Option<field::OwnerKey>,
+    pub api_key: Option<String>,
+    pub expires_at: field::ExpiresAt,
+}
@@ -0,0 +1,56 @@
+use crate::data::model;
+use crate::data::repository::AuditRepository;
+use crate::domain::audit::{Actor, AuditEvent, Outcome};
+use crate::ShortCode;
+
+/// who an action is performed for, and where its audit entries go
+pub struct Audit<'a> {
+    actor: Actor,
+    log: &'a dyn AuditRepository,
+}
+
+impl<'a> Audit<'a> {
+    pub fn new(actor: Actor, log: &'a dyn AuditRepository) -> Self {
+        Self { actor, log }
+    }
+
+    /// for work the server does on its own
+    pub fn system(log: &'a dyn AuditRepository) -> Self {
+        Self::new(Actor::System, log)
+    }
+
+    pub fn actor(&self) -> &Actor {
+        &self.actor
+    }
+
+    /// appends an entry. when that fails the error is logged and the action goes ahead, so an
+    /// unavailable log does not take the service down with it
+    pub async fn record(
+        &self,
+        event: AuditEvent,
+        short_code: Option<&ShortCode>,
+        outcome: Outcome,
+        detail: Option<String>,
+    ) {
+        let salt = match self.actor.needs_salt() {
+            true => match self.log.audit_salt().await {
+                Ok(salt) => salt,
+                Err(e) => {
+                    tracing::error!(error = %e, %event, "failed to load audit salt");
+                    return;
+                }
+            },
+            false => vec![],
+        };
+        let record = model::NewAuditRecord::new(
+            event,
+            self.actor.to_record(&salt),
+            short_code,
+            outcome,
+            detail,
+        );
+        if let Err(e) = self.log.append_audit(record).await {
+            tracing::error!(error = %e, %event, "failed to write audit log");
+        }
+    }
+}
@@ -1,7 +1,9 @@
 // Service links everything together
 pub mod action;
 pub mod ask;
+pub mod audit;
 
+use crate::data::blob::BlobError;
 use crate::{ClipError, DataError};
 
 #[derive(Debug, thiserror::Error)]
@@ -12,6 +14,9 @@ pub enum ServiceError {
     #[error("database error: {0}")]
     Data(DataError),
 
+    #[error("blob storage error: {0}")]
+    Blob(BlobError),
+
     #[error("not found")]
     NotFound,
 
@@ -19,13 +24,33 @@ pub enum ServiceError {
     PermissionError(String),
 }
 
+impl ServiceError {
+    /// the id of the message shown to users for this error, in `locales/*.ftl`. the details of
+    /// storage errors are for the logs only
+    pub fn message_id(&self) -> &'static str {
+        match self {
+            Self::Clip(e) => e.message_id(),
+            Self::Data(_) | Self::Blob(_) => "error-server-retry",
+            Self::NotFound => "error-clip-not-found",
+            Self::PermissionError(_) => "error-permission-denied",
+        }
+    }
+}
+
 impl From<DataError> for ServiceError {
     fn from(err: DataError) -> Self {
         match err {
-            DataError::Database(data_err) => match data_err {
-                sqlx::Error::RowNotFound => Self::NotFound,
-                other => Self::Data(DataError::Database(other)),
-            },
+            DataError::NotFound => Self::NotFound,
+            other => Self::Data(other),
+        }
+    }
+}
+
+impl From<BlobError> for ServiceError {
+    fn from(err: BlobError) -> Self {
+        match err {
+            BlobError::NotFound(_) => Self::NotFound,
+            other => Self::Blob(other),
         }
     }
 }
@@ -0,0 +1,41 @@
+use crate::config::AppConfig;
+use crate::logging::Redacted;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::State;
+
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+/// request guard for admin endpoints. without a configured `admin_key` they answer 404, as if
+/// they did not exist, and a missing or wrong key is rejected with 401
+pub struct Admin;
+
+/// compares in constant time, so response times do not reveal how much of a key matched
+fn keys_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = match req.guard::<&State<AppConfig>>().await {
+            Outcome::Success(config) => config,
+            _ => return Outcome::Error((Status::InternalServerError, "server error")),
+        };
+        let Some(Redacted(expected)) = config.admin_key.as_ref() else {
+            return Outcome::Error((Status::NotFound, "admin endpoints are disabled"));
+        };
+
+        match req.headers().get_one(ADMIN_KEY_HEADER) {
+            Some(given) if keys_match(given, expected) => Outcome::Success(Admin),
+            _ => Outcome::Error((Status::Unauthorized, "admin key missing or invalid")),
+        }
+    }
+}
@@ -1,20 +1,29 @@
-use crate::data::AppDatabase;
+use crate::config::AppConfig;
+use crate::data::{blob::AppBlobStore, repository::AppRepository};
+use crate::domain::audit::Actor;
+use crate::domain::stats::{ClipStats, ViewSource};
+use crate::logging::Redacted;
+use crate::metrics::AppMetrics;
 use crate::service;
 use crate::service::action;
-use crate::web::{Views, PASSWORD_COOKIE};
+use crate::service::audit::Audit;
+use crate::web::views::Viewer;
+use crate::web::{form, owner_cookie, with_request_id, Views, PASSWORD_COOKIE};
 use crate::ServiceError;
 use base64::{engine::general_purpose, Engine as _};
+use rocket::form::Form;
 use rocket::http::{CookieJar, Status};
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket::serde::json::Json;
 use rocket::Responder;
 use rocket::State;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::str::FromStr;
 
 pub const API_KEY_HEADER: &str = "x-api-key";
 
-#[derive(Responder, Debug, thiserror::Error, Serialize)]
+#[derive(Responder, Debug, Clone, thiserror::Error, Serialize)]
 pub enum ApiKeyError {
     #[error("API key not found")]
     #[response(status = 404, content_type = "json")]
@@ -25,9 +34,15 @@ pub enum ApiKeyError {
     DecodeError(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct ApiKey(Vec<u8>);
 
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ApiKey").field(&Redacted(())).finish()
+    }
+}
+
 impl ApiKey {
     pub fn to_base64(&self) -> String {
         general_purpose::STANDARD.encode(self.0.as_slice())
@@ -36,6 +51,17 @@ impl ApiKey {
     pub fn into_inner(self) -> Vec<u8> {
         self.0
     }
+
+    /// names the key in logs and the audit trail without revealing it
+    pub fn id(&self) -> String {
+        crate::domain::audit::hex_prefix(&Sha256::digest(&self.0))
+    }
+}
+
+impl From<Vec<u8>> for ApiKey {
+    fn from(key: Vec<u8>) -> Self {
+        Self(key)
+    }
 }
 
 impl Default for ApiKey {
@@ -55,91 +81,143 @@ impl FromStr for ApiKey {
     }
 }
 
-#[derive(Responder, Debug, thiserror::Error)]
+#[derive(Debug, thiserror::Error)]
 pub enum ApiError {
     #[error("not found")]
-    #[response(status = 404, content_type = "json")]
     NotFound(Json<String>),
 
     #[error("server error")]
-    #[response(status = 500, content_type = "json")]
     Server(Json<String>),
 
     #[error("client error")]
-    #[response(status = 401, content_type = "json")]
     User(Json<String>),
 
+    #[error("conflict")]
+    Conflict(Json<String>),
+
     #[error("key error")]
-    #[response(status = 400, content_type = "json")]
     KeyError(Json<ApiKeyError>),
 }
 
+impl<'r> rocket::response::Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> rocket::response::Result<'static> {
+        use rocket::response::status::Custom;
+
+        let (status, Json(message)) = match self {
+            Self::NotFound(message) => (Status::NotFound, message),
+            Self::Server(message) => (Status::InternalServerError, message),
+            Self::User(message) => (Status::Unauthorized, message),
+            Self::Conflict(message) => (Status::Conflict, message),
+            Self::KeyError(err) => return Custom(Status::BadRequest, err).respond_to(req),
+        };
+        Custom(status, Json(with_request_id(&message, req))).respond_to(req)
+    }
+}
+
 impl From<ServiceError> for ApiError {
     fn from(err: ServiceError) -> Self {
         match err {
             ServiceError::Clip(c) => Self::User(Json(format!("clip parsing error: {}", c))),
             ServiceError::NotFound => Self::NotFound(Json("entity not found".to_owned())),
-            ServiceError::Data(_) => Self::Server(Json("a server error occurred".to_owned())),
+            ServiceError::Data(_) | ServiceError::Blob(_) => {
+                tracing::error!(error = %err, "service error");
+                Self::Server(Json("a server error occurred".to_owned()))
+            }
             ServiceError::PermissionError(msg) => Self::User(Json(msg)),
         }
     }
 }
 
+impl From<form::UploadError> for ApiError {
+    fn from(err: form::UploadError) -> Self {
+        match err {
+            form::UploadError::Clip(c) => Self::User(Json(format!("clip parsing error: {}", c))),
+            form::UploadError::Io(e) => {
+                tracing::error!(error = %e, "failed to read upload");
+                Self::Server(Json("a server error occurred".to_owned()))
+            }
+        }
+    }
+}
+
+/// what became of the key presented with a request, cached so that it is checked and its use
+/// recorded once however many guards ask for it
+enum KeyCheck {
+    Missing,
+    Malformed(ApiKeyError),
+    Valid(ApiKey),
+    Unknown,
+    Failed,
+}
+
+async fn check_api_key(req: &Request<'_>) -> KeyCheck {
+    let Some(key) = req.headers().get_one(API_KEY_HEADER) else {
+        return KeyCheck::Missing;
+    };
+    let repository = match req.guard::<&State<AppRepository>>().await {
+        Outcome::Success(repository) => repository,
+        _ => return KeyCheck::Failed,
+    };
+    let api_key = match ApiKey::from_str(key) {
+        Ok(key) => key,
+        Err(e) => return KeyCheck::Malformed(e),
+    };
+
+    let audit = Audit::new(Actor::ApiKey(api_key.id()), repository.as_ref());
+    match action::api_key_is_valid(api_key.clone(), repository.as_ref(), &audit).await {
+        Ok(true) => KeyCheck::Valid(api_key),
+        Ok(false) => KeyCheck::Unknown,
+        Err(_) => KeyCheck::Failed,
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for ApiKey {
     type Error = ApiError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        fn server_error() -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((
-                Status::InternalServerError,
-                ApiError::Server(Json("server error".to_string())),
-            ))
-        }
-
-        fn key_error(e: ApiKeyError) -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((Status::BadRequest, ApiError::KeyError(Json(e))))
-        }
+        let key_error =
+            |e: ApiKeyError| Outcome::Error((Status::BadRequest, ApiError::KeyError(Json(e))));
 
-        match req.headers().get_one(API_KEY_HEADER) {
-            None => key_error(ApiKeyError::NotFound("API key not found".to_string())),
-            Some(key) => {
-                let db = match req.guard::<&State<AppDatabase>>().await {
-                    Outcome::Success(db) => db,
-                    _ => return server_error(),
-                };
-
-                let api_key = match ApiKey::from_str(key) {
-                    Ok(key) => key,
-                    Err(e) => return key_error(e),
-                };
-
-                match action::api_key_is_valid(api_key.clone(), db.get_pool()).await {
-                    Ok(valid) if valid => Outcome::Success(api_key),
-                    Ok(valid) if !valid => {
-                        key_error(ApiKeyError::NotFound("API key not found".to_owned()))
-                    }
-                    _ => server_error(),
-                }
+        match req.local_cache_async(check_api_key(req)).await {
+            KeyCheck::Valid(api_key) => Outcome::Success(api_key.clone()),
+            KeyCheck::Missing | KeyCheck::Unknown => {
+                key_error(ApiKeyError::NotFound("API key not found".to_owned()))
             }
+            KeyCheck::Malformed(e) => key_error(e.clone()),
+            KeyCheck::Failed => Outcome::Error((
+                Status::InternalServerError,
+                ApiError::Server(Json("server error".to_string())),
+            )),
         }
     }
 }
 
 #[rocket::get("/key")]
-pub async fn new_api_key(database: &State<AppDatabase>) -> Result<Json<&str>, ApiError> {
-    let api_key = action::generate_api_key(database.get_pool()).await?;
-    println!("Api key: {}", api_key.to_base64());
+pub async fn new_api_key(
+    actor: Actor,
+    repository: &State<AppRepository>,
+    config: &State<AppConfig>,
+) -> Result<Json<&'static str>, ApiError> {
+    let audit = Audit::new(actor, repository.as_ref());
+    let lifetime = config.api_key_lifetime();
+    let api_key = action::generate_api_key(lifetime, repository.as_ref(), &audit).await?;
+    // the one secret that is logged: this is how a new key reaches the operator
+    tracing::info!(api_key = %api_key.to_base64(), "api key generated");
     Ok(Json("Api key generated. See logs for details."))
 }
 
 #[rocket::get("/<short_code>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn get_clip(
     short_code: &str,
-    database: &State<AppDatabase>,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
     cookies: &CookieJar<'_>,
+    viewer: Viewer,
     views: &State<Views>,
-    _api_key: ApiKey,
+    metrics: &State<AppMetrics>,
+    api_key: ApiKey,
 ) -> Result<Json<crate::Clip>, ApiError> {
     use crate::domain::clip::field::Password;
 
@@ -148,70 +226,228 @@ pub async fn get_clip(
         password: cookies
             .get(PASSWORD_COOKIE)
             .map(|cookie| cookie.value())
-            .map(|raw_password| Password::new(raw_password.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
+            .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+            .unwrap_or_default(),
     };
 
-    let clip = action::get_clip(req, database.get_pool()).await?;
-    views.view(short_code.into(), 1);
-    Ok(Json(clip))
+    let has_password = req.password.has_password();
+
+    let audit = Audit::new(Actor::ApiKey(api_key.id()), repository.as_ref());
+    let clip = action::get_clip(req, repository.as_ref(), blobs.as_ref(), &audit).await;
+    if has_password && matches!(clip, Err(ServiceError::PermissionError(_))) {
+        metrics.password_failures.inc();
+    }
+    let clip = clip?;
+    views.view(short_code.into(), ViewSource::Api, viewer).await;
+    Ok(Json(views.with_pending(clip)))
 }
 
-#[rocket::post("/", data = "<req>")]
+/// view statistics of the last `days` days, 30 unless given. only for the creator of the clip:
+/// the API key it was created with, or the owner cookie handed out by the web form
+#[rocket::get("/<short_code>/stats?<days>")]
+pub async fn get_clip_stats(
+    short_code: &str,
+    days: Option<u32>,
+    repository: &State<AppRepository>,
+    cookies: &CookieJar<'_>,
+    api_key: ApiKey,
+) -> Result<Json<ClipStats>, ApiError> {
+    use crate::domain::clip::field::OwnerKey;
+
+    let short_code = crate::ShortCode::from(short_code);
+    let owner = cookies
+        .get(&owner_cookie(&short_code))
+        .map(|cookie| OwnerKey::new(cookie.value()));
+    let days = days.unwrap_or(30).clamp(1, ClipStats::MAX_DAYS);
+
+    let stats = action::clip_stats(
+        short_code,
+        owner.as_ref(),
+        Some(&api_key.id()),
+        days,
+        repository.as_ref(),
+        repository.as_ref(),
+    )
+    .await?;
+    Ok(Json(stats))
+}
+
+#[rocket::post("/", data = "<req>", format = "json")]
 pub async fn new_clip(
     req: Json<service::ask::NewClip>,
-    database: &State<AppDatabase>,
-    _api_key: ApiKey,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    config: &State<AppConfig>,
+    metrics: &State<AppMetrics>,
+    api_key: ApiKey,
+) -> Result<Json<crate::Clip>, ApiError> {
+    let mut req = req.into_inner();
+    req.api_key = Some(api_key.id());
+    let audit = Audit::new(Actor::ApiKey(api_key.id()), repository.as_ref());
+    let clip = action::new_clip(
+        req,
+        repository.as_ref(),
+        blobs.as_ref(),
+        &config.clip,
+        &audit,
+    )
+    .await?;
+    metrics.clips_created.inc();
+    Ok(Json(clip))
+}
+
+#[rocket::post("/", data = "<form>", format = "multipart/form-data")]
+pub async fn upload_clip(
+    form: Form<form::NewClip<'_>>,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    config: &State<AppConfig>,
+    metrics: &State<AppMetrics>,
+    api_key: ApiKey,
 ) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::new_clip(req.into_inner(), database.get_pool()).await?;
+    let mut req = form.into_inner().into_ask().await?;
+    req.api_key = Some(api_key.id());
+    let audit = Audit::new(Actor::ApiKey(api_key.id()), repository.as_ref());
+    let clip = action::new_clip(
+        req,
+        repository.as_ref(),
+        blobs.as_ref(),
+        &config.clip,
+        &audit,
+    )
+    .await?;
+    metrics.clips_created.inc();
     Ok(Json(clip))
 }
 
 #[rocket::put("/", data = "<req>")]
 pub async fn update_clip(
     req: Json<service::ask::UpdateClip>,
-    database: &State<AppDatabase>,
-    _api_key: ApiKey,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    config: &State<AppConfig>,
+    api_key: ApiKey,
+) -> Result<Json<crate::Clip>, ApiError> {
+    let audit = Audit::new(Actor::ApiKey(api_key.id()), repository.as_ref());
+    let clip = action::update_clip(
+        req.into_inner(),
+        repository.as_ref(),
+        blobs.as_ref(),
+        &config.clip,
+        &audit,
+    )
+    .await?;
+    Ok(Json(clip))
+}
+
+/// moves a clip to the trash. only its creator may: the API key it was created with, or the
+/// owner cookie handed out by the web form
+#[rocket::delete("/<short_code>")]
+pub async fn delete_clip(
+    short_code: &str,
+    repository: &State<AppRepository>,
+    cookies: &CookieJar<'_>,
+    api_key: ApiKey,
+) -> Result<Json<&'static str>, ApiError> {
+    use crate::domain::clip::field::OwnerKey;
+
+    let short_code = crate::ShortCode::from(short_code);
+    let req = service::ask::DeleteClip {
+        owner: cookies
+            .get(&owner_cookie(&short_code))
+            .map(|cookie| OwnerKey::new(cookie.value())),
+        api_key: Some(api_key.id()),
+        short_code,
+    };
+
+    let audit = Audit::new(Actor::ApiKey(api_key.id()), repository.as_ref());
+    action::delete_clip(req, repository.as_ref(), &audit).await?;
+    Ok(Json("clip deleted"))
+}
+
+/// takes a deleted or expired clip out of the trash, for its creator like [`delete_clip`]. an
+/// expired clip needs a new `expires_at` date
+#[rocket::post("/<short_code>/restore?<expires_at>")]
+pub async fn restore_clip(
+    short_code: &str,
+    expires_at: Option<&str>,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    config: &State<AppConfig>,
+    cookies: &CookieJar<'_>,
+    api_key: ApiKey,
 ) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::update_clip(req.into_inner(), database.get_pool()).await?;
+    use crate::domain::clip::field::{ExpiresAt, OwnerKey};
+
+    let short_code = crate::ShortCode::from(short_code);
+    let req = service::ask::RestoreClip {
+        owner: cookies
+            .get(&owner_cookie(&short_code))
+            .map(|cookie| OwnerKey::new(cookie.value())),
+        api_key: Some(api_key.id()),
+        expires_at: expires_at
+            .unwrap_or_default()
+            .parse::<ExpiresAt>()
+            .map_err(ServiceError::from)?,
+        short_code,
+    };
+
+    let audit = Audit::new(Actor::ApiKey(api_key.id()), repository.as_ref());
+    let clip = action::restore_clip(
+        req,
+        config.trash_retention(),
+        repository.as_ref(),
+        blobs.as_ref(),
+        &audit,
+    )
+    .await?;
     Ok(Json(clip))
 }
 
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![get_clip, new_clip, update_clip, new_api_key]
+    rocket::routes![
+        get_clip,
+        get_clip_stats,
+        new_clip,
+        upload_clip,
+        update_clip,
+        delete_clip,
+        restore_clip,
+        new_api_key
+    ]
 }
 
 pub mod catcher {
+    use crate::web::with_request_id;
+    use rocket::http::Status;
     use rocket::serde::json::Json;
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
 
     #[catch(default)]
-    fn default(req: &Request) -> Json<&'static str> {
-        eprintln!("general error: {:?}", req);
-        Json("something went wrong...")
+    fn default(status: Status, req: &Request) -> Json<String> {
+        tracing::warn!(status = status.code, "unhandled error");
+        Json(with_request_id("something went wrong...", req))
     }
 
     #[catch(500)]
-    fn internal_error(req: &Request) -> Json<&'static str> {
-        eprintln!("internal error: {:?}", req);
-        Json("internal server error")
+    fn internal_error(req: &Request) -> Json<String> {
+        Json(with_request_id("internal server error", req))
     }
 
     #[catch(404)]
-    fn not_found() -> Json<&'static str> {
-        Json("404")
+    fn not_found(req: &Request) -> Json<String> {
+        Json(with_request_id("404", req))
     }
 
     #[catch(401)]
-    fn request_error() -> Json<&'static str> {
-        Json("request error")
+    fn request_error(req: &Request) -> Json<String> {
+        Json(with_request_id("request error", req))
     }
 
     #[catch(400)]
-    fn missing_api_key() -> Json<&'static str> {
-        Json("API key missing or invalid")
+    fn missing_api_key(req: &Request) -> Json<String> {
+        Json(with_request_id("API key missing or invalid", req))
     }
 
     pub fn catchers() -> Vec<Catcher> {
@@ -224,3 +460,218 @@ pub mod catcher {
         ]
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::{ApiKey, API_KEY_HEADER};
+    use crate::test::background_runtime;
+    use crate::web::owner_cookie;
+    use crate::web::test::each_backend;
+    use rocket::http::{ContentType, Cookie, Header, Status};
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn restores_trashed_clips_for_owners() {
+        each_backend(|backend| {
+            let config = backend.config();
+            let repository = config.repository.clone();
+            let api_key = background_runtime()
+                .block_on(repository.save_api_key(ApiKey::default(), None))
+                .unwrap();
+            let api_key = || Header::new(API_KEY_HEADER, api_key.to_base64());
+            let client = Client::untracked(crate::rocket(config)).unwrap();
+
+            let response = client
+                .post("/")
+                .header(ContentType::Form)
+                .body("content=hello&title=&expires_at=&password=")
+                .dispatch();
+            let location = response.headers().get_one("Location").unwrap().to_owned();
+            let short_code = crate::ShortCode::from(location.trim_start_matches("/clip/"));
+            let owner = owner_cookie(&short_code);
+            let owner_key = response.cookies().get(&owner).unwrap().value().to_owned();
+            let clip = format!("/api/clip/{}", short_code.as_str());
+            let restore = format!("{}/restore", clip);
+
+            let response = client.post(restore.as_str()).header(api_key()).dispatch();
+            assert_eq!(
+                response.status(),
+                Status::NotFound,
+                "live clips are not in the trash"
+            );
+
+            let response = client.delete(clip.as_str()).header(api_key()).dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+            let response = client
+                .delete(clip.as_str())
+                .header(api_key())
+                .cookie(Cookie::new(owner.clone(), owner_key.clone()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let response = client.get(clip.as_str()).header(api_key()).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+            let response = client.get(location.as_str()).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+
+            for cookie in [None, Some("not-the-owner")] {
+                let mut request = client.post(restore.as_str()).header(api_key());
+                if let Some(value) = cookie {
+                    request = request.cookie(Cookie::new(owner.clone(), value));
+                }
+                assert_eq!(request.dispatch().status(), Status::Unauthorized);
+            }
+
+            let response = client
+                .post(restore.as_str())
+                .header(api_key())
+                .cookie(Cookie::new(owner.clone(), owner_key.clone()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let restored: crate::Clip = response.into_json().unwrap();
+            assert_eq!(restored.content.as_str(), "hello");
+            let response = client.get(clip.as_str()).header(api_key()).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        });
+    }
+
+    #[test]
+    fn deletes_clips_for_the_key_that_created_them() {
+        each_backend(|backend| {
+            let config = backend.config();
+            let repository = config.repository.clone();
+            let [creator, other] = [(); 2].map(|_| {
+                background_runtime()
+                    .block_on(repository.save_api_key(ApiKey::default(), None))
+                    .unwrap()
+            });
+            let client = Client::untracked(crate::rocket(config)).unwrap();
+
+            let response = client
+                .post("/api/clip")
+                .header(Header::new(API_KEY_HEADER, creator.to_base64()))
+                .header(ContentType::JSON)
+                .body(r#"{"content":"hello","title":null,"exprires_at":null,"password":null}"#)
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let clip: crate::Clip = response.into_json().unwrap();
+            let clip = format!("/api/clip/{}", clip.short_code.as_str());
+
+            let response = client
+                .delete(clip.as_str())
+                .header(Header::new(API_KEY_HEADER, other.to_base64()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+            let response = client
+                .delete(clip.as_str())
+                .header(Header::new(API_KEY_HEADER, creator.to_base64()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        });
+    }
+
+    #[test]
+    fn shows_stats_to_the_key_that_created_the_clip_only() {
+        each_backend(|backend| {
+            let config = backend.config();
+            let repository = config.repository.clone();
+            let [creator, other] = [(); 2].map(|_| {
+                background_runtime()
+                    .block_on(repository.save_api_key(ApiKey::default(), None))
+                    .unwrap()
+            });
+            let client = Client::untracked(crate::rocket(config)).unwrap();
+
+            let response = client
+                .post("/api/clip")
+                .header(Header::new(API_KEY_HEADER, creator.to_base64()))
+                .header(ContentType::JSON)
+                .body(r#"{"content":"hello","title":null,"exprires_at":null,"password":null}"#)
+                .dispatch();
+            let clip: crate::Clip = response.into_json().unwrap();
+            let stats = format!("/api/clip/{}/stats", clip.short_code.as_str());
+
+            let response = client
+                .get(stats.as_str())
+                .header(Header::new(API_KEY_HEADER, other.to_base64()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+            let response = client
+                .get(stats.as_str())
+                .header(Header::new(API_KEY_HEADER, creator.to_base64()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        });
+    }
+
+    #[test]
+    fn restores_expired_clips_with_a_new_expiry_for_their_key() {
+        use crate::domain::clip::field::{Content, ExpiresAt};
+        use crate::domain::time::Time;
+        use crate::service::{action, ask, audit::Audit};
+        use chrono::{Duration, Utc};
+
+        each_backend(|backend| {
+            let config = backend.config();
+            let repository = config.repository.clone();
+            let blobs = config.blobs.clone();
+            let [creator, other] = [(); 2].map(|_| {
+                background_runtime()
+                    .block_on(repository.save_api_key(ApiKey::default(), None))
+                    .unwrap()
+            });
+            let clip = background_runtime().block_on(async {
+                let req = ask::NewClip {
+                    content: Content::new("expired").unwrap(),
+                    title: Default::default(),
+                    exprires_at: ExpiresAt::new(Time::from(Utc::now() - Duration::days(1))),
+                    password: Default::default(),
+                    attachment: None,
+                    owner: None,
+                    api_key: Some(creator.id()),
+                };
+                let audit = Audit::system(repository.as_ref());
+                let clip = action::new_clip(
+                    req,
+                    repository.as_ref(),
+                    blobs.as_ref(),
+                    &Default::default(),
+                    &audit,
+                )
+                .await
+                .unwrap();
+                assert_eq!(repository.trash_expired().await.unwrap(), 1);
+                clip
+            });
+            let client = Client::untracked(crate::rocket(config)).unwrap();
+            let restore = format!("/api/clip/{}/restore", clip.short_code.as_str());
+            let next_week = (Utc::now() + Duration::days(7)).format("%Y-%m-%d");
+
+            let response = client
+                .post(format!("{}?expires_at={}", restore, next_week))
+                .header(Header::new(API_KEY_HEADER, other.to_base64()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+            let response = client
+                .post(restore.as_str())
+                .header(Header::new(API_KEY_HEADER, creator.to_base64()))
+                .dispatch();
+            assert_eq!(
+                response.status(),
+                Status::Unauthorized,
+                "an expired clip needs a new expiry"
+            );
+
+            let response = client
+                .post(format!("{}?expires_at={}", restore, next_week))
+                .header(Header::new(API_KEY_HEADER, creator.to_base64()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let restored: crate::Clip = response.into_json().unwrap();
+            let expires_at = restored.expires_at.into_inner().unwrap();
+            assert_eq!(
+                expires_at.into_inner().format("%Y-%m-%d").to_string(),
+                next_week.to_string()
+            );
+        });
+    }
+}
@@ -0,0 +1,255 @@
+//! the static files, built into the binary and served under `/static` with their content hash
+//! in the name, so browsers can keep them for good
+
+use rocket::http::{ContentType, Header};
+use rocket::State;
+use rust_embed::RustEmbed;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// the name `theme_css` is served under
+pub const CUSTOM_THEME: &str = "themes/custom.css";
+
+/// how long browsers keep files requested by their hashed name
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// how long browsers keep files requested by their plain name, which may change on redeploy
+const REVALIDATE: &str = "public, max-age=300";
+
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct Embedded;
+
+#[derive(Debug)]
+struct Asset {
+    data: Cow<'static, [u8]>,
+    /// the first hex digits of the SHA-256 of `data`
+    hash: String,
+}
+
+impl Asset {
+    fn new(data: Cow<'static, [u8]>) -> Self {
+        let digest = Sha256::digest(&data);
+        let hash = digest[..5].iter().map(|b| format!("{:02x}", b)).collect();
+        Self { data, hash }
+    }
+}
+
+/// the built-in static files, replaced or added to by those in an override directory
+#[derive(Debug, Default)]
+pub struct Assets {
+    files: HashMap<String, Asset>,
+}
+
+impl Assets {
+    /// the built-in files, then those under `override_dir`, read once
+    pub fn load(override_dir: Option<&Path>) -> std::io::Result<Self> {
+        let mut files = HashMap::new();
+        for path in Embedded::iter() {
+            if let Some(file) = Embedded::get(&path) {
+                files.insert(path.into_owned(), Asset::new(file.data));
+            }
+        }
+        if let Some(dir) = override_dir {
+            for path in walk(dir)? {
+                let name = path
+                    .strip_prefix(dir)
+                    .expect("walked paths are below the directory")
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.insert(name, Asset::new(std::fs::read(&path)?.into()));
+            }
+        }
+        Ok(Self { files })
+    }
+
+    /// the built-in files with those of `static_dir`, and `theme_css` as [`CUSTOM_THEME`]
+    pub fn from_config(config: &crate::config::AppConfig) -> std::io::Result<Self> {
+        let mut assets = Self::load(config.static_dir.as_deref())?;
+        if let Some(theme_css) = &config.theme_css {
+            let css = std::fs::read(theme_css)?;
+            assets
+                .files
+                .insert(CUSTOM_THEME.to_owned(), Asset::new(css.into()));
+        }
+        Ok(assets)
+    }
+
+    /// `/static/` and the hashed name of `path`, or its plain name for an unknown file
+    pub fn url(&self, path: &str) -> String {
+        match self.files.get(path) {
+            Some(asset) => format!("/static/{}", hashed_name(path, &asset.hash)),
+            None => format!("/static/{}", path),
+        }
+    }
+
+    /// the urls of all files by their plain names, for the `asset` helper
+    pub fn urls(&self) -> HashMap<String, String> {
+        self.files
+            .keys()
+            .map(|path| (path.clone(), self.url(path)))
+            .collect()
+    }
+
+    /// the file asked for by its plain or its current hashed name, and whether it was the latter
+    fn get(&self, requested: &str) -> Option<(&Asset, bool)> {
+        if let Some(asset) = self.files.get(requested) {
+            return Some((asset, false));
+        }
+        let (path, hash) = unhashed_name(requested)?;
+        self.files
+            .get(&path)
+            .filter(|asset| asset.hash == hash)
+            .map(|asset| (asset, true))
+    }
+}
+
+fn walk(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(walk(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// `css/site.css` as `css/site.<hash>.css`
+fn hashed_name(path: &str, hash: &str) -> String {
+    let (dir, file) = path
+        .rsplit_once('/')
+        .map_or(("", path), |(dir, file)| (dir, file));
+    let file = match file.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}.{}.{}", stem, hash, extension),
+        _ => format!("{}.{}", file, hash),
+    };
+    match dir {
+        "" => file,
+        dir => format!("{}/{}", dir, file),
+    }
+}
+
+/// the plain name and the hash of a name built by [`hashed_name`]
+fn unhashed_name(path: &str) -> Option<(String, String)> {
+    let (dir, file) = path
+        .rsplit_once('/')
+        .map_or(("", path), |(dir, file)| (dir, file));
+    let (rest, extension) = file.rsplit_once('.')?;
+    let (stem, hash) = match rest.rsplit_once('.') {
+        Some((stem, hash)) => (format!("{}.{}", stem, extension), hash),
+        // a file without an extension, hashed as `name.<hash>`
+        None => (rest.to_owned(), extension),
+    };
+    let path = match dir {
+        "" => stem,
+        dir => format!("{}/{}", dir, stem),
+    };
+    Some((path, hash.to_owned()))
+}
+
+#[derive(rocket::Responder)]
+pub struct AssetResponse {
+    data: Vec<u8>,
+    content_type: ContentType,
+    cache_control: Header<'static>,
+}
+
+#[rocket::get("/<path..>")]
+pub fn asset(path: PathBuf, assets: &State<Assets>) -> Option<AssetResponse> {
+    let requested = path.to_str()?.replace('\\', "/");
+    let (asset, hashed) = assets.get(&requested)?;
+    let content_type = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ContentType::from_extension)
+        .unwrap_or(ContentType::Binary);
+    Some(AssetResponse {
+        data: asset.data.to_vec(),
+        content_type,
+        cache_control: Header::new(
+            "Cache-Control",
+            match hashed {
+                true => IMMUTABLE,
+                false => REVALIDATE,
+            },
+        ),
+    })
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![asset]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn names_files_by_their_hash() {
+        assert_eq!(hashed_name("site.css", "ab12"), "site.ab12.css");
+        assert_eq!(
+            hashed_name("vendor/clipboard.min.js", "ab12"),
+            "vendor/clipboard.min.ab12.js"
+        );
+        assert_eq!(hashed_name("LICENSE", "ab12"), "LICENSE.ab12");
+        for path in ["site.css", "vendor/clipboard.min.js", "LICENSE"] {
+            assert_eq!(
+                unhashed_name(&hashed_name(path, "ab12")),
+                Some((path.to_owned(), "ab12".to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn overrides_built_in_files() {
+        let dir = std::env::temp_dir().join(format!("clipshare-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("extra")).unwrap();
+        std::fs::write(dir.join("clipstash.css"), "body {}").unwrap();
+        std::fs::write(dir.join("extra/brand.svg"), "<svg/>").unwrap();
+
+        let built_in = Assets::load(None).unwrap();
+        let assets = Assets::load(Some(&dir)).unwrap();
+        assert_ne!(built_in.url("clipstash.css"), assets.url("clipstash.css"));
+        assert_eq!(built_in.url("logo.svg"), assets.url("logo.svg"));
+
+        let url = assets.url("extra/brand.svg");
+        let (asset, hashed) = assets.get(url.trim_start_matches("/static/")).unwrap();
+        assert!(hashed);
+        assert_eq!(asset.data.as_ref(), b"<svg/>");
+        assert!(!assets.get("extra/brand.svg").unwrap().1);
+        assert!(assets.get("extra/brand.0000000000.svg").is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn serves_hashed_files_for_good() {
+        use crate::web::test::client;
+        use rocket::http::Status;
+
+        let client = client();
+        let page = client.get("/").dispatch().into_string().unwrap();
+        assert!(!page.contains("https://cdn") && !page.contains("https://unpkg"));
+        let url = Assets::load(None).unwrap().url("base.css");
+        assert!(page.contains(&url));
+
+        let response = client.get(url).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::CSS));
+        assert_eq!(response.headers().get_one("Cache-Control"), Some(IMMUTABLE));
+
+        let response = client.get("/static/base.css").dispatch();
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some(REVALIDATE)
+        );
+        let response = client.get("/static/base.0123456789.css").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
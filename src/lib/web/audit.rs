@@ -0,0 +1,175 @@
+use crate::data::repository::AppRepository;
+use crate::domain::audit::{self, Actor, AuditEntry, AuditFilter};
+use crate::service::action;
+use crate::web::admin::Admin;
+use crate::web::api::{ApiError, ApiKey};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// clients of the API are known by their key once it was found valid, visitors of the pages
+/// by their address, whatever key they send
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Actor {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Outcome::Success(api_key) = req.guard::<ApiKey>().await {
+            return Outcome::Success(Actor::ApiKey(api_key.id()));
+        }
+        Outcome::Success(match req.client_ip() {
+            Some(address) => Actor::Address(address),
+            None => Actor::Anonymous,
+        })
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: Option<&str>) -> Result<Option<T>, ApiError> {
+    value
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ApiError::User(Json(format!("invalid {}: {}", name, value))))
+        })
+        .transpose()
+}
+
+fn parse_time(name: &str, value: Option<&str>) -> Result<Option<chrono::NaiveDateTime>, ApiError> {
+    value
+        .map(|value| {
+            audit::parse_time(value)
+                .ok_or_else(|| ApiError::User(Json(format!("invalid {}: {}", name, value))))
+        })
+        .transpose()
+}
+
+/// entries of the audit log, oldest first. `since` and `until` take RFC 3339 times or dates,
+/// and `after` the id of the last entry of the previous page
+#[rocket::get("/audit?<event>&<actor>&<short_code>&<outcome>&<since>&<until>&<after>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn audit_log(
+    event: Option<&str>,
+    actor: Option<String>,
+    short_code: Option<String>,
+    outcome: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    after: Option<i64>,
+    limit: Option<u32>,
+    repository: &State<AppRepository>,
+    _admin: Admin,
+) -> Result<Json<Vec<AuditEntry>>, ApiError> {
+    let filter = AuditFilter {
+        event: parse("event", event)?,
+        actor,
+        short_code,
+        outcome: parse("outcome", outcome)?,
+        since: parse_time("since", since)?,
+        until: parse_time("until", until)?,
+        after,
+        limit: limit.unwrap_or(100),
+    };
+    Ok(Json(action::audit_log(&filter, repository.as_ref()).await?))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![audit_log]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::domain::audit::AuditEntry;
+    use crate::web::test::{each_backend, ADMIN_KEY};
+    use rocket::http::{ContentType, Header, Status};
+
+    #[test]
+    fn records_and_filters_the_audit_log() {
+        each_backend(|backend| {
+            let client = backend.admin_client();
+            let admin_key = Header::new("x-admin-key", ADMIN_KEY);
+
+            let response = client
+                .post("/")
+                .header(ContentType::Form)
+                .body("content=hello&title=&expires_at=&password=secret")
+                .dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+            let location = response.headers().get_one("Location").unwrap().to_owned();
+            for password in ["wrong", "secret"] {
+                client
+                    .post(location.as_str())
+                    .header(ContentType::Form)
+                    .body(format!("password={}", password))
+                    .dispatch();
+            }
+
+            let response = client.get("/api/admin/audit").dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+
+            let entries: Vec<AuditEntry> = client
+                .get("/api/admin/audit")
+                .header(admin_key.clone())
+                .dispatch()
+                .into_json()
+                .unwrap();
+            let events: Vec<_> = entries.iter().map(|entry| entry.event.as_str()).collect();
+            assert_eq!(events, ["clip_created", "password_failed", "clip_unlocked"]);
+            let short_code = location.trim_start_matches("/clip/");
+            assert!(entries
+                .iter()
+                .all(|entry| entry.short_code.as_deref() == Some(short_code)));
+
+            let entries: Vec<AuditEntry> = client
+                .get("/api/admin/audit?outcome=failure")
+                .header(admin_key.clone())
+                .dispatch()
+                .into_json()
+                .unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].event, "password_failed");
+
+            let entries: Vec<AuditEntry> = client
+                .get(format!("/api/admin/audit?after={}", entries[0].id))
+                .header(admin_key.clone())
+                .dispatch()
+                .into_json()
+                .unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].event, "clip_unlocked");
+
+            let response = client
+                .get("/api/admin/audit?event=clip_stolen")
+                .header(admin_key)
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+        });
+    }
+
+    #[test]
+    fn records_visitors_by_address_whatever_key_they_send() {
+        use crate::web::api::API_KEY_HEADER;
+
+        each_backend(|backend| {
+            let client = backend.admin_client();
+            let response = client
+                .post("/")
+                .header(ContentType::Form)
+                .header(Header::new(API_KEY_HEADER, "bm90IGEga2V5"))
+                .remote("10.0.0.1:4000".parse().unwrap())
+                .body("content=hello&title=&expires_at=&password=")
+                .dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+
+            let entries: Vec<AuditEntry> = client
+                .get("/api/admin/audit?event=clip_created")
+                .header(Header::new("x-admin-key", ADMIN_KEY))
+                .dispatch()
+                .into_json()
+                .unwrap();
+            assert_eq!(entries.len(), 1);
+            assert!(entries[0].actor.starts_with("ip:"), "{}", entries[0].actor);
+        });
+    }
+}
@@ -12,7 +12,7 @@ pub struct Home {}
 
 impl PageContext for Home {
     fn title(&self) -> &str {
-        "Share Your Clipboard"
+        "page-home"
     }
 
     fn template_path(&self) -> &str {
@@ -27,11 +27,16 @@ impl PageContext for Home {
 #[derive(Debug, Serialize, Constructor)]
 pub struct ViewClip {
     pub clip: crate::Clip,
+    /// whether the viewer created the clip, and may see its statistics or delete it
+    pub owner: bool,
+    pub time_zone: crate::web::time::ViewerTimeZone,
+    /// the owner key, shown to the creator once, right after the clip was created
+    pub owner_key: Option<String>,
 }
 
 impl PageContext for ViewClip {
     fn title(&self) -> &str {
-        "View Clip"
+        "page-view-clip"
     }
 
     fn template_path(&self) -> &str {
@@ -50,7 +55,7 @@ pub struct PasswordRequired {
 
 impl PageContext for PasswordRequired {
     fn title(&self) -> &str {
-        "Password Required"
+        "page-password-required"
     }
 
     fn template_path(&self) -> &str {
@@ -61,3 +66,140 @@ impl PageContext for PasswordRequired {
         "base"
     }
 }
+
+/// a deleted or expired clip, for its creator to restore
+#[derive(Debug, Serialize, Constructor)]
+pub struct TrashedClip {
+    short_code: crate::ShortCode,
+}
+
+impl PageContext for TrashedClip {
+    fn title(&self) -> &str {
+        "page-trashed-clip"
+    }
+
+    fn template_path(&self) -> &str {
+        "clip_trashed"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+/// what went wrong with a request, for the error pages of the web catchers and `PageError`
+#[derive(Debug, Serialize)]
+pub struct ErrorPage {
+    status: u16,
+    reason: String,
+    message: String,
+    request_id: String,
+}
+
+impl ErrorPage {
+    pub fn new(
+        status: rocket::http::Status,
+        reason: &str,
+        message: &str,
+        request_id: &str,
+    ) -> Self {
+        Self {
+            status: status.code,
+            reason: reason.to_owned(),
+            message: message.to_owned(),
+            request_id: request_id.to_owned(),
+        }
+    }
+}
+
+impl PageContext for ErrorPage {
+    fn title(&self) -> &str {
+        &self.reason
+    }
+
+    fn template_path(&self) -> &str {
+        "error"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+/// one day in the views chart, in the coordinates of its SVG
+#[derive(Debug, Serialize)]
+pub struct ChartBar {
+    day: String,
+    views: u64,
+    visitors: u64,
+    x: u64,
+    y: u64,
+    height: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Chart {
+    width: u64,
+    bars: Vec<ChartBar>,
+}
+
+impl Chart {
+    const HEIGHT: u64 = 100;
+    const BAR_WIDTH: u64 = 10;
+    const BAR_GAP: u64 = 2;
+
+    fn new(days: &[crate::domain::stats::DayStats]) -> Self {
+        let most = days.iter().map(|day| day.views).max().unwrap_or(0).max(1);
+        let bars = days
+            .iter()
+            .enumerate()
+            .map(|(i, day)| {
+                // days with any views get at least a sliver, so they stand out from empty ones
+                let height = match day.views {
+                    0 => 0,
+                    views => (views * Self::HEIGHT / most).max(1),
+                };
+                ChartBar {
+                    day: day.day.format("%Y-%m-%d").to_string(),
+                    views: day.views,
+                    visitors: day.visitors,
+                    x: i as u64 * (Self::BAR_WIDTH + Self::BAR_GAP),
+                    y: Self::HEIGHT - height,
+                    height,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            width: (bars.len() as u64 * (Self::BAR_WIDTH + Self::BAR_GAP)).max(1),
+            bars,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClipStats {
+    stats: crate::domain::stats::ClipStats,
+    chart: Chart,
+}
+
+impl ClipStats {
+    pub fn new(stats: crate::domain::stats::ClipStats) -> Self {
+        let chart = Chart::new(&stats.days);
+        Self { stats, chart }
+    }
+}
+
+impl PageContext for ClipStats {
+    fn title(&self) -> &str {
+        "page-clip-stats"
+    }
+
+    fn template_path(&self) -> &str {
+        "clip_stats"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
@@ -1,16 +1,84 @@
 use crate::domain::clip::field;
+use crate::service::ask;
+use crate::ClipError;
 use rocket::form::FromForm;
+use rocket::fs::TempFile;
 use serde::Serialize;
 
-#[derive(Debug, Serialize, FromForm)]
-pub struct NewClip {
-    pub content: field::Content,
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("{0}")]
+    Clip(#[from] ClipError),
+
+    #[error("failed to read upload: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewClip<'r> {
+    pub content: Option<field::Content>,
     pub title: field::Title,
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
+    pub file: Option<TempFile<'r>>,
+}
+
+impl NewClip<'_> {
+    /// reads the uploaded file (if any) and builds the service request.
+    /// a clip with a file but no text content uses the file name as its content
+    pub async fn into_ask(self) -> Result<ask::NewClip, UploadError> {
+        let attachment = match self.file {
+            Some(file) if file.len() > 0 => Some(read_upload(&file).await?),
+            _ => None,
+        };
+
+        let content = match (self.content, &attachment) {
+            (Some(content), _) => content,
+            (None, Some(upload)) => field::Content::new(&upload.file_name)?,
+            (None, None) => return Err(ClipError::EmptyContent.into()),
+        };
+
+        Ok(ask::NewClip {
+            content,
+            title: self.title,
+            exprires_at: self.expires_at,
+            password: self.password,
+            attachment,
+            owner: None,
+            api_key: None,
+        })
+    }
+}
+
+async fn read_upload(file: &TempFile<'_>) -> Result<ask::Upload, UploadError> {
+    use rocket::tokio::io::AsyncReadExt;
+
+    let mut data = Vec::with_capacity(file.len() as usize);
+    file.open().await?.read_to_end(&mut data).await?;
+
+    let file_name = file
+        .raw_name()
+        .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str().to_owned())
+        .unwrap_or_default();
+
+    let mime_type = file
+        .content_type()
+        .map(|content_type| content_type.to_string())
+        .unwrap_or_default();
+
+    Ok(ask::Upload {
+        file_name,
+        mime_type,
+        data,
+    })
 }
 
 #[derive(Debug, Serialize, FromForm)]
 pub struct GetPasswordProtectedClip {
     pub password: field::Password,
 }
+
+#[derive(Debug, FromForm)]
+pub struct RestoreClip {
+    pub expires_at: field::ExpiresAt,
+}
@@ -0,0 +1,131 @@
+use crate::data::migrate::MigrationState;
+use crate::data::repository::AppRepository;
+use crate::domain::health::{ComponentHealth, HealthStatus};
+use crate::domain::jobs::Scheduler;
+use crate::web::Views;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// longest a readiness probe waits for the database
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    /// the worst status of any component
+    pub status: HealthStatus,
+    pub components: BTreeMap<&'static str, ComponentHealth>,
+}
+
+impl Health {
+    fn new(components: BTreeMap<&'static str, ComponentHealth>) -> Self {
+        let status = components
+            .values()
+            .map(|component| component.status)
+            .max()
+            .unwrap_or(HealthStatus::Ok);
+        Self { status, components }
+    }
+}
+
+async fn database(repository: &AppRepository) -> ComponentHealth {
+    match tokio::time::timeout(DATABASE_TIMEOUT, repository.ping()).await {
+        Ok(Ok(())) => ComponentHealth::ok(),
+        Ok(Err(e)) => ComponentHealth::down(e.to_string()),
+        Err(_) => {
+            ComponentHealth::down(format!("no answer within {}s", DATABASE_TIMEOUT.as_secs()))
+        }
+    }
+}
+
+/// down unless the schema is exactly the one this build expects
+async fn migrations(repository: &AppRepository) -> ComponentHealth {
+    let status = match tokio::time::timeout(DATABASE_TIMEOUT, repository.migration_status()).await {
+        Ok(Ok(status)) => status,
+        Ok(Err(e)) => return ComponentHealth::down(e.to_string()),
+        Err(_) => return ComponentHealth::down("timed out reading migrations"),
+    };
+    let count = |state| {
+        status
+            .iter()
+            .filter(|migration| migration.state == state)
+            .count()
+    };
+    match (
+        count(MigrationState::Pending),
+        count(MigrationState::Unknown),
+    ) {
+        (0, 0) => ComponentHealth::ok(),
+        (pending, 0) => ComponentHealth::down(format!("{} migrations pending", pending)),
+        (_, unknown) => ComponentHealth::down(format!(
+            "{} applied migrations are unknown to this build",
+            unknown
+        )),
+    }
+}
+
+/// liveness: the server is up and answering
+#[rocket::get("/healthz")]
+pub fn healthz() -> Json<Health> {
+    Json(Health::new(BTreeMap::new()))
+}
+
+/// readiness: 503 while the database is unusable, 200 otherwise. the background tasks only
+/// degrade the status, as clips are still served while they are failing
+#[rocket::get("/readyz")]
+pub async fn readyz(
+    repository: &State<AppRepository>,
+    views: &State<Views>,
+    scheduler: &State<Scheduler>,
+) -> status::Custom<Json<Health>> {
+    let (database, migrations) = tokio::join!(database(repository), migrations(repository));
+    let health = Health::new(BTreeMap::from([
+        ("database", database),
+        ("migrations", migrations),
+        ("views", views.health()),
+        ("jobs", scheduler.health()),
+    ]));
+
+    let status = match health.status {
+        HealthStatus::Down => {
+            tracing::warn!(?health, "not ready");
+            Status::ServiceUnavailable
+        }
+        _ => Status::Ok,
+    };
+    status::Custom(status, Json(health))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![healthz, readyz]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::web::test::each_backend;
+    use rocket::http::Status;
+    use serde_json::Value;
+
+    #[test]
+    fn reports_liveness_and_readiness() {
+        each_backend(|backend| {
+            let client = backend.client();
+
+            let response = client.get("/healthz").dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.into_json::<Value>().unwrap()["status"], "ok");
+
+            let response = client.get("/readyz").dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let health = response.into_json::<Value>().unwrap();
+            assert_eq!(health["status"], "ok", "{}", health);
+            for component in ["database", "migrations", "views", "jobs"] {
+                assert_eq!(health["components"][component]["status"], "ok");
+            }
+        });
+    }
+}
@@ -0,0 +1,390 @@
+//! the handlebars helpers every `Renderer` starts with
+
+use crate::web::i18n::Localizer;
+use crate::web::time;
+use crate::ShortCode;
+use chrono::{DateTime, Utc};
+use fluent_bundle::{FluentArgs, FluentValue};
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
+    RenderError,
+};
+use rocket::uri;
+use std::sync::Arc;
+use unic_langid::LanguageIdentifier;
+
+pub fn register_builtins(handlebars: &mut Handlebars, localizer: Arc<Localizer>) {
+    handlebars.register_helper("t", Box::new(Translate(localizer.clone())));
+    handlebars.register_helper("relative_time", Box::new(RelativeTime(localizer)));
+    handlebars.register_helper("datetime", Box::new(datetime));
+    handlebars.register_helper("pluralize", Box::new(pluralize));
+    handlebars.register_helper("due_within", Box::new(due_within));
+    handlebars.register_helper("file_size", Box::new(file_size));
+    handlebars.register_helper("truncate", Box::new(truncate));
+    handlebars.register_helper("clip_url", Box::new(clip_url));
+    handlebars.register_helper("asset", Box::new(asset));
+    handlebars.register_helper("icon", Box::new(icon));
+}
+
+fn time_param(helper: &Helper, index: usize) -> Result<DateTime<Utc>, RenderError> {
+    helper
+        .param(index)
+        .and_then(|param| param.value().as_str())
+        .and_then(|time| time.parse().ok())
+        .ok_or_else(|| RenderError::new(format!("{}: expected a time", helper.name())))
+}
+
+/// the locale the page is rendered in, from `_locale`
+fn page_locale(ctx: &Context, localizer: &Localizer) -> LanguageIdentifier {
+    ctx.data()
+        .get("_locale")
+        .and_then(|locale| locale.as_str())
+        .and_then(|locale| locale.parse().ok())
+        .unwrap_or_else(|| localizer.default_locale().clone())
+}
+
+/// `{{t "clip-views" count=clip.views}}`: a message of the page's locale, with the hash as its
+/// arguments
+pub struct Translate(pub Arc<Localizer>);
+
+impl HelperDef for Translate {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        helper: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let id = helper
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or_else(|| RenderError::new("t: expected a message id"))?;
+        let mut args = FluentArgs::new();
+        for (name, value) in helper.hash() {
+            let value = match value.value() {
+                serde_json::Value::Number(number) => {
+                    FluentValue::from(number.as_f64().unwrap_or_default())
+                }
+                serde_json::Value::String(text) => FluentValue::from(text.clone()),
+                serde_json::Value::Null => FluentValue::None,
+                other => FluentValue::from(other.to_string()),
+            };
+            args.set(*name, value);
+        }
+        let locale = page_locale(ctx, &self.0);
+        let message = self.0.message(&locale, id, Some(&args));
+        out.write(&handlebars::html_escape(&message))?;
+        Ok(())
+    }
+}
+
+/// `{{relative_time clip.expires_at}}`: "in 3 hours", "2 days ago", in the page's locale
+pub struct RelativeTime(pub Arc<Localizer>);
+
+impl HelperDef for RelativeTime {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        helper: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let time = time_param(helper, 0)?;
+        let locale = page_locale(ctx, &self.0);
+        let message = match time::relative_span(time, Utc::now()) {
+            None => self.0.message(&locale, "time-just-now", None),
+            Some((count, unit, ahead)) => {
+                let mut args = FluentArgs::new();
+                args.set("count", count);
+                let id = match ahead {
+                    true => format!("time-in-{}", unit),
+                    false => format!("time-ago-{}", unit),
+                };
+                self.0.message(&locale, &id, Some(&args))
+            }
+        };
+        out.write(&handlebars::html_escape(&message))?;
+        Ok(())
+    }
+}
+
+/// `{{datetime clip.created_at time_zone}}`, in UTC when the timezone is left out or unknown
+fn datetime(
+    helper: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let time = time_param(helper, 0)?;
+    let tz = helper
+        .param(1)
+        .and_then(|param| param.value().as_str())
+        .and_then(|tz| tz.parse().ok())
+        .unwrap_or(chrono_tz::UTC);
+    out.write(&time::absolute(time, tz))?;
+    Ok(())
+}
+
+/// `{{pluralize clip.views "view"}}`, with the plural as a third parameter when it is not
+/// the singular with an "s"
+fn pluralize(
+    helper: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let count = helper
+        .param(0)
+        .and_then(|param| param.value().as_u64())
+        .ok_or_else(|| RenderError::new("pluralize: expected a count"))?;
+    let singular = helper
+        .param(1)
+        .and_then(|param| param.value().as_str())
+        .ok_or_else(|| RenderError::new("pluralize: expected a word"))?;
+    let plural = match helper.param(2).and_then(|param| param.value().as_str()) {
+        Some(plural) => plural.to_owned(),
+        None => format!("{}s", singular),
+    };
+    out.write(&time::pluralize(count, singular, &plural))?;
+    Ok(())
+}
+
+// `{{#if (due_within clip.expires_at 3600)}}`: whether the time is still ahead, and at most that
+// many seconds away
+handlebars_helper!(due_within: |time: str, seconds: i64| {
+    time.parse::<DateTime<Utc>>().is_ok_and(|time| {
+        let left = (time - Utc::now()).num_seconds();
+        (0..=seconds).contains(&left)
+    })
+});
+
+/// `bytes` in the largest binary unit that keeps it at 1 or more: "512 B", "1.5 MiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+handlebars_helper!(file_size: |bytes: u64| format_size(bytes));
+
+/// `text` cut to at most `length` characters, ending in an ellipsis when anything was cut
+pub fn truncate_text(text: &str, length: usize) -> String {
+    match text.char_indices().nth(length) {
+        Some(_) => {
+            let kept = text
+                .chars()
+                .take(length.saturating_sub(1))
+                .collect::<String>();
+            format!("{}…", kept.trim_end())
+        }
+        None => text.to_owned(),
+    }
+}
+
+// `{{truncate clip.title 40}}`, rendering nothing for a missing text
+handlebars_helper!(truncate: |text: Json, length: u64| {
+    truncate_text(text.as_str().unwrap_or_default(), length as usize)
+});
+
+/// `{{clip_url clip.short_code}}`, or with "raw", "stats", "delete" or "restore" as a second
+/// parameter the link to the raw clip, its statistics or the forms that delete and restore it,
+/// below the `base_url` global
+fn clip_url(
+    helper: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let short_code = helper
+        .param(0)
+        .and_then(|param| param.value().as_str())
+        .map(ShortCode::from)
+        .ok_or_else(|| RenderError::new("clip_url: expected a short code"))?;
+    let uri = match helper.param(1).and_then(|param| param.value().as_str()) {
+        None => uri!(crate::web::http::get_clip(short_code = short_code)),
+        Some("raw") => uri!(crate::web::http::get_raw_clip(short_code = short_code)),
+        Some("stats") => uri!(crate::web::http::get_clip_stats(short_code = short_code)),
+        Some("delete") => uri!(crate::web::http::delete_clip(short_code = short_code)),
+        Some("restore") => uri!(crate::web::http::restore_clip(short_code = short_code)),
+        Some(page) => {
+            return Err(RenderError::new(format!(
+                "clip_url: unknown page {:?}",
+                page
+            )))
+        }
+    };
+    let base_url = ctx
+        .data()
+        .pointer("/_globals/base_url")
+        .and_then(|base_url| base_url.as_str())
+        .unwrap_or_default();
+    out.write(base_url.trim_end_matches('/'))?;
+    out.write(&uri.to_string())?;
+    Ok(())
+}
+
+/// the hashed url of the static file `path`, if the `assets` global lists it
+fn listed_asset<'a>(ctx: &'a Context, path: &str) -> Option<&'a str> {
+    ctx.data()
+        .get("_globals")
+        .and_then(|globals| globals.get("assets"))
+        .and_then(|assets| assets.get(path))
+        .and_then(|url| url.as_str())
+}
+
+/// the url of the static file `path` under its hashed name, from the `assets` global
+fn asset_url(ctx: &Context, path: &str) -> String {
+    listed_asset(ctx, path).map_or_else(|| format!("/static/{}", path), str::to_owned)
+}
+
+/// `{{asset "clipstash.css"}}`
+fn asset(
+    helper: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let path = helper
+        .param(0)
+        .and_then(|param| param.value().as_str())
+        .ok_or_else(|| RenderError::new("asset: expected a path"))?;
+    out.write(&asset_url(ctx, path))?;
+    Ok(())
+}
+
+/// the FontAwesome Free 5 sprite, used for icons instead of `icons.svg` once it is vendored
+const FONTAWESOME_SPRITE: &str = "vendor/fontawesome/sprites/solid.svg";
+
+/// `{{icon "clock"}}`: a symbol of the FontAwesome sprite, or else of `static/icons.svg`
+fn icon(
+    helper: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let name = helper
+        .param(0)
+        .and_then(|param| param.value().as_str())
+        .ok_or_else(|| RenderError::new("icon: expected a name"))?;
+    let sprite = if listed_asset(ctx, FONTAWESOME_SPRITE).is_some() {
+        FONTAWESOME_SPRITE
+    } else {
+        "icons.svg"
+    };
+    out.write(&format!(
+        r#"<svg class="icon-svg" aria-hidden="true"><use href="{}#{}"></use></svg>"#,
+        asset_url(ctx, sprite),
+        handlebars::html_escape(name)
+    ))?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn handlebars() -> Handlebars<'static> {
+        let mut handlebars = Handlebars::new();
+        register_builtins(&mut handlebars, Arc::new(Localizer::load()));
+        handlebars
+    }
+
+    fn render(template: &str, data: serde_json::Value) -> String {
+        handlebars().render_template(template, &data).unwrap()
+    }
+
+    #[test]
+    fn formats_sizes_and_truncates() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(
+            render("{{file_size size}}", json!({"size": 2048})),
+            "2.0 KiB"
+        );
+
+        assert_eq!(truncate_text("short", 10), "short");
+        assert_eq!(truncate_text("exactly10!", 10), "exactly10!");
+        assert_eq!(truncate_text("a longer title", 8), "a longe…");
+        assert_eq!(truncate_text("héllo wörld", 7), "héllo…");
+        assert_eq!(
+            render("[{{truncate title 3}}]", json!({"title": null})),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn builds_clip_urls() {
+        let data = json!({"code": "abc123"});
+        assert_eq!(render("{{clip_url code}}", data.clone()), "/clip/abc123");
+        assert_eq!(
+            render("{{clip_url code \"raw\"}}", data.clone()),
+            "/clip/raw/abc123"
+        );
+        let data = json!({"code": "abc123", "_globals": {"base_url": "https://clips.example/"}});
+        assert_eq!(
+            render("{{clip_url code \"stats\"}}", data),
+            "https://clips.example/clip/stats/abc123"
+        );
+
+        assert!(handlebars()
+            .render_template("{{clip_url code \"edit\"}}", &json!({"code": "abc123"}))
+            .is_err());
+    }
+
+    #[test]
+    fn translates_into_the_pages_locale() {
+        let data = json!({"_locale": "de", "views": 2, "name": "<b>"});
+        assert_eq!(
+            render("{{t \"clip-views\" count=views}}", data.clone()),
+            "2 Aufrufe"
+        );
+        assert_eq!(render("{{t \"clip-views\" count=1}}", json!({})), "1 view");
+        assert_eq!(render("{{t name}}", data.clone()), "&lt;b&gt;");
+
+        let soon = (Utc::now() + chrono::Duration::minutes(150)).to_rfc3339();
+        let data = json!({"_locale": "de", "soon": soon});
+        assert_eq!(render("{{relative_time soon}}", data), "in 2 Stunden");
+        assert_eq!(
+            render(
+                "{{t \"clip-expires-relative\" time=(relative_time soon)}}",
+                json!({"soon": soon})
+            ),
+            "Expires in 2 hours"
+        );
+    }
+
+    #[test]
+    fn prefers_the_vendored_icon_sprite() {
+        assert_eq!(
+            render("{{icon \"clock\"}}", json!({})),
+            r#"<svg class="icon-svg" aria-hidden="true"><use href="/static/icons.svg#clock"></use></svg>"#
+        );
+        let globals = json!({"_globals": {"assets": {
+            "icons.svg": "/static/icons.ab12.svg",
+            "vendor/fontawesome/sprites/solid.svg": "/static/vendor/fontawesome/sprites/solid.cd34.svg",
+        }}});
+        assert_eq!(
+            render("{{icon \"clock\"}}", globals),
+            r#"<svg class="icon-svg" aria-hidden="true"><use href="/static/vendor/fontawesome/sprites/solid.cd34.svg#clock"></use></svg>"#
+        );
+    }
+}
@@ -1,50 +1,119 @@
-use crate::data::AppDatabase;
+use crate::config::AppConfig;
+use crate::data::{blob::AppBlobStore, repository::AppRepository};
+use crate::domain::audit::Actor;
+use crate::domain::clip::field::{FileMeta, OwnerKey};
+use crate::domain::stats::ViewSource;
+use crate::metrics::AppMetrics;
 use crate::service::action;
+use crate::service::audit::Audit;
 use crate::service::{self, ask};
-use crate::web::{ctx, form, renderer::Renderer, PageError};
+use crate::web::i18n::LOCALE_COOKIE;
+use crate::web::theme::{Prefs, Theme, THEME_COOKIE};
+use crate::web::{ctx, form, renderer::Renderer, time::ViewerTimeZone, PageError};
 use crate::{ServiceError, ShortCode};
 use rocket::form::{Contextual, Form};
-use rocket::http::{Cookie, CookieJar, Status};
+use rocket::http::{ContentType, Cookie, CookieJar, Header, SameSite, Status};
 use rocket::response::content::RawHtml;
 use rocket::response::{status, Redirect};
 use rocket::{uri, State};
 
-use super::views::Views;
-use super::PASSWORD_COOKIE;
+use super::views::{Viewer, Views};
+use super::{owner_cookie, PASSWORD_COOKIE};
+
+/// days shown on the statistics page
+const STATS_DAYS: u32 = 30;
+
+/// holds the short code of the clip just created, so that its page shows the owner key once
+const CREATED_COOKIE: &str = "created";
 
 #[rocket::get("/")]
-fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
+fn home(prefs: Prefs, renderer: &State<Renderer<'_>>) -> Result<RawHtml<String>, PageError> {
     let context = ctx::Home::default();
 
-    RawHtml(renderer.render(context, &[]))
+    Ok(RawHtml(renderer.render(context, &prefs, &[])?))
+}
+
+/// the new clip, or the form again with what was wrong with it
+#[derive(rocket::Responder)]
+pub enum NewClipResponse {
+    Created(Box<Redirect>),
+    Form(status::Custom<RawHtml<String>>),
 }
 
 #[rocket::post("/", data = "<form>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn new_clip(
-    form: Form<Contextual<'_, form::NewClip>>,
-    database: &State<AppDatabase>,
+    cookies: &CookieJar<'_>,
+    actor: Actor,
+    prefs: Prefs,
+    form: Form<Contextual<'_, form::NewClip<'_>>>,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    config: &State<AppConfig>,
+    metrics: &State<AppMetrics>,
     renderer: &State<Renderer<'_>>,
-) -> Result<Redirect, (Status, RawHtml<String>)> {
+) -> Result<NewClipResponse, PageError> {
+    let page = |status, page| NewClipResponse::Form(status::Custom(status, RawHtml(page)));
     let form = form.into_inner();
 
     if let Some(value) = form.value {
-        let req = service::ask::NewClip {
-            content: value.content,
-            title: value.title,
-            exprires_at: value.expires_at,
-            password: value.password,
+        let mut req = match value.into_ask().await {
+            Ok(req) => req,
+            Err(form::UploadError::Clip(e)) => {
+                return Ok(page(
+                    Status::BadRequest,
+                    renderer.render_with_data(
+                        ctx::Home::default(),
+                        &prefs,
+                        ("clip", &form.context),
+                        &[e.message_id()],
+                    )?,
+                ))
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to read upload");
+                return Ok(page(
+                    Status::InternalServerError,
+                    renderer.render(ctx::Home::default(), &prefs, &["error-server-retry"])?,
+                ));
+            }
         };
 
-        match action::new_clip(req, database.get_pool()).await {
-            Ok(clip) => Ok(Redirect::to(uri!(get_clip(short_code = clip.short_code)))),
+        let owner = OwnerKey::default();
+        req.owner = Some(owner.clone());
+
+        let audit = Audit::new(actor, repository.as_ref());
+        let created = action::new_clip(
+            req,
+            repository.as_ref(),
+            blobs.as_ref(),
+            &config.clip,
+            &audit,
+        );
+        match created.await {
+            Ok(clip) => {
+                metrics.clips_created.inc();
+                cookies.add(
+                    Cookie::build((owner_cookie(&clip.short_code), owner.as_str().to_owned()))
+                        .path("/clip")
+                        .http_only(true)
+                        .same_site(SameSite::Lax),
+                );
+                cookies.add(
+                    Cookie::build((CREATED_COOKIE, clip.short_code.as_str().to_owned()))
+                        .path("/clip")
+                        .http_only(true)
+                        .same_site(SameSite::Lax),
+                );
+                Ok(NewClipResponse::Created(Box::new(Redirect::to(uri!(
+                    get_clip(short_code = clip.short_code)
+                )))))
+            }
             Err(e) => {
-                eprint!("internal error: {}", e);
-                Err((
+                tracing::error!(error = %e, "failed to create clip");
+                Ok(page(
                     Status::InternalServerError,
-                    RawHtml(renderer.render(
-                        ctx::Home::default(),
-                        &["A server error occurred. Please try again"],
-                    )),
+                    renderer.render(ctx::Home::default(), &prefs, &["error-server-retry"])?,
                 ))
             }
         }
@@ -58,65 +127,118 @@ pub async fn new_clip(
                 if let ErrorKind::Validation(msg) = &err.kind {
                     msg.as_ref()
                 } else {
-                    eprintln!("unhandled error: {}", err);
-                    "An error occurred, please try again"
+                    tracing::warn!(error = %err, "unhandled form error");
+                    "error-form"
                 }
             })
             .collect::<Vec<_>>();
 
-        Err((
+        Ok(page(
             Status::BadRequest,
-            RawHtml(renderer.render_with_data(
+            renderer.render_with_data(
                 ctx::Home::default(),
+                &prefs,
                 ("clip", &form.context),
                 &error,
-            )),
+            )?,
         ))
     }
 }
 
+/// the search box of the error pages, which opens a clip by its short code
+#[rocket::get("/clip?<short_code>")]
+fn find_clip(short_code: &str) -> Redirect {
+    match short_code.trim() {
+        "" => Redirect::to(uri!(home)),
+        short_code => Redirect::to(uri!(get_clip(short_code = ShortCode::from(short_code)))),
+    }
+}
+
 #[rocket::get("/clip/<short_code>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn get_clip(
+    cookies: &CookieJar<'_>,
     short_code: ShortCode,
-    database: &State<AppDatabase>,
+    viewer: Viewer,
+    time_zone: ViewerTimeZone,
+    prefs: Prefs,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
     views: &State<Views>,
     renderer: &State<Renderer<'_>>,
 ) -> Result<status::Custom<RawHtml<String>>, PageError> {
     fn render_with_status<T: ctx::PageContext + serde::Serialize + std::fmt::Debug>(
         status: Status,
         context: T,
+        prefs: &Prefs,
         renderer: &Renderer,
     ) -> Result<status::Custom<RawHtml<String>>, PageError> {
         Ok(status::Custom(
             status,
-            RawHtml(renderer.render(context, &[])),
+            RawHtml(renderer.render(context, prefs, &[])?),
         ))
     }
 
-    match action::get_clip(short_code.clone().into(), database.get_pool()).await {
+    let audit = Audit::new(viewer.actor(), repository.as_ref());
+    match action::get_clip(
+        short_code.clone().into(),
+        repository.as_ref(),
+        blobs.as_ref(),
+        &audit,
+    )
+    .await
+    {
         Ok(clip) => {
-            views.view(short_code.clone(), 1);
-            let context = ctx::ViewClip::new(clip);
-            render_with_status(Status::Ok, context, renderer)
+            views
+                .view(short_code.clone(), ViewSource::Web, viewer)
+                .await;
+            let owner = cookies.get(&owner_cookie(&short_code)).is_some();
+            let owner_key = just_created_key(cookies, &short_code);
+            let context = ctx::ViewClip::new(views.with_pending(clip), owner, time_zone, owner_key);
+            render_with_status(Status::Ok, context, &prefs, renderer)
         }
         Err(e) => match e {
             ServiceError::PermissionError(_) => {
                 let context = ctx::PasswordRequired::new(short_code);
-                render_with_status(Status::Unauthorized, context, renderer)
+                render_with_status(Status::Unauthorized, context, &prefs, renderer)
+            }
+            // the creator may still restore it, if it is in the trash
+            ServiceError::NotFound if cookies.get(&owner_cookie(&short_code)).is_some() => {
+                let context = ctx::TrashedClip::new(short_code);
+                render_with_status(Status::NotFound, context, &prefs, renderer)
+            }
+            ServiceError::NotFound => Err(PageError::NotFound(e.message_id().to_owned())),
+            e => {
+                tracing::error!(error = %e, "failed to get clip");
+                Err(PageError::Internal("error-server".to_owned()))
             }
-            ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
-            _ => Err(PageError::Internal("server error".to_owned())),
         },
     }
 }
 
+/// the owner key of `short_code` if it was just created, which is only shown once
+fn just_created_key(cookies: &CookieJar<'_>, short_code: &ShortCode) -> Option<String> {
+    let created = cookies.get(CREATED_COOKIE)?.value() == short_code.as_str();
+    if created {
+        cookies.remove(Cookie::build(CREATED_COOKIE).path("/clip"));
+    }
+    let owner = cookies.get(&owner_cookie(short_code))?;
+    created.then(|| owner.value().to_owned())
+}
+
 #[rocket::post("/clip/<short_code>", data = "<form>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn submit_clip_password(
     cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::GetPasswordProtectedClip>>,
     short_code: ShortCode,
+    viewer: Viewer,
+    time_zone: ViewerTimeZone,
+    prefs: Prefs,
     views: &State<Views>,
-    database: &State<AppDatabase>,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    metrics: &State<AppMetrics>,
     renderer: &State<Renderer<'_>>,
 ) -> Result<RawHtml<String>, PageError> {
     if let Some(form) = &form.value {
@@ -124,31 +246,77 @@ pub async fn submit_clip_password(
             short_code: short_code.clone(),
             password: form.password.clone(),
         };
-        match action::get_clip(req, database.get_pool()).await {
+        let audit = Audit::new(viewer.actor(), repository.as_ref());
+        match action::get_clip(req, repository.as_ref(), blobs.as_ref(), &audit).await {
             Ok(clip) => {
-                views.view(short_code.clone(), 1);
-                let context = ctx::ViewClip::new(clip);
+                views
+                    .view(short_code.clone(), ViewSource::Web, viewer)
+                    .await;
+                let owner = cookies.get(&owner_cookie(&short_code)).is_some();
+                let owner_key = just_created_key(cookies, &short_code);
+                let context =
+                    ctx::ViewClip::new(views.with_pending(clip), owner, time_zone, owner_key);
                 cookies.add(Cookie::new(
                     PASSWORD_COOKIE,
                     form.password.clone().into_inner().unwrap_or_default(),
                 ));
-                Ok(RawHtml(renderer.render(context, &[])))
+                Ok(RawHtml(renderer.render(context, &prefs, &[])?))
             }
             Err(e) => match e {
-                ServiceError::PermissionError(e) => {
+                ServiceError::PermissionError(_) => {
+                    if form.password.has_password() {
+                        metrics.password_failures.inc();
+                    }
                     let context = ctx::PasswordRequired::new(short_code);
-                    Ok(RawHtml(renderer.render(context, &[e.as_str()])))
+                    Ok(RawHtml(renderer.render(
+                        context,
+                        &prefs,
+                        &["clip-error-invalid-password"],
+                    )?))
+                }
+                ServiceError::NotFound => Err(PageError::NotFound(e.message_id().to_owned())),
+                e => {
+                    tracing::error!(error = %e, "failed to get clip");
+                    Err(PageError::Internal("error-server".to_owned()))
                 }
-                ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
-                _ => Err(PageError::Internal("server error".to_owned())),
             },
         }
     } else {
         let context = ctx::PasswordRequired::new(short_code);
         Ok(RawHtml(renderer.render(
             context,
-            &["A password is required to view this clip"],
-        )))
+            &prefs,
+            &["error-password-required"],
+        )?))
+    }
+}
+
+#[derive(rocket::Responder)]
+pub struct Download {
+    data: Vec<u8>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+#[derive(rocket::Responder)]
+pub enum RawClip {
+    Text(String),
+    File(Box<Download>),
+}
+
+impl RawClip {
+    fn file(data: Vec<u8>, attachment: &FileMeta) -> Self {
+        let content_type =
+            ContentType::parse_flexible(&attachment.mime_type).unwrap_or(ContentType::Binary);
+        let disposition = Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", attachment.file_name),
+        );
+        Self::File(Box::new(Download {
+            data,
+            content_type,
+            disposition,
+        }))
     }
 }
 
@@ -156,9 +324,12 @@ pub async fn submit_clip_password(
 pub async fn get_raw_clip(
     cookies: &CookieJar<'_>,
     short_code: ShortCode,
+    viewer: Viewer,
     views: &State<Views>,
-    database: &State<AppDatabase>,
-) -> Result<status::Custom<String>, Status> {
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    metrics: &State<AppMetrics>,
+) -> Result<status::Custom<RawClip>, Status> {
     use crate::domain::clip::field::Password;
 
     let req = ask::GetClip {
@@ -166,59 +337,326 @@ pub async fn get_raw_clip(
         password: cookies
             .get(PASSWORD_COOKIE)
             .map(|cookie| cookie.value())
-            .map(|raw_password| Password::new(raw_password.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
+            .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+            .unwrap_or_default(),
     };
+    let has_password = req.password.has_password();
 
-    match action::get_clip(req, database.get_pool()).await {
+    let audit = Audit::new(viewer.actor(), repository.as_ref());
+    match action::get_clip(req, repository.as_ref(), blobs.as_ref(), &audit).await {
         Ok(clip) => {
-            views.view(short_code.clone(), 1);
-            Ok(status::Custom(Status::Ok, clip.content.into_inner()))
+            let raw = match clip.attachment.as_ref() {
+                Some(attachment) => match action::get_attachment(&clip, blobs.as_ref()).await {
+                    Ok(data) => RawClip::file(data, attachment),
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to read attachment");
+                        return Err(Status::InternalServerError);
+                    }
+                },
+                None => RawClip::Text(clip.content.into_inner()),
+            };
+            views
+                .view(short_code.clone(), ViewSource::Raw, viewer)
+                .await;
+            Ok(status::Custom(Status::Ok, raw))
         }
         Err(e) => match e {
-            ServiceError::PermissionError(msg) => Ok(status::Custom(Status::Unauthorized, msg)),
+            ServiceError::PermissionError(msg) => {
+                if has_password {
+                    metrics.password_failures.inc();
+                }
+                Ok(status::Custom(Status::Unauthorized, RawClip::Text(msg)))
+            }
             ServiceError::NotFound => Err(Status::NotFound),
-            _ => Err(Status::InternalServerError),
+            e => {
+                tracing::error!(error = %e, "failed to get clip");
+                Err(Status::InternalServerError)
+            }
         },
     }
 }
 
+/// view statistics, for the owner of the clip only
+#[rocket::get("/clip/stats/<short_code>")]
+pub async fn get_clip_stats(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    prefs: Prefs,
+    repository: &State<AppRepository>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<RawHtml<String>, PageError> {
+    let forbidden = || PageError::Forbidden("error-stats-owner-only".to_owned());
+
+    let owner = cookies
+        .get(&owner_cookie(&short_code))
+        .map(|cookie| OwnerKey::new(cookie.value()))
+        .ok_or_else(forbidden)?;
+    match action::clip_stats(
+        short_code,
+        Some(&owner),
+        None,
+        STATS_DAYS,
+        repository.as_ref(),
+        repository.as_ref(),
+    )
+    .await
+    {
+        Ok(stats) => Ok(RawHtml(renderer.render(
+            ctx::ClipStats::new(stats),
+            &prefs,
+            &[],
+        )?)),
+        Err(ServiceError::PermissionError(_)) => Err(forbidden()),
+        Err(e @ ServiceError::NotFound) => Err(PageError::NotFound(e.message_id().to_owned())),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to load statistics");
+            Err(PageError::Internal("error-server".to_owned()))
+        }
+    }
+}
+
+/// the owner key from the cookie the web form handed out for `short_code`
+fn owner_key(cookies: &CookieJar<'_>, short_code: &ShortCode) -> Result<OwnerKey, PageError> {
+    cookies
+        .get(&owner_cookie(short_code))
+        .map(|cookie| OwnerKey::new(cookie.value()))
+        .ok_or_else(|| PageError::Forbidden("error-owner-only".to_owned()))
+}
+
+/// moves a clip to the trash, for the creator of the clip only, and offers to restore it
+#[rocket::post("/clip/delete/<short_code>")]
+pub async fn delete_clip(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    actor: Actor,
+    prefs: Prefs,
+    repository: &State<AppRepository>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<RawHtml<String>, PageError> {
+    let req = ask::DeleteClip {
+        owner: Some(owner_key(cookies, &short_code)?),
+        api_key: None,
+        short_code: short_code.clone(),
+    };
+    let audit = Audit::new(actor, repository.as_ref());
+    match action::delete_clip(req, repository.as_ref(), &audit).await {
+        Ok(()) => Ok(RawHtml(renderer.render(
+            ctx::TrashedClip::new(short_code),
+            &prefs,
+            &[],
+        )?)),
+        Err(ServiceError::PermissionError(_)) => {
+            Err(PageError::Forbidden("error-owner-only".to_owned()))
+        }
+        Err(e @ ServiceError::NotFound) => Err(PageError::NotFound(e.message_id().to_owned())),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to delete clip");
+            Err(PageError::Internal("error-server".to_owned()))
+        }
+    }
+}
+
+/// the restored clip, or the restore form again with what was wrong
+#[derive(rocket::Responder)]
+pub enum RestoreClipResponse {
+    Restored(Box<Redirect>),
+    Form(status::Custom<RawHtml<String>>),
+}
+
+/// takes a clip out of the trash, for the creator of the clip only. an expired clip needs a
+/// new expiry
+#[rocket::post("/clip/restore/<short_code>", data = "<form>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn restore_clip(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    form: Form<Contextual<'_, form::RestoreClip>>,
+    actor: Actor,
+    prefs: Prefs,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    config: &State<AppConfig>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<RestoreClipResponse, PageError> {
+    let page = |error: &str| -> Result<RestoreClipResponse, PageError> {
+        let context = ctx::TrashedClip::new(short_code.clone());
+        Ok(RestoreClipResponse::Form(status::Custom(
+            Status::BadRequest,
+            RawHtml(renderer.render(context, &prefs, &[error])?),
+        )))
+    };
+    let Some(form) = form.into_inner().value else {
+        return page("clip-error-invalid-date");
+    };
+
+    let req = ask::RestoreClip {
+        owner: Some(owner_key(cookies, &short_code)?),
+        api_key: None,
+        expires_at: form.expires_at,
+        short_code: short_code.clone(),
+    };
+    let audit = Audit::new(actor, repository.as_ref());
+    let restored = action::restore_clip(
+        req,
+        config.trash_retention(),
+        repository.as_ref(),
+        blobs.as_ref(),
+        &audit,
+    );
+    match restored.await {
+        Ok(clip) => Ok(RestoreClipResponse::Restored(Box::new(Redirect::to(uri!(
+            get_clip(short_code = clip.short_code)
+        ))))),
+        Err(e @ ServiceError::Clip(_)) => page(e.message_id()),
+        Err(ServiceError::PermissionError(_)) => {
+            Err(PageError::Forbidden("error-owner-only".to_owned()))
+        }
+        Err(e @ ServiceError::NotFound) => Err(PageError::NotFound(e.message_id().to_owned())),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to restore clip");
+            Err(PageError::Internal("error-server".to_owned()))
+        }
+    }
+}
+
+/// the language switcher: shows the pages in `locale` from now on, starting with the page it
+/// was used on
+#[rocket::get("/language/<locale>")]
+fn set_language(
+    cookies: &CookieJar<'_>,
+    locale: &str,
+    back: BackTo,
+    renderer: &State<Renderer<'_>>,
+) -> Option<Redirect> {
+    let locale = locale
+        .parse()
+        .ok()
+        .filter(|locale| renderer.localizer().supports(locale))?;
+    cookies.add(
+        Cookie::build((LOCALE_COOKIE, locale.to_string()))
+            .path("/")
+            .permanent()
+            .same_site(SameSite::Lax),
+    );
+    Some(Redirect::to(back.0))
+}
+
+/// the theme toggle: shows the pages in `theme` from now on, starting with the page it was used on
+#[rocket::get("/theme/<theme>")]
+fn set_theme(cookies: &CookieJar<'_>, theme: &str, back: BackTo) -> Option<Redirect> {
+    let theme = theme.parse::<Theme>().ok()?;
+    cookies.add(
+        Cookie::build((THEME_COOKIE, theme.as_str()))
+            .path("/")
+            .permanent()
+            .same_site(SameSite::Lax),
+    );
+    Some(Redirect::to(back.0))
+}
+
+/// the page the request came from when it is one of ours, or the home page
+pub struct BackTo(String);
+
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for BackTo {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(
+        req: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        use rocket::http::uri::Absolute;
+
+        // requests built by hand, such as those of the local client, only carry the header
+        let own_host = req
+            .host()
+            .map(|host| host.to_string())
+            .or_else(|| req.headers().get_one("Host").map(str::to_owned));
+        let back = req
+            .headers()
+            .get_one("Referer")
+            .and_then(|referer| Absolute::parse(referer).ok())
+            .filter(|uri| {
+                let host = uri.authority().map(|authority| authority.to_string());
+                host.is_some() && host == own_host
+            })
+            .map(|uri| match uri.query() {
+                Some(query) => format!("{}?{}", uri.path(), query),
+                None => uri.path().to_string(),
+            })
+            .filter(|path| path.starts_with('/') && !path.starts_with("//"))
+            .unwrap_or_else(|| "/".to_owned());
+        rocket::request::Outcome::Success(Self(back))
+    }
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![home, get_clip, new_clip, submit_clip_password, get_raw_clip]
+    rocket::routes![
+        home,
+        find_clip,
+        set_language,
+        set_theme,
+        get_clip,
+        new_clip,
+        submit_clip_password,
+        get_raw_clip,
+        get_clip_stats,
+        delete_clip,
+        restore_clip
+    ]
 }
 
 pub mod catcher {
+    use crate::web::ErrorResponse;
+    use rocket::http::Status;
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
 
+    // the request itself is logged by the tracing fairing. its headers are never logged, as
+    // they carry passwords and keys
+
     #[catch(default)]
-    fn default(req: &Request) -> &'static str {
-        eprintln!("general error: {:?}", req);
-        "something went wrong..."
+    fn default(status: Status, _: &Request) -> ErrorResponse {
+        tracing::warn!(status = status.code, "unhandled error");
+        ErrorResponse::new(status, "error-generic")
     }
 
     #[catch(500)]
-    fn internal_error(req: &Request) -> &'static str {
-        eprintln!("internal error: {:?}", req);
-        "internal server error"
+    fn internal_error() -> ErrorResponse {
+        ErrorResponse::new(Status::InternalServerError, "error-internal")
     }
 
     #[catch(404)]
-    fn not_found() -> &'static str {
-        "404"
+    fn not_found() -> ErrorResponse {
+        ErrorResponse::new(Status::NotFound, "error-not-found")
+    }
+
+    #[catch(401)]
+    fn unauthorized() -> ErrorResponse {
+        ErrorResponse::new(Status::Unauthorized, "error-unauthorized")
+    }
+
+    #[catch(429)]
+    fn too_many_requests() -> ErrorResponse {
+        ErrorResponse::new(Status::TooManyRequests, "error-too-many-requests")
     }
 
     pub fn catchers() -> Vec<Catcher> {
-        catchers![not_found, default, internal_error]
+        catchers![
+            not_found,
+            default,
+            internal_error,
+            unauthorized,
+            too_many_requests
+        ]
     }
 }
 
 #[cfg(test)]
 pub mod test {
-    use crate::data::AppDatabase;
+    use crate::data::{blob::AppBlobStore, repository::AppRepository};
     use crate::test::async_runtime;
-    use crate::web::test::client;
+    use crate::web::test::{client, each_backend};
+    use crate::ShortCode;
     use rocket::http::Status;
 
     #[test]
@@ -229,66 +667,522 @@ pub mod test {
     }
 
     #[test]
-    fn error_on_missing_clip() {
-        let client = client();
-        let response = client.get("/clip/asasdasdasdasd").dispatch();
-        assert_eq!(response.status(), Status::NotFound);
+    fn falls_back_to_a_plain_error_page_when_rendering_fails() {
+        use crate::web::renderer::Renderer;
+        use crate::web::test::config;
+        use rocket::http::ContentType;
+        use rocket::local::blocking::Client;
+
+        for dev in [false, true] {
+            let mut config = config();
+            if dev {
+                config.renderer = Renderer::dev("templates/".into()).unwrap();
+            }
+            config
+                .renderer
+                .register_partial("home", "{{clip_url}}")
+                .unwrap();
+            let client = Client::tracked(crate::rocket(config)).unwrap();
+
+            let response = client.get("/").dispatch();
+            assert_eq!(response.status(), Status::InternalServerError);
+            assert_eq!(response.content_type(), Some(ContentType::HTML));
+            let page = response.into_string().unwrap();
+            assert!(page.contains("server error (request id: "));
+            assert_eq!(page.contains("clip_url: expected a short code"), dev);
+        }
     }
 
     #[test]
-    fn requires_password_when_applicable() {
-        use crate::domain::clip::field::{Content, ExpiresAt, Password, Title};
-        use crate::service;
-        use rocket::http::{ContentType, Cookie};
-
-        let rt = async_runtime();
+    fn renders_error_pages() {
+        use rocket::http::ContentType;
 
         let client = client();
-        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let response = client.get("/nothing/here").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::HTML));
+        let request_id = response
+            .headers()
+            .get_one("X-Request-Id")
+            .unwrap()
+            .to_owned();
+        let page = response.into_string().unwrap();
+        assert!(page.contains("Not Found"));
+        assert!(page.contains(&request_id));
+        assert!(page.contains(r#"<form method="get" action="/clip">"#));
 
-        let req = service::ask::NewClip {
-            content: Content::new("content").unwrap(),
-            exprires_at: ExpiresAt::default(),
-            password: Password::new("123".to_owned()).unwrap(),
-            title: Title::default(),
-        };
+        let response = client.get("/clip?short_code=%20abc%20").dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/clip/abc"));
 
-        let clip = rt
-            .block_on(async move { service::action::new_clip(req, db.get_pool()).await })
+        // the API keeps answering in JSON
+        let response = client.get("/api/nothing/here").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+    }
+
+    #[test]
+    fn shows_pages_in_the_viewers_language() {
+        use rocket::http::{ContentType, Cookie, Header};
+
+        let client = client();
+        let german = Header::new("Accept-Language", "de-CH, de;q=0.9, en;q=0.5");
+        let page = client
+            .get("/")
+            .header(german.clone())
+            .dispatch()
+            .into_string()
             .unwrap();
+        assert!(page.contains(r#"<html lang="de" "#));
+        assert!(page.contains("Teile deine Zwischenablage"));
 
-        // Block clip when no password is provided
         let response = client
-            .get(format!("/clip/{}", clip.short_code.as_str()))
+            .post("/")
+            .header(german.clone())
+            .header(ContentType::Form)
+            .body("content=hello&title=&expires_at=tomorrow&password=")
             .dispatch();
-        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("Das Ablaufdatum ist ungültig"));
+
+        let page = client
+            .get("/nothing/here")
+            .header(german.clone())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains("Nicht gefunden"));
 
-        // Block clip when no password is provided
+        // the language switcher wins over the browser, and goes back to where it was used
         let response = client
-            .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+            .get("/language/en")
+            .header(Header::new("Host", "localhost"))
+            .header(Header::new("Referer", "http://localhost/clip/abc?x=1"))
             .dispatch();
-        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some("/clip/abc?x=1")
+        );
+        let page = client
+            .get("/")
+            .header(german)
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains("Share Your Clipboard"));
 
-        // Get clip when the password is provided
         let response = client
-            .post(format!("/clip/{}", clip.short_code.as_str()))
-            .header(ContentType::Form)
-            .body("password=123")
+            .get("/language/de")
+            .header(Header::new("Host", "localhost"))
+            .header(Header::new("Referer", "https://elsewhere.example/page"))
             .dispatch();
-        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Location"), Some("/"));
+        assert_eq!(
+            response.cookies().get("lang").map(Cookie::value),
+            Some("de")
+        );
+        assert_eq!(
+            client.get("/language/xx").dispatch().status(),
+            Status::NotFound
+        );
+    }
+
+    #[test]
+    fn shows_pages_in_the_chosen_theme() {
+        use crate::web::assets::{Assets, CUSTOM_THEME};
+        use rocket::http::{Cookie, Header};
+
+        let client = client();
+        let assets = Assets::load(None).unwrap();
+        let page = client.get("/").dispatch().into_string().unwrap();
+        assert!(page.contains(r#"data-theme="auto""#));
+        assert!(page.contains(&format!(
+            r#"href="{}" media="(prefers-color-scheme: dark)""#,
+            assets.url("themes/dark.css")
+        )));
 
-        // Get clip when the password is provided
         let response = client
-            .get(format!("/clip/raw/{}", clip.short_code.as_str()))
-            .cookie(Cookie::new("password", "123"))
+            .get("/theme/dark")
+            .header(Header::new("Host", "localhost"))
+            .header(Header::new("Referer", "http://localhost/clip/abc"))
             .dispatch();
-        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/clip/abc"));
+        assert_eq!(
+            response.cookies().get("theme").map(Cookie::value),
+            Some("dark")
+        );
+        let page = client.get("/").dispatch().into_string().unwrap();
+        assert!(page.contains(r#"data-theme="dark""#));
+        assert!(page.contains(&assets.url("vendor/highlight-dark.css")));
+        assert!(!page.contains(&assets.url("themes/light.css")));
+        assert!(!page.contains("prefers-color-scheme"));
+        assert!(!page.contains(CUSTOM_THEME));
+        assert_eq!(
+            client.get("/theme/purple").dispatch().status(),
+            Status::NotFound
+        );
+
+        // the stylesheet of the operator comes after the built-in ones
+        use crate::config::AppConfig;
+        use crate::data::blob::MemoryBlobStore;
+        use crate::data::repository::MemoryRepository;
+        use crate::web::test::config_from;
+        use rocket::local::blocking::Client;
+        use std::sync::Arc;
+
+        let path = std::env::temp_dir().join(format!("clipshare-{}.css", uuid::Uuid::new_v4()));
+        std::fs::write(&path, ":root { --brand: rebeccapurple; }").unwrap();
+        let config = AppConfig {
+            theme_css: Some(path.clone()),
+            ..Default::default()
+        };
+        let client = Client::tracked(crate::rocket(config_from(
+            config,
+            Arc::new(MemoryRepository::default()),
+            Arc::new(MemoryBlobStore::default()),
+        )))
+        .unwrap();
+        let page = client.get("/").dispatch().into_string().unwrap();
+        let custom = page.find("/static/themes/custom.").unwrap();
+        assert!(page.find(&assets.url("clipstash.css")).unwrap() < custom);
+        let url = &page[custom..page[custom..].find('"').unwrap() + custom];
+        let css = client.get(url.to_owned()).dispatch().into_string().unwrap();
+        assert!(css.contains("rebeccapurple"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn error_on_missing_clip() {
+        each_backend(|backend| {
+            let client = backend.client();
+            let response = client.get("/clip/asasdasdasdasd").dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+        });
+    }
+
+    #[test]
+    fn requires_password_when_applicable() {
+        use crate::domain::clip::field::{Content, ExpiresAt, Password, Title};
+        use crate::service;
+        use rocket::http::{ContentType, Cookie};
+
+        each_backend(|backend| {
+            let rt = async_runtime();
+
+            let client = backend.client();
+            let repository = client.rocket().state::<AppRepository>().unwrap();
+            let blobs = client.rocket().state::<AppBlobStore>().unwrap();
+
+            let req = service::ask::NewClip {
+                content: Content::new("content").unwrap(),
+                exprires_at: ExpiresAt::default(),
+                password: Password::new("123".to_owned()).unwrap(),
+                title: Title::default(),
+                attachment: None,
+                owner: None,
+                api_key: None,
+            };
 
-        // Get clip when the password is provided, but incorrect
+            let clip = rt
+                .block_on(async move {
+                    service::action::new_clip(
+                        req,
+                        repository.as_ref(),
+                        blobs.as_ref(),
+                        &Default::default(),
+                        &crate::service::audit::Audit::system(repository.as_ref()),
+                    )
+                    .await
+                })
+                .unwrap();
+
+            // Block clip when no password is provided
+            let response = client
+                .get(format!("/clip/{}", clip.short_code.as_str()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+
+            // Block clip when no password is provided
+            let response = client
+                .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+
+            // Get clip when the password is provided
+            let response = client
+                .post(format!("/clip/{}", clip.short_code.as_str()))
+                .header(ContentType::Form)
+                .body("password=123")
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+
+            // Get clip when the password is provided
+            let response = client
+                .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+                .cookie(Cookie::new("password", "123"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+
+            // Get clip when the password is provided, but incorrect
+            let response = client
+                .get(format!("/clip/raw/{}", clip.short_code.as_str()))
+                .cookie(Cookie::new("password", "abc"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+        });
+    }
+
+    #[test]
+    fn shows_times_in_the_viewers_timezone() {
+        use crate::domain::clip::field::{Content, ExpiresAt, Password, Title};
+        use crate::domain::time::Time;
+        use crate::service;
+        use chrono::{Duration, Utc};
+        use rocket::http::Cookie;
+
+        each_backend(|backend| {
+            let rt = async_runtime();
+
+            let client = backend.client();
+            let repository = client.rocket().state::<AppRepository>().unwrap();
+            let blobs = client.rocket().state::<AppBlobStore>().unwrap();
+
+            let new_clip = |expires_at: Duration| service::ask::NewClip {
+                content: Content::new("content").unwrap(),
+                exprires_at: ExpiresAt::new(Time::from(Utc::now() + expires_at)),
+                password: Password::default(),
+                title: Title::default(),
+                attachment: None,
+                owner: None,
+                api_key: None,
+            };
+            let create = |req| {
+                rt.block_on(async {
+                    service::action::new_clip(
+                        req,
+                        repository.as_ref(),
+                        blobs.as_ref(),
+                        &Default::default(),
+                        &crate::service::audit::Audit::system(repository.as_ref()),
+                    )
+                    .await
+                })
+                .unwrap()
+            };
+
+            let soon = create(new_clip(Duration::minutes(30)));
+            let response = client
+                .get(format!("/clip/{}", soon.short_code.as_str()))
+                .cookie(Cookie::new("tz", "Asia/Tokyo"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let page = response.into_string().unwrap();
+            assert!(page.contains(" JST\""));
+            assert!(page.contains("data-expires-at=\""));
+            assert!(page.contains("just now"));
+
+            let later = create(new_clip(Duration::days(3) + Duration::minutes(1)));
+            let page = client
+                .get(format!("/clip/{}", later.short_code.as_str()))
+                .cookie(Cookie::new("tz", "Not/AZone"))
+                .dispatch()
+                .into_string()
+                .unwrap();
+            assert!(page.contains(" UTC\""));
+            assert!(page.contains("Expires in 3 days"));
+            assert!(!page.contains("data-expires-at=\""));
+        });
+    }
+
+    #[test]
+    fn uploads_and_downloads_attachment() {
+        use rocket::http::ContentType;
+
+        each_backend(|backend| {
+            let client = backend.client();
+
+            let body = concat!(
+                "--BOUNDARY\r\n",
+                "Content-Disposition: form-data; name=\"title\"\r\n\r\n",
+                "notes\r\n",
+                "--BOUNDARY\r\n",
+                "Content-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "hello file\r\n",
+                "--BOUNDARY--\r\n",
+            );
+            let response = client
+                .post("/")
+                .header(
+                    ContentType::new("multipart", "form-data")
+                        .with_params(("boundary", "BOUNDARY")),
+                )
+                .body(body)
+                .dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+
+            let location = response.headers().get_one("Location").unwrap().to_owned();
+            let short_code = location.trim_start_matches("/clip/");
+
+            let response = client.get(format!("/clip/raw/{}", short_code)).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.content_type(), Some(ContentType::Plain));
+            assert_eq!(
+                response.headers().get_one("Content-Disposition"),
+                Some("attachment; filename=\"notes.txt\"")
+            );
+            assert_eq!(response.into_string().unwrap(), "hello file");
+        });
+    }
+
+    #[test]
+    fn serves_clips() {
+        use rocket::http::ContentType;
+
+        each_backend(|backend| {
+            let client = backend.client();
+            let response = client
+                .post("/")
+                .header(ContentType::Form)
+                .body("content=in+memory&title=&expires_at=&password=")
+                .dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+
+            let location = response.headers().get_one("Location").unwrap().to_owned();
+            let short_code = location.trim_start_matches("/clip/");
+            let response = client.get(format!("/clip/raw/{}", short_code)).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.into_string().unwrap(), "in memory");
+        });
+    }
+
+    #[test]
+    fn flushes_views_on_shutdown() {
+        use crate::web::test::memory_client;
+        use rocket::http::ContentType;
+
+        let client = memory_client();
         let response = client
-            .get(format!("/clip/raw/{}", clip.short_code.as_str()))
-            .cookie(Cookie::new("password", "abc"))
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=counted&title=&expires_at=&password=")
             .dispatch();
-        assert_eq!(response.status(), Status::Unauthorized);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        drop(response);
+        let short_code = ShortCode::from(location.trim_start_matches("/clip/"));
+        assert_eq!(client.get(&location).dispatch().status(), Status::Ok);
+
+        let rocket = client.terminate();
+        async_runtime().block_on(crate::shutdown(&rocket));
+        let repository = rocket.state::<AppRepository>().unwrap();
+        let clip = async_runtime()
+            .block_on(repository.get_clip(&short_code))
+            .unwrap();
+        let clip = crate::Clip::try_from(clip).unwrap();
+        assert_eq!(clip.views.into_inner(), 1);
+    }
+
+    #[test]
+    fn shows_stats_to_owner_only() {
+        use rocket::http::ContentType;
+
+        each_backend(|backend| {
+            let client = backend.client();
+            let response = client
+                .post("/")
+                .header(ContentType::Form)
+                .body("content=tracked&title=&expires_at=&password=")
+                .dispatch();
+            let location = response.headers().get_one("Location").unwrap().to_owned();
+            drop(response);
+            let short_code = location.trim_start_matches("/clip/").to_owned();
+
+            let response = client.get(&location).dispatch();
+            assert!(response.into_string().unwrap().contains("Statistics"));
+            let response = client.get(format!("/clip/stats/{}", short_code)).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert!(response.into_string().unwrap().contains("<svg"));
+
+            // the owner cookie is only held by the client that created the clip
+            let stranger = backend.client();
+            let response = stranger
+                .get(format!("/clip/stats/{}", short_code))
+                .dispatch();
+            assert_eq!(response.status(), Status::Forbidden);
+        });
+    }
+
+    #[test]
+    fn lets_the_creator_delete_and_restore_a_clip() {
+        use rocket::http::ContentType;
+
+        each_backend(|backend| {
+            let client = backend.client();
+            let response = client
+                .post("/")
+                .header(ContentType::Form)
+                .body("content=mine&title=&expires_at=&password=")
+                .dispatch();
+            let location = response.headers().get_one("Location").unwrap().to_owned();
+            drop(response);
+            let short_code = location.trim_start_matches("/clip/").to_owned();
+            let owner_key = client
+                .cookies()
+                .get(&format!("owner-{}", short_code))
+                .unwrap()
+                .value()
+                .to_owned();
+
+            // the owner key is shown once, right after creating the clip
+            let response = client.get(&location).dispatch();
+            assert!(response.into_string().unwrap().contains(&owner_key));
+            let response = client.get(&location).dispatch();
+            let page = response.into_string().unwrap();
+            assert!(!page.contains(&owner_key));
+            assert!(page.contains(&format!("/clip/delete/{}", short_code)));
+
+            let stranger = backend.client();
+            let response = stranger
+                .post(format!("/clip/delete/{}", short_code))
+                .dispatch();
+            assert_eq!(response.status(), Status::Forbidden);
+
+            let response = client
+                .post(format!("/clip/delete/{}", short_code))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert!(response
+                .into_string()
+                .unwrap()
+                .contains(&format!("/clip/restore/{}", short_code)));
+            let response = client.get(&location).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+            assert!(response
+                .into_string()
+                .unwrap()
+                .contains(&format!("/clip/restore/{}", short_code)));
+            let response = stranger.get(&location).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+
+            let response = stranger
+                .post(format!("/clip/restore/{}", short_code))
+                .header(ContentType::Form)
+                .body("expires_at=")
+                .dispatch();
+            assert_eq!(response.status(), Status::Forbidden);
+            let response = client
+                .post(format!("/clip/restore/{}", short_code))
+                .header(ContentType::Form)
+                .body("expires_at=")
+                .dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+            let response = client.get(&location).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        });
     }
 }
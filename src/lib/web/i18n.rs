@@ -0,0 +1,266 @@
+//! translations of the pages and their messages, from the Fluent files in `locales/` that are
+//! built into the binary
+
+use crate::web::renderer::Renderer;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use fluent_langneg::{negotiate_languages, NegotiationStrategy};
+use rocket::request::{FromRequest, Outcome, Request};
+use rust_embed::RustEmbed;
+use serde::Serialize;
+use std::convert::Infallible;
+use unic_langid::LanguageIdentifier;
+
+/// the cookie the language switcher sets to the chosen locale
+pub const LOCALE_COOKIE: &str = "lang";
+/// the locale of messages missing from the others
+pub const DEFAULT_LOCALE: &str = "en";
+
+#[derive(RustEmbed)]
+#[folder = "locales/"]
+struct Embedded;
+
+/// a locale offered by the language switcher, named in its own language
+#[derive(Debug, Clone, Serialize)]
+pub struct Language {
+    pub id: String,
+    pub name: String,
+}
+
+/// the messages of every shipped locale
+pub struct Localizer {
+    /// the default locale first
+    bundles: Vec<(LanguageIdentifier, FluentBundle<FluentResource>)>,
+}
+
+impl Localizer {
+    /// reads the built-in `locales/<locale>.ftl` files
+    pub fn load() -> Self {
+        let mut bundles = Vec::new();
+        for path in Embedded::iter() {
+            let Some(locale) = path.strip_suffix(".ftl") else {
+                continue;
+            };
+            let locale: LanguageIdentifier = locale.parse().expect("invalid built-in locale name");
+            let file = Embedded::get(&path).expect("listed locales are embedded");
+            let source = String::from_utf8_lossy(&file.data).into_owned();
+            let resource = FluentResource::try_new(source).expect("invalid built-in messages");
+
+            let mut bundle = FluentBundle::new_concurrent(vec![locale.clone()]);
+            // the isolation marks around arguments would end up in attributes and plain text
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .expect("conflicting built-in messages");
+            bundles.push((locale, bundle));
+        }
+        bundles.sort_by_key(|(locale, _)| *locale != DEFAULT_LOCALE);
+        Self { bundles }
+    }
+
+    pub fn default_locale(&self) -> &LanguageIdentifier {
+        &self.bundles[0].0
+    }
+
+    /// the shipped locales, for the language switcher
+    pub fn languages(&self) -> Vec<Language> {
+        self.bundles
+            .iter()
+            .map(|(locale, _)| Language {
+                id: locale.to_string(),
+                name: self.message(locale, "language-name", None),
+            })
+            .collect()
+    }
+
+    /// the shipped locale closest to those `requested`, in order of preference
+    pub fn negotiate(&self, requested: &[LanguageIdentifier]) -> LanguageIdentifier {
+        let available = self
+            .bundles
+            .iter()
+            .map(|(locale, _)| locale)
+            .collect::<Vec<_>>();
+        negotiate_languages(
+            requested,
+            &available,
+            Some(&self.default_locale()),
+            NegotiationStrategy::Lookup,
+        )
+        .first()
+        .map_or_else(
+            || self.default_locale().clone(),
+            |locale| (**locale).clone(),
+        )
+    }
+
+    /// whether `locale` is one of the shipped ones
+    pub fn supports(&self, locale: &LanguageIdentifier) -> bool {
+        self.bundles.iter().any(|(shipped, _)| shipped == locale)
+    }
+
+    /// message `id` in `locale`, or in the default locale when it is not translated
+    pub fn get(
+        &self,
+        locale: &LanguageIdentifier,
+        id: &str,
+        args: Option<&FluentArgs>,
+    ) -> Option<String> {
+        let bundles = self.bundles.iter().filter(|(shipped, _)| shipped == locale);
+        bundles.chain(self.bundles.first()).find_map(|(_, bundle)| {
+            let pattern = bundle.get_message(id)?.value()?;
+            let mut errors = Vec::new();
+            let message = bundle.format_pattern(pattern, args, &mut errors);
+            if !errors.is_empty() {
+                tracing::warn!(id, ?errors, "failed to format message");
+            }
+            Some(message.into_owned())
+        })
+    }
+
+    /// like [`Localizer::get`], but `id` itself when there is no such message, so that text
+    /// which is not a message id, such as a form error from an embedding application, is shown
+    /// as it is
+    pub fn message(
+        &self,
+        locale: &LanguageIdentifier,
+        id: &str,
+        args: Option<&FluentArgs>,
+    ) -> String {
+        self.get(locale, id, args).unwrap_or_else(|| id.to_owned())
+    }
+}
+
+impl std::fmt::Debug for Localizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.bundles.iter().map(|(locale, _)| locale))
+            .finish()
+    }
+}
+
+/// the locale pages are shown in: the one chosen with the language switcher, or the closest to
+/// the browser's `Accept-Language`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Locale(#[serde(serialize_with = "serialize_locale")] LanguageIdentifier);
+
+fn serialize_locale<S: serde::Serializer>(
+    locale: &LanguageIdentifier,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(locale)
+}
+
+impl Locale {
+    pub fn new(locale: LanguageIdentifier) -> Self {
+        Self(locale)
+    }
+
+    pub fn id(&self) -> &LanguageIdentifier {
+        &self.0
+    }
+
+    /// the locale for `req`, also for responders and catchers, which cannot use request guards
+    pub fn of(req: &Request<'_>) -> Self {
+        let Some(localizer) = req
+            .rocket()
+            .state::<Renderer>()
+            .map(|renderer| renderer.localizer())
+        else {
+            return Self::default();
+        };
+        let chosen = req
+            .cookies()
+            .get(LOCALE_COOKIE)
+            .and_then(|cookie| cookie.value().parse::<LanguageIdentifier>().ok())
+            .filter(|locale| localizer.supports(locale));
+        let locale = chosen.unwrap_or_else(|| {
+            let requested = req
+                .headers()
+                .get_one("Accept-Language")
+                .map(fluent_langneg::accepted_languages::parse)
+                .unwrap_or_default();
+            localizer.negotiate(&requested)
+        });
+        Self(locale)
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self(DEFAULT_LOCALE.parse().expect("invalid default locale"))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Locale {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self::of(req))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn picks_the_closest_shipped_locale() {
+        let localizer = Localizer::load();
+        assert_eq!(localizer.default_locale().to_string(), DEFAULT_LOCALE);
+
+        let negotiate = |header: &str| {
+            localizer
+                .negotiate(&fluent_langneg::accepted_languages::parse(header))
+                .to_string()
+        };
+        assert_eq!(negotiate("de-AT,de;q=0.9,en;q=0.8"), "de");
+        assert_eq!(negotiate("fr-FR,en-GB;q=0.5"), "en");
+        assert_eq!(negotiate("fr"), "en");
+        assert_eq!(negotiate(""), "en");
+    }
+
+    #[test]
+    fn translates_messages_with_fallbacks() {
+        let localizer = Localizer::load();
+        let de: LanguageIdentifier = "de".parse().unwrap();
+
+        assert_eq!(localizer.message(&de, "clip-copied", None), "Kopiert!");
+        let mut args = FluentArgs::new();
+        args.set("count", 1);
+        assert_eq!(
+            localizer.message(&de, "clip-views", Some(&args)),
+            "1 Aufruf"
+        );
+        args.set("count", 3);
+        assert_eq!(
+            localizer.message(localizer.default_locale(), "clip-views", Some(&args)),
+            "3 views"
+        );
+        assert_eq!(
+            localizer.message(&de, "no-such-message", None),
+            "no-such-message"
+        );
+
+        // every message of the default locale is translated
+        let languages = localizer.languages();
+        assert_eq!(
+            languages
+                .iter()
+                .map(|l| l.name.as_str())
+                .collect::<Vec<_>>(),
+            ["English", "Deutsch"]
+        );
+        let ids = |locale: &str| {
+            let file = Embedded::get(&format!("{}.ftl", locale)).unwrap();
+            let mut ids = String::from_utf8_lossy(&file.data)
+                .lines()
+                .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+                .filter_map(|line| line.split_once(" =").map(|(id, _)| id.to_owned()))
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids("en"), ids("de"));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::domain::jobs::{JobError, JobName, JobStatus, Scheduler};
+use crate::web::admin::Admin;
+use crate::web::api::ApiError;
+use rocket::serde::json::Json;
+use rocket::State;
+
+/// every background job with its schedule and the outcome of its last run
+#[rocket::get("/jobs")]
+pub fn jobs(scheduler: &State<Scheduler>, _admin: Admin) -> Json<Vec<JobStatus>> {
+    Json(scheduler.statuses())
+}
+
+/// runs a job now and answers once it finished, with the outcome in its status
+#[rocket::post("/jobs/<name>")]
+pub async fn run_job(
+    name: &str,
+    scheduler: &State<Scheduler>,
+    _admin: Admin,
+) -> Result<Json<JobStatus>, ApiError> {
+    let name: JobName = name
+        .parse()
+        .map_err(|_| ApiError::NotFound(Json(format!("no such job: {}", name))))?;
+    match scheduler.run_now(name).await {
+        Ok(status) => Ok(Json(status)),
+        Err(e @ (JobError::Running(_) | JobError::Standby)) => {
+            Err(ApiError::Conflict(Json(e.to_string())))
+        }
+    }
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![jobs, run_job]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::blob;
+    use crate::domain::jobs::{JobName, JobStatus};
+    use crate::test::background_runtime;
+    use crate::web::test::{each_backend, ADMIN_KEY};
+    use rocket::http::{Header, Status};
+
+    #[test]
+    fn lists_and_runs_jobs() {
+        each_backend(|backend| {
+            let client = backend.admin_client();
+            let admin_key = Header::new("x-admin-key", ADMIN_KEY);
+            let blobs = client.rocket().state::<blob::AppBlobStore>().unwrap();
+            let orphan = b"nobody refers to this".to_vec();
+            let key = blob::key_for(&orphan);
+            background_runtime()
+                .block_on(blobs.put(&key, orphan))
+                .unwrap();
+
+            assert_eq!(
+                client.get("/api/admin/jobs").dispatch().status(),
+                Status::Unauthorized
+            );
+            let jobs: Vec<JobStatus> = client
+                .get("/api/admin/jobs")
+                .header(admin_key.clone())
+                .dispatch()
+                .into_json()
+                .unwrap();
+            assert_eq!(jobs.len(), 6);
+            let optimize = jobs
+                .iter()
+                .find(|job| job.name == JobName::Optimize)
+                .unwrap();
+            assert_eq!(optimize.runs, 0);
+            assert!(optimize.next_run.is_some());
+
+            for job in [
+                "optimize",
+                "orphaned_blobs",
+                "api_key_expiry",
+                "trash_purge",
+            ] {
+                let status: JobStatus = client
+                    .post(format!("/api/admin/jobs/{}", job))
+                    .header(admin_key.clone())
+                    .dispatch()
+                    .into_json()
+                    .unwrap();
+                assert_eq!(status.name.as_ref(), job);
+                assert_eq!((status.runs, status.failures), (1, 0), "{}", job);
+                assert!(status.last_run.is_some() && status.last_duration_ms.is_some());
+                assert!(!status.running);
+            }
+
+            // found unreferenced once, the orphan is kept in case its clip is still being created
+            assert!(background_runtime().block_on(blobs.get(&key)).is_ok());
+            let status: JobStatus = client
+                .post("/api/admin/jobs/orphaned_blobs")
+                .header(admin_key.clone())
+                .dispatch()
+                .into_json()
+                .unwrap();
+            assert_eq!(status.runs, 2);
+            assert!(background_runtime().block_on(blobs.get(&key)).is_err());
+
+            let response = client
+                .post("/api/admin/jobs/defragment")
+                .header(admin_key)
+                .dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+        });
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn runs_jobs_on_one_instance_at_a_time() {
+        use crate::config::AppConfig;
+        use crate::data::blob::DatabaseBlobStore;
+        use crate::data::repository::SqliteRepository;
+        use crate::data::AppDatabase;
+        use crate::domain::jobs::{JobsConfig, Schedule, Scheduler};
+        use crate::logging::Redacted;
+        use crate::web::test::config_from;
+        use rocket::local::blocking::Client;
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let path = std::env::temp_dir().join(format!("clipshare-{}.db", uuid::Uuid::new_v4()));
+        let url = format!("sqlite:{}", path.display());
+        let server = || {
+            let database = background_runtime().block_on(async {
+                let database = AppDatabase::new(&url).await.unwrap();
+                database.migrate().await.unwrap();
+                database
+            });
+            let config = AppConfig {
+                admin_key: Some(Redacted(ADMIN_KEY.to_owned())),
+                jobs: JobsConfig {
+                    lease: 1,
+                    expired_cleanup: Schedule::every(1, 0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let blobs = Arc::new(DatabaseBlobStore::new(database.get_pool().clone()));
+            let repository = Arc::new(SqliteRepository::new(database));
+            Client::tracked(crate::rocket(config_from(config, repository, blobs))).unwrap()
+        };
+        let runs = |client: &Client| {
+            let jobs: Vec<JobStatus> = client
+                .get("/api/admin/jobs")
+                .header(Header::new("x-admin-key", ADMIN_KEY))
+                .dispatch()
+                .into_json()
+                .unwrap();
+            jobs.into_iter()
+                .find(|job| job.name == JobName::ExpiredCleanup)
+                .unwrap()
+                .runs
+        };
+
+        // polls rather than sleeping for a lease or two, which is slow and flaky on a busy machine
+        let wait_until = |done: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !done() {
+                assert!(
+                    Instant::now() < deadline,
+                    "timed out waiting for a job to run"
+                );
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        };
+
+        let (first, second) = (server(), server());
+        wait_until(&|| runs(&first) + runs(&second) > 0);
+        let (leader, standby) = match runs(&first) {
+            0 => (second, first),
+            _ => (first, second),
+        };
+        assert!(runs(&leader) > 0);
+        assert_eq!(runs(&standby), 0);
+        let response = standby
+            .post("/api/admin/jobs/expired_cleanup")
+            .header(Header::new("x-admin-key", ADMIN_KEY))
+            .dispatch()
+            .status();
+        assert_eq!(response, Status::Conflict);
+
+        // the standby takes over once the leader is gone
+        let scheduler = leader.rocket().state::<Scheduler>().unwrap();
+        background_runtime().block_on(scheduler.shutdown());
+        wait_until(&|| runs(&standby) > 0);
+
+        drop((leader, standby));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::data::{blob::AppBlobStore, repository::AppRepository};
+use crate::metrics::AppMetrics;
+use crate::web::admin::Admin;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::{Data, Request, Response, State};
+use std::time::Instant;
+
+/// counts requests and times them, labelled by the route that handled them
+pub struct RequestMetrics;
+
+/// when the request arrived, kept in the request-local cache
+struct RequestStart(Option<Instant>);
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(metrics) = req.rocket().state::<AppMetrics>() else {
+            return;
+        };
+        // unmatched paths share one label, so scanners cannot blow up the number of series
+        let route = req
+            .route()
+            .map(|route| route.uri.to_string())
+            .unwrap_or_else(|| "unmatched".to_owned());
+        let method = req.method().as_str();
+
+        metrics
+            .http_requests
+            .with_label_values(&[method, &route, res.status().code.to_string().as_str()])
+            .inc();
+        if let Some(start) = req.local_cache(|| RequestStart(None)).0 {
+            metrics
+                .http_duration
+                .with_label_values(&[method, &route])
+                .observe(start.elapsed().as_secs_f64());
+        }
+    }
+}
+
+/// gauges that are read from storage when scraped
+async fn refresh(metrics: &AppMetrics, repository: &AppRepository, blobs: &AppBlobStore) {
+    if let Some(pool) = repository.pool_stats() {
+        let idle = i64::from(pool.idle);
+        metrics
+            .db_connections
+            .with_label_values(&["idle"])
+            .set(idle);
+        metrics
+            .db_connections
+            .with_label_values(&["active"])
+            .set(i64::from(pool.open) - idle);
+        metrics.db_max_connections.set(i64::from(pool.max));
+    }
+
+    match repository.content_bytes().await {
+        Ok(bytes) => metrics
+            .stored_bytes
+            .with_label_values(&["clips"])
+            .set(bytes as i64),
+        Err(e) => tracing::error!(error = %e, "failed to read clip sizes for metrics"),
+    }
+    match blobs.stored_bytes().await {
+        Ok(Some(bytes)) => metrics
+            .stored_bytes
+            .with_label_values(&["blobs"])
+            .set(bytes as i64),
+        Ok(None) => {}
+        Err(e) => tracing::error!(error = %e, "failed to read blob sizes for metrics"),
+    }
+}
+
+/// metrics in the Prometheus text format
+#[rocket::get("/metrics")]
+pub async fn metrics(
+    metrics: &State<AppMetrics>,
+    repository: &State<AppRepository>,
+    blobs: &State<AppBlobStore>,
+    _admin: Admin,
+) -> (ContentType, String) {
+    refresh(metrics, repository, blobs).await;
+    (
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        metrics.encode(),
+    )
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![metrics]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::web::test::{client, each_backend, ADMIN_KEY};
+    use rocket::http::{ContentType, Header, Status};
+
+    #[test]
+    fn serves_metrics_to_admins_only() {
+        assert_eq!(
+            client().get("/metrics").dispatch().status(),
+            Status::NotFound
+        );
+
+        each_backend(|backend| {
+            let client = backend.admin_client();
+
+            let response = client
+                .post("/")
+                .header(ContentType::Form)
+                .body("content=hello&title=&expires_at=&password=")
+                .dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+
+            let response = client
+                .get("/metrics")
+                .header(Header::new("x-admin-key", "fedcba9876543210"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+
+            let response = client
+                .get("/metrics")
+                .header(Header::new("x-admin-key", ADMIN_KEY))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let body = response.into_string().unwrap();
+            assert!(body.contains("clipshare_clips_created_total 1"), "{}", body);
+            assert!(body.contains(
+                r#"clipshare_http_requests_total{method="POST",route="/",status="303"} 1"#
+            ));
+            assert!(body.contains(r#"clipshare_stored_bytes{store="clips"} 5"#));
+        });
+    }
+}
@@ -1,35 +1,150 @@
+pub mod admin;
 pub mod api;
+pub mod assets;
+pub mod audit;
 pub mod ctx;
 pub mod form;
+pub mod health;
+pub mod helpers;
 pub mod http;
+pub mod i18n;
+pub mod jobs;
+pub mod metrics;
 pub mod renderer;
+pub mod theme;
+pub mod time;
+pub mod trace;
 pub mod views;
 
 pub use views::Views;
 
 pub const PASSWORD_COOKIE: &str = "password";
 
-#[derive(rocket::Responder)]
+/// name of the cookie holding the owner key of a clip created through the web form
+pub fn owner_cookie(short_code: &crate::ShortCode) -> String {
+    format!("owner-{}", short_code.as_str())
+}
+
+#[derive(Debug)]
 pub enum PageError {
-    #[response(status = 500)]
     Serialization(String),
-
-    #[response(status = 500)]
     Render(String),
-
-    #[response(status = 404)]
+    Forbidden(String),
     NotFound(String),
-
-    #[response(status = 500)]
     Internal(String),
 }
 
+/// `message` with the id of the request, for users to quote when reporting an error
+pub fn with_request_id(message: &str, req: &rocket::Request<'_>) -> String {
+    format!("{} (request id: {})", message, trace::RequestId::of(req))
+}
+
+/// `message` on the templated error page for `status`, a message id or text shown as it is
+#[derive(Debug)]
+pub struct ErrorResponse {
+    pub status: rocket::http::Status,
+    pub message: String,
+}
+
+impl ErrorResponse {
+    pub fn new(status: rocket::http::Status, message: &str) -> Self {
+        Self {
+            status,
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl<'r> rocket::response::Responder<'r, 'static> for ErrorResponse {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        use rocket::response::{content::RawHtml, status::Custom};
+
+        let Some(renderer) = req.rocket().state::<renderer::Renderer>() else {
+            return Custom(self.status, with_request_id(&self.message, req)).respond_to(req);
+        };
+        let prefs = theme::Prefs::of(req);
+        let message = renderer.message(&prefs.locale, &self.message);
+        let reason = renderer
+            .localizer()
+            .get(
+                prefs.locale.id(),
+                &format!("status-{}", self.status.code),
+                None,
+            )
+            .unwrap_or_else(|| self.status.reason_lossy().to_owned());
+        let request_id = trace::RequestId::of(req);
+        let context = ctx::ErrorPage::new(self.status, &reason, &message, request_id.as_str());
+        let page = renderer.render(context, &prefs, &[]).unwrap_or_else(|e| {
+            tracing::error!(error = %e, "failed to render error page");
+            renderer::fallback_page(&reason, &with_request_id(&message, req), None)
+        });
+        Custom(self.status, RawHtml(page)).respond_to(req)
+    }
+}
+
+impl<'r> rocket::response::Responder<'r, 'static> for PageError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        use rocket::http::Status;
+
+        let (status, message) = match self {
+            Self::Forbidden(msg) => (Status::Forbidden, msg),
+            Self::NotFound(msg) => (Status::NotFound, msg),
+            // these carry the underlying error, which is logged rather than shown outside of dev
+            // mode, on a page that does not need the templates that just failed
+            Self::Serialization(msg) | Self::Render(msg) => {
+                tracing::error!(error = %msg, "failed to render page");
+                let renderer = req.rocket().state::<renderer::Renderer>();
+                let dev = renderer.is_some_and(|renderer| renderer.is_dev());
+                let locale = i18n::Locale::of(req);
+                let (reason, message) = match renderer {
+                    Some(renderer) => (
+                        renderer.message(&locale, "status-500"),
+                        renderer.message(&locale, "error-server"),
+                    ),
+                    None => (
+                        Status::InternalServerError.reason_lossy().to_owned(),
+                        "server error".to_owned(),
+                    ),
+                };
+                let page = renderer::fallback_page(
+                    &reason,
+                    &with_request_id(&message, req),
+                    dev.then_some(msg.as_str()),
+                );
+                return rocket::response::status::Custom(
+                    Status::InternalServerError,
+                    rocket::response::content::RawHtml(page),
+                )
+                .respond_to(req);
+            }
+            Self::Internal(msg) => (Status::InternalServerError, msg),
+        };
+        ErrorResponse::new(status, &message).respond_to(req)
+    }
+}
+
 impl From<handlebars::RenderError> for PageError {
     fn from(err: handlebars::RenderError) -> Self {
         PageError::Render(format!("{}", err))
     }
 }
 
+impl From<renderer::RenderError> for PageError {
+    fn from(err: renderer::RenderError) -> Self {
+        // with its causes, such as the template syntax error behind a failed render
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(cause) = source {
+            message = format!("{}: {}", message, cause);
+            source = cause.source();
+        }
+        match err {
+            renderer::RenderError::Serialization(_) => PageError::Serialization(message),
+            _ => PageError::Render(message),
+        }
+    }
+}
+
 impl From<serde_json::Error> for PageError {
     fn from(err: serde_json::Error) -> Self {
         PageError::Serialization(format!("{}", err))
@@ -38,31 +153,183 @@ impl From<serde_json::Error> for PageError {
 
 #[cfg(test)]
 pub mod test {
-    use crate::test::async_runtime;
+    use crate::config::AppConfig;
+    use crate::data::blob::AppBlobStore;
+    use crate::data::repository::AppRepository;
+    use crate::test::background_runtime;
     use crate::RocketConfig;
     use rocket::local::blocking::Client;
 
-    pub fn config() -> RocketConfig {
-        use crate::web::{renderer::Renderer, views::Views};
-        let rt = async_runtime();
-        let renderer = Renderer::new("templates/".into());
-        let database = crate::data::test::new_db(rt.handle());
-        let maintenance = crate::domain::maintenance::Maintenance::spawn(
-            database.get_pool().clone(),
+    pub fn config_with(repository: AppRepository, blobs: AppBlobStore) -> RocketConfig {
+        config_from(AppConfig::default(), repository, blobs)
+    }
+
+    /// a server with its background work started from `config`
+    pub fn config_from(
+        config: AppConfig,
+        repository: AppRepository,
+        blobs: AppBlobStore,
+    ) -> RocketConfig {
+        use crate::domain::jobs::{JobContext, Scheduler};
+        use crate::web::{assets::Assets, renderer::Renderer, views::Views};
+        let rt = background_runtime();
+        let renderer = Renderer::new(None).unwrap();
+        let assets = Assets::from_config(&config).unwrap();
+        let metrics: crate::metrics::AppMetrics = Default::default();
+        let context = JobContext {
+            repository: repository.clone(),
+            blobs: blobs.clone(),
+            metrics: metrics.clone(),
+            trash_retention: config.trash_retention(),
+            retention: config.retention.clone(),
+        };
+        let scheduler = Scheduler::spawn(context, &config.jobs, rt.handle().clone());
+        let views = Views::new(
+            repository.clone(),
+            metrics.clone(),
+            config.views_flush_interval(),
+            config.views_batch_size,
             rt.handle().clone(),
         );
-        let views = Views::new(database.get_pool().clone(), rt.handle().clone());
 
         RocketConfig {
+            figment: rocket::Config::figment(),
+            config,
             renderer,
-            database,
+            assets,
+            repository,
+            blobs,
+            metrics,
             views,
-            maintenance,
+            scheduler,
+        }
+    }
+
+    /// the storage a test server runs on
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Backend {
+        Memory,
+        #[cfg(feature = "sqlite")]
+        Sqlite,
+        /// on the server of [`postgres_url`]
+        ///
+        /// [`postgres_url`]: crate::data::test::postgres_url
+        #[cfg(feature = "postgres")]
+        Postgres,
+    }
+
+    impl Backend {
+        /// every backend of this build, Postgres only when a server was found
+        pub fn all() -> Vec<Self> {
+            #[allow(unused_mut)]
+            let mut backends = vec![Self::Memory];
+            #[cfg(feature = "sqlite")]
+            backends.push(Self::Sqlite);
+            #[cfg(feature = "postgres")]
+            if crate::data::test::postgres_url().is_some() {
+                backends.push(Self::Postgres);
+            }
+            backends
+        }
+
+        /// a fresh repository and blob store
+        pub fn storage(self) -> (AppRepository, AppBlobStore) {
+            use std::sync::Arc;
+
+            match self {
+                Self::Memory => {
+                    use crate::data::blob::MemoryBlobStore;
+                    use crate::data::repository::MemoryRepository;
+
+                    (
+                        Arc::new(MemoryRepository::default()),
+                        Arc::new(MemoryBlobStore::default()),
+                    )
+                }
+                #[cfg(feature = "sqlite")]
+                Self::Sqlite => {
+                    use crate::data::blob::DatabaseBlobStore;
+                    use crate::data::repository::SqliteRepository;
+
+                    let database = crate::data::test::new_db(background_runtime().handle());
+                    let blobs = Arc::new(DatabaseBlobStore::new(database.get_pool().clone()));
+                    (Arc::new(SqliteRepository::new(database)), blobs)
+                }
+                #[cfg(feature = "postgres")]
+                Self::Postgres => {
+                    use crate::data::blob::DatabaseBlobStore;
+                    use crate::data::repository::PostgresRepository;
+
+                    let url = crate::data::test::postgres_url().expect("no Postgres server");
+                    let database =
+                        crate::data::test::new_pg_db(background_runtime().handle(), &url);
+                    let blobs = Arc::new(DatabaseBlobStore::new(database.get_pool().clone()));
+                    (Arc::new(PostgresRepository::new(database)), blobs)
+                }
+            }
+        }
+
+        /// a server on fresh storage of this backend, started from `config`
+        pub fn config_from(self, config: AppConfig) -> RocketConfig {
+            let (repository, blobs) = self.storage();
+            config_from(config, repository, blobs)
+        }
+
+        pub fn config(self) -> RocketConfig {
+            self.config_from(AppConfig::default())
+        }
+
+        pub fn client(self) -> Client {
+            Client::tracked(crate::rocket(self.config())).expect("failed to build rocket client")
+        }
+
+        /// client for a server whose admin endpoints take [`ADMIN_KEY`] in the `x-admin-key`
+        /// header
+        pub fn admin_client(self) -> Client {
+            let mut config = self.config();
+            config.config.admin_key = Some(crate::logging::Redacted(ADMIN_KEY.to_owned()));
+            Client::tracked(crate::rocket(config)).expect("failed to build rocket client")
+        }
+    }
+
+    /// SQLite when it is built, as it is what `httpd` runs on by default
+    impl Default for Backend {
+        fn default() -> Self {
+            #[cfg(feature = "sqlite")]
+            return Self::Sqlite;
+            #[cfg(not(feature = "sqlite"))]
+            Self::Memory
         }
     }
 
+    /// runs `test` on each of [`Backend::all`], for the tests of what the storage affects
+    pub fn each_backend(test: impl Fn(Backend)) {
+        for backend in Backend::all() {
+            // shown along with the failure of the test
+            eprintln!("testing on the {:?} backend", backend);
+            test(backend);
+        }
+    }
+
+    /// a server on the default backend, for the tests that do not depend on the storage
+    pub fn config() -> RocketConfig {
+        Backend::default().config()
+    }
+
     pub fn client() -> Client {
-        let config = config();
-        Client::tracked(crate::rocket(config)).expect("failed to build rocket client")
+        Backend::default().client()
+    }
+
+    /// the key [`admin_client`] enables the admin endpoints with
+    pub const ADMIN_KEY: &str = "0123456789abcdef";
+
+    /// [`Backend::admin_client`] on the default backend
+    pub fn admin_client() -> Client {
+        Backend::default().admin_client()
+    }
+
+    /// client for a server without any database, as used when embedding ClipShare
+    pub fn memory_client() -> Client {
+        Backend::Memory.client()
     }
 }
@@ -1,44 +1,168 @@
-use crate::web::ctx;
+use crate::web::i18n::{Locale, Localizer};
+use crate::web::theme::Prefs;
+use crate::web::{assets::Assets, ctx, helpers};
+use handlebars::HelperDef;
+use rust_embed::RustEmbed;
+use serde_json::{Map, Value};
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum RenderError {
     #[error("rendering error: {0}")]
     Render(#[from] handlebars::RenderError),
+
+    #[error("template error: {0}")]
+    Template(#[from] Box<handlebars::TemplateError>),
+
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+struct EmbeddedTemplates;
+
+/// a link in the page footer, set as the `footer_links` global
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FooterLink {
+    pub title: String,
+    pub url: String,
 }
 
-pub struct Renderer<'a>(handlebars::Handlebars<'a>);
+/// the templates, with the built-in helpers from [`helpers`] and whatever helpers, partials and
+/// globals the embedding application adds before handing it to `RocketConfig`
+pub struct Renderer<'a> {
+    handlebars: handlebars::Handlebars<'a>,
+    /// available to every template as `_globals`
+    globals: Map<String, Value>,
+    localizer: Arc<Localizer>,
+}
 
 impl<'a> Renderer<'a> {
-    pub fn new(template_dir: std::path::PathBuf) -> Self {
+    /// the built-in templates, replaced or added to by the `.hbs` files in `template_dir`. fails
+    /// if one of those cannot be read or has a syntax error
+    pub fn new(template_dir: Option<std::path::PathBuf>) -> Result<Self, RenderError> {
+        Self::build(template_dir, false)
+    }
+
+    /// for development: templates in `template_dir` are read again on every render, and
+    /// rendering errors are shown on the error page. templates added after the start still need
+    /// a restart, and so does fixing one that failed to load at the start
+    pub fn dev(template_dir: std::path::PathBuf) -> Result<Self, RenderError> {
+        Self::build(Some(template_dir), true)
+    }
+
+    fn build(
+        template_dir: Option<std::path::PathBuf>,
+        dev_mode: bool,
+    ) -> Result<Self, RenderError> {
         let mut renderer = handlebars::Handlebars::new();
-        renderer
-            .register_templates_directory(".hbs", &template_dir)
-            .expect("failed to register handlebars templates");
+        for path in EmbeddedTemplates::iter() {
+            let Some(name) = path.strip_suffix(".hbs") else {
+                continue;
+            };
+            let file = EmbeddedTemplates::get(&path).expect("listed templates are embedded");
+            renderer
+                .register_template_string(name, String::from_utf8_lossy(&file.data))
+                .expect("failed to register built-in template");
+        }
+        // has to come before the directory, so that its files are remembered
+        renderer.set_dev_mode(dev_mode);
+        if let Some(template_dir) = template_dir {
+            renderer
+                .register_templates_directory(".hbs", &template_dir)
+                .map_err(Box::new)?;
+        }
+        let localizer = Arc::new(Localizer::load());
+        helpers::register_builtins(&mut renderer, localizer.clone());
+
+        let mut this = Self {
+            handlebars: renderer,
+            globals: Map::new(),
+            localizer,
+        };
+        this.set_global("site_name", "ClipStash");
+        this.set_global("base_url", "");
+        this.set_global("footer_links", Vec::<FooterLink>::new());
+        this.set_global("languages", this.localizer.languages());
+        Ok(this)
+    }
+
+    /// adds a helper, or replaces the one of the same name
+    pub fn register_helper<H>(&mut self, name: &str, helper: H)
+    where
+        H: HelperDef + Send + Sync + 'a,
+    {
+        self.handlebars.register_helper(name, Box::new(helper));
+    }
+
+    /// adds a partial, or replaces the template of the same name, such as `footer` or `header`
+    pub fn register_partial(&mut self, name: &str, source: &str) -> Result<(), RenderError> {
+        // in dev mode the template's file would otherwise be read again over the partial
+        self.handlebars.unregister_template(name);
+        self.handlebars
+            .register_partial(name, source)
+            .map_err(Box::new)?;
+        Ok(())
+    }
+
+    /// sets `{{_globals.<name>}}`. `site_name`, `base_url` (prefixed to the links `clip_url`
+    /// builds) and `footer_links` (a list of [`FooterLink`]) are used by the shipped templates
+    pub fn set_global<V: serde::Serialize>(&mut self, name: &str, value: V) {
+        let value = serde_json::to_value(value).expect("failed to convert global to value");
+        self.globals.insert(name.to_owned(), value);
+    }
+
+    /// lets the `asset` helper link the hashed names of the static files
+    pub fn set_assets(&mut self, assets: &Assets) {
+        self.set_global("assets", assets.urls());
+    }
+
+    /// the translations behind the `t` helper
+    pub fn localizer(&self) -> &Localizer {
+        &self.localizer
+    }
+
+    /// message `id` in `locale`, for text built outside of the templates
+    pub fn message(&self, locale: &Locale, id: &str) -> String {
+        self.localizer.message(locale.id(), id, None)
+    }
 
-        Self(renderer)
+    /// whether templates are read again on every render, see [`Renderer::dev`]
+    pub fn is_dev(&self) -> bool {
+        self.handlebars.dev_mode()
     }
 
-    fn convert_to_value<S>(serializable: &S) -> serde_json::Value
+    fn convert_to_value<S>(serializable: &S) -> Result<serde_json::Value, RenderError>
     where
         S: serde::Serialize + std::fmt::Debug,
     {
-        // serde_json::to_value(&serializable).expect("failed to convert structure to value")
-        // the code above is not wrong but according to: https://rust-lang.github.io/rust-clippy/master/index.html#/needless_borrows_for_generic_args
+        // serde_json::to_value(&serializable) is not wrong but according to: https://rust-lang.github.io/rust-clippy/master/index.html#/needless_borrows_for_generic_args
         // The lint cannot tell when the implementation of a trait for &T and T do different things. Removing a borrow in such a case can change the semantics of the code.
-        serde_json::to_value(serializable).expect("failed to convert structure to value")
+        Ok(serde_json::to_value(serializable)?)
     }
 
-    pub fn render<PageCtx>(&self, context: PageCtx, errors: &[&str]) -> String
+    /// the page for `context` in the viewer's locale and theme. `errors` are message ids, or text
+    /// shown as it is
+    pub fn render<PageCtx>(
+        &self,
+        context: PageCtx,
+        prefs: &Prefs,
+        errors: &[&str],
+    ) -> Result<String, RenderError>
     where
         PageCtx: ctx::PageContext + serde::Serialize + std::fmt::Debug,
     {
-        let mut value = Self::convert_to_value(&context);
+        let mut value = Self::convert_to_value(&context)?;
 
         if let Some(value) = value.as_object_mut() {
             value.insert("_errors".into(), errors.into());
             value.insert("_title".into(), context.title().into());
             value.insert("_base".into(), context.parent().into());
+            value.insert("_globals".into(), self.globals.clone().into());
+            value.insert("_locale".into(), Self::convert_to_value(&prefs.locale)?);
+            value.insert("_theme".into(), prefs.theme.as_str().into());
         }
 
         self.do_render(context.template_path(), value)
@@ -47,28 +171,142 @@ impl<'a> Renderer<'a> {
     pub fn render_with_data<PageCtx, D>(
         &self,
         context: PageCtx,
+        prefs: &Prefs,
         data: (&str, D),
         errors: &[&str],
-    ) -> String
+    ) -> Result<String, RenderError>
     where
         PageCtx: ctx::PageContext + serde::Serialize + std::fmt::Debug,
         D: serde::Serialize + std::fmt::Debug,
     {
-        use handlebars::to_json;
-
-        let mut value = Self::convert_to_value(&context);
+        let mut value = Self::convert_to_value(&context)?;
 
         if let Some(value) = value.as_object_mut() {
             value.insert("_errors".into(), errors.into());
             value.insert("_title".into(), context.title().into());
             value.insert("_base".into(), context.parent().into());
-            value.insert(data.0.into(), to_json(data.1));
+            value.insert("_globals".into(), self.globals.clone().into());
+            value.insert("_locale".into(), Self::convert_to_value(&prefs.locale)?);
+            value.insert("_theme".into(), prefs.theme.as_str().into());
+            value.insert(data.0.into(), Self::convert_to_value(&data.1)?);
         }
 
         self.do_render(context.template_path(), value)
     }
 
-    fn do_render(&self, path: &str, ctx: serde_json::Value) -> String {
-        self.0.render(path, &ctx).expect("error rendering template")
+    fn do_render(&self, path: &str, ctx: serde_json::Value) -> Result<String, RenderError> {
+        Ok(self.handlebars.render(path, &ctx)?)
+    }
+}
+
+/// the page for when rendering failed, which does not depend on the templates. `detail` is the
+/// error itself, for dev mode only
+pub fn fallback_page(title: &str, message: &str, detail: Option<&str>) -> String {
+    use handlebars::html_escape;
+
+    let detail = detail
+        .map(|detail| format!("<pre>{}</pre>", html_escape(detail)))
+        .unwrap_or_default();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"UTF-8\"><title>{0}</title></head>\n\
+         <body>\n<h1>{0}</h1>\n<p>{1}</p>\n{2}<p><a href=\"/\">Home</a></p>\n</body>\n</html>\n",
+        html_escape(title),
+        html_escape(message),
+        detail
+    )
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use handlebars::handlebars_helper;
+
+    #[test]
+    fn brands_pages_with_helpers_partials_and_globals() {
+        handlebars_helper!(shout: |text: str| text.to_uppercase());
+
+        let mut renderer = Renderer::new(None).unwrap();
+        renderer.set_global("site_name", "Acme Paste");
+        renderer.set_global(
+            "footer_links",
+            vec![FooterLink {
+                title: "Imprint".to_owned(),
+                url: "https://acme.example/imprint".to_owned(),
+            }],
+        );
+        renderer.register_helper("shout", shout);
+        renderer
+            .register_partial("header", "<h1>{{shout _globals.site_name}}</h1>")
+            .unwrap();
+
+        let page = renderer
+            .render(ctx::Home::default(), &Prefs::default(), &[])
+            .unwrap();
+        assert!(page.contains("<title>Acme Paste - "));
+        assert!(page.contains("<h1>ACME PASTE</h1>"));
+        assert!(page.contains(r#"<a href="https://acme.example/imprint">Imprint</a>"#));
+
+        assert!(renderer.register_partial("broken", "{{#if}}").is_err());
+    }
+
+    #[derive(Debug, serde::Serialize)]
+    struct Page {
+        name: &'static str,
+    }
+
+    impl ctx::PageContext for Page {
+        fn title(&self) -> &str {
+            "Page"
+        }
+
+        fn template_path(&self) -> &str {
+            "page"
+        }
+
+        fn parent(&self) -> &str {
+            "base"
+        }
+    }
+
+    #[test]
+    fn reloads_templates_in_dev_mode() {
+        let dir = std::env::temp_dir().join(format!("clipshare-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let template = dir.join("page.hbs");
+        std::fs::write(&template, "hello {{name}}").unwrap();
+
+        let renderer = Renderer::dev(dir.clone()).unwrap();
+        let page = || renderer.render(Page { name: "<you>" }, &Prefs::default(), &[]);
+        assert_eq!(page().unwrap(), "hello &lt;you&gt;");
+
+        std::fs::write(&template, "goodbye {{name}}").unwrap();
+        assert_eq!(page().unwrap(), "goodbye &lt;you&gt;");
+
+        std::fs::write(&template, "{{#if name}}unclosed").unwrap();
+        assert!(matches!(page(), Err(RenderError::Render(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_syntax_errors_in_the_template_directory() {
+        let dir = std::env::temp_dir().join(format!("clipshare-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("page.hbs"), "{{#if name}}unclosed").unwrap();
+
+        for dev in [false, true] {
+            let renderer = match dev {
+                true => Renderer::dev(dir.clone()),
+                false => Renderer::new(Some(dir.clone())),
+            };
+            match renderer {
+                Err(RenderError::Template(e)) => {
+                    assert_eq!(e.template_name.as_deref(), Some("page"))
+                }
+                _ => panic!("the broken template was not reported"),
+            }
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
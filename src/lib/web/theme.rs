@@ -0,0 +1,78 @@
+use crate::web::i18n::Locale;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// the cookie the theme toggle sets to the chosen theme
+pub const THEME_COOKIE: &str = "theme";
+
+/// the colors of the pages and of highlighted clips
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    /// light or dark, following the `prefers-color-scheme` of the browser
+    #[default]
+    Auto,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Light, Theme::Dark, Theme::Auto];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// the theme for `req`, also for responders and catchers, which cannot use request guards
+    pub fn of(req: &Request<'_>) -> Self {
+        req.cookies()
+            .get(THEME_COOKIE)
+            .and_then(|cookie| cookie.value().parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for Theme {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|theme| theme.as_str() == name)
+            .ok_or(())
+    }
+}
+
+/// what the viewer chose for how pages are shown, for [`Renderer::render`]
+///
+/// [`Renderer::render`]: crate::web::renderer::Renderer::render
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Prefs {
+    pub locale: Locale,
+    pub theme: Theme,
+}
+
+impl Prefs {
+    /// the preferences for `req`, also for responders and catchers
+    pub fn of(req: &Request<'_>) -> Self {
+        Self {
+            locale: Locale::of(req),
+            theme: Theme::of(req),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Prefs {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self::of(req))
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::Serialize;
+use std::convert::Infallible;
+
+/// the cookie the pages set to the IANA name of the browser's timezone
+pub const TIME_ZONE_COOKIE: &str = "tz";
+
+/// the timezone pages show times in, UTC until the browser told us its own
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ViewerTimeZone(#[serde(serialize_with = "serialize_tz")] Tz);
+
+impl ViewerTimeZone {
+    pub fn into_inner(self) -> Tz {
+        self.0
+    }
+}
+
+impl Default for ViewerTimeZone {
+    fn default() -> Self {
+        Self(Tz::UTC)
+    }
+}
+
+fn serialize_tz<S: serde::Serializer>(tz: &Tz, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(tz.name())
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ViewerTimeZone {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let tz = req
+            .cookies()
+            .get(TIME_ZONE_COOKIE)
+            .and_then(|cookie| cookie.value().parse::<Tz>().ok())
+            .unwrap_or(Tz::UTC);
+        Outcome::Success(Self(tz))
+    }
+}
+
+/// `time` in `tz`, to the minute
+pub fn absolute(time: DateTime<Utc>, tz: Tz) -> String {
+    time.with_timezone(&tz)
+        .format("%Y-%m-%d %H:%M %Z")
+        .to_string()
+}
+
+/// "1 view", "3 views"
+pub fn pluralize(count: u64, singular: &str, plural: &str) -> String {
+    match count {
+        1 => format!("1 {}", singular),
+        count => format!("{} {}", count, plural),
+    }
+}
+
+/// how far `time` is from `now` in its largest whole unit, as the count, the unit's plural
+/// ("hours") and whether it is ahead. `None` within a minute either way
+pub fn relative_span(time: DateTime<Utc>, now: DateTime<Utc>) -> Option<(u64, &'static str, bool)> {
+    const UNITS: [(i64, &str); 5] = [
+        (365 * 86400, "years"),
+        (30 * 86400, "months"),
+        (86400, "days"),
+        (3600, "hours"),
+        (60, "minutes"),
+    ];
+
+    let seconds = (time - now).num_seconds();
+    UNITS
+        .iter()
+        .find(|(unit, _)| seconds.abs() >= *unit)
+        .map(|(unit, name)| ((seconds.abs() / unit) as u64, *name, seconds >= 0))
+}
+
+/// `time` as seen from `now` in its largest whole unit: "in 3 hours", "2 days ago", or "just now"
+/// within a minute either way
+pub fn relative(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    match relative_span(time, now) {
+        None => "just now".to_owned(),
+        Some((count, unit, ahead)) => {
+            let span = pluralize(count, unit.trim_end_matches('s'), unit);
+            match ahead {
+                true => format!("in {}", span),
+                false => format!("{} ago", span),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn describes_times_relative_to_now() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let cases = [
+            (Duration::seconds(30), "just now"),
+            (Duration::seconds(-30), "just now"),
+            (Duration::minutes(1), "in 1 minute"),
+            (Duration::minutes(-90), "1 hour ago"),
+            (Duration::hours(3) + Duration::minutes(59), "in 3 hours"),
+            (Duration::days(-2), "2 days ago"),
+            (Duration::days(45), "in 1 month"),
+            (Duration::days(800), "in 2 years"),
+        ];
+        for (offset, expected) in cases {
+            assert_eq!(relative(now + offset, now), expected);
+        }
+    }
+
+    #[test]
+    fn shows_times_in_the_viewers_timezone() {
+        let time = Utc.with_ymd_and_hms(2024, 7, 1, 12, 30, 0).unwrap();
+        assert_eq!(absolute(time, Tz::UTC), "2024-07-01 12:30 UTC");
+        assert_eq!(
+            absolute(time, chrono_tz::Europe::Berlin),
+            "2024-07-01 14:30 CEST"
+        );
+        assert_eq!(pluralize(1, "view", "views"), "1 view");
+        assert_eq!(pluralize(0, "view", "views"), "0 views");
+    }
+}
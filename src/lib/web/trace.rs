@@ -0,0 +1,159 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::route::{Handler, Outcome as RouteOutcome};
+use rocket::{Data, Request, Response, Route};
+use std::convert::Infallible;
+use std::fmt;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// longest request id accepted from a client
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// identifies a request in logs and responses. taken from the `X-Request-Id` header when the
+/// client or a proxy sent a usable one, generated otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().simple().to_string())
+    }
+
+    /// ids from outside are kept short and free of anything that would garble a log line
+    fn parse(id: &str) -> Option<Self> {
+        let valid = !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LENGTH
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+        valid.then(|| Self(id.to_owned()))
+    }
+
+    /// the id of `req`, assigned on first use
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r RequestId {
+        req.local_cache(|| {
+            req.headers()
+                .get_one(REQUEST_ID_HEADER)
+                .and_then(Self::parse)
+                .unwrap_or_else(Self::generate)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r RequestId {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId::of(req))
+    }
+}
+
+/// assigns every request its id, echoes it in the `X-Request-Id` response header and logs the
+/// outcome of the request
+pub struct RequestTracing;
+
+#[rocket::async_trait]
+impl Fairing for RequestTracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        RequestId::of(req);
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let id = RequestId::of(req);
+        res.set_header(Header::new(REQUEST_ID_HEADER, id.as_str().to_owned()));
+
+        let status = res.status().code;
+        // only the path, the query may carry anything
+        let path = req.uri().path();
+        if status >= 500 {
+            tracing::error!(request_id = %id, method = %req.method(), %path, status, "request failed");
+        } else {
+            tracing::info!(request_id = %id, method = %req.method(), %path, status, "request handled");
+        }
+    }
+}
+
+/// runs a route handler inside a span carrying the request id, so that everything logged while
+/// handling the request, down to the queries, can be traced back to it
+#[derive(Clone)]
+struct Traced(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for Traced {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> RouteOutcome<'r> {
+        let span = tracing::info_span!(
+            "request",
+            request_id = %RequestId::of(req),
+            method = %req.method(),
+            route = %req.route().map(|route| route.uri.to_string()).unwrap_or_default(),
+        );
+        self.0.handle(req, data).instrument(span).await
+    }
+}
+
+/// `routes` with their handlers wrapped in a request span
+pub fn traced(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(Traced(route.handler));
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::web::test::client;
+    use rocket::http::Status;
+
+    #[test]
+    fn propagates_or_assigns_request_ids() {
+        let client = client();
+
+        let response = client
+            .get("/")
+            .header(Header::new(REQUEST_ID_HEADER, "edge-1234"))
+            .dispatch();
+        assert_eq!(
+            response.headers().get_one(REQUEST_ID_HEADER),
+            Some("edge-1234")
+        );
+
+        // an unusable id is replaced rather than echoed
+        let response = client
+            .get("/clip/missing")
+            .header(Header::new(REQUEST_ID_HEADER, "bad id\n"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let id = response
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_owned();
+        assert_eq!(id.len(), 32);
+        assert!(response.into_string().unwrap().contains(&id));
+    }
+}
@@ -1,114 +1,429 @@
-use crate::data::DatabasePool;
-use crate::service::{self, ServiceError};
+use crate::data::repository::AppRepository;
+use crate::domain::audit::Actor;
+use crate::domain::health::{ComponentHealth, Heartbeat};
+use crate::domain::stats::{self, DailyViews, ViewSource};
+use crate::metrics::AppMetrics;
+use crate::service;
 use crate::ShortCode;
-use crossbeam_channel::TryRecvError;
-use crossbeam_channel::{unbounded, Sender};
+use chrono::{NaiveDate, Utc};
 use parking_lot::Mutex;
+use rocket::request::{FromRequest, Outcome, Request};
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
 
-type ViewStore = Arc<Mutex<HashMap<ShortCode, u32>>>;
+/// views waiting in the channel before `view` has to wait for the writer
+const CHANNEL_CAPACITY: usize = 1024;
 
-#[derive(Debug, thiserror::Error)]
-enum ViewsError {
-    #[error("service error: {0}")]
-    Service(#[from] ServiceError),
+type PendingViews = Arc<Mutex<HashMap<ShortCode, u32>>>;
 
-    #[error("communication error: {0}")]
-    Channel(#[from] crossbeam_channel::SendError<ViewMsg>),
+/// who is viewing a clip, as far as the view statistics need to know
+#[derive(Debug, Clone, Default)]
+pub struct Viewer {
+    pub address: Option<IpAddr>,
+    pub user_agent: String,
+    /// host of the referring page, when it is not this server
+    pub referrer: Option<String>,
 }
 
-enum ViewMsg {
-    Commit,
-    View(ShortCode, u32),
-}
+impl Viewer {
+    pub fn is_bot(&self) -> bool {
+        stats::is_bot(&self.user_agent)
+    }
 
-pub struct Views {
-    tx: Sender<ViewMsg>,
+    /// the viewer as named in the audit log
+    pub fn actor(&self) -> Actor {
+        self.address.map_or(Actor::Anonymous, Actor::Address)
+    }
 }
 
-impl Views {
-    fn commit_views(
-        store: ViewStore,
-        handle: Handle,
-        pool: DatabasePool,
-    ) -> Result<(), ViewsError> {
-        let store = Arc::clone(&store);
-
-        let store: Vec<(ShortCode, u32)> = {
-            let mut store = store.lock();
-            let store_vec = store.iter().map(|(k, v)| (k.clone(), *v)).collect();
-            store.clear();
-            store_vec
-        };
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Viewer {
+    type Error = Infallible;
 
-        handle.block_on(async move {
-            let transaction = service::action::begin_transaction(&pool).await?;
-            for (short_code, views) in store {
-                if let Err(e) = service::action::increase_views(&short_code, views, &pool).await {
-                    eprintln!("error increasing views: {}", e);
-                }
-            }
-            Ok(service::action::end_transaction(transaction).await?)
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let own_host = req
+            .host()
+            .map(|host| host.domain().as_str().to_ascii_lowercase());
+        let referrer = req
+            .headers()
+            .get_one("Referer")
+            .and_then(stats::referrer_host)
+            .filter(|host| Some(host) != own_host.as_ref());
+
+        Outcome::Success(Self {
+            address: req.client_ip(),
+            user_agent: req
+                .headers()
+                .get_one("User-Agent")
+                .unwrap_or_default()
+                .to_owned(),
+            referrer,
         })
     }
+}
 
-    fn process_msg(
-        msg: ViewMsg,
-        store: ViewStore,
-        handle: Handle,
-        pool: DatabasePool,
-    ) -> Result<(), ViewsError> {
-        match msg {
-            ViewMsg::Commit => Self::commit_views(store.clone(), handle.clone(), pool.clone())?,
-            ViewMsg::View(short_code, views) => {
-                let mut view_count = store.lock();
-                let view_count = view_count.entry(short_code).or_insert(0);
-                *view_count += views;
+struct ViewEvent {
+    short_code: ShortCode,
+    source: ViewSource,
+    viewer: Viewer,
+}
+
+enum ViewMsg {
+    View(Box<ViewEvent>),
+    /// commits whatever is pending, then stops the writer
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// state owned by the writer task
+struct Writer {
+    repository: AppRepository,
+    metrics: AppMetrics,
+    heartbeat: Arc<Heartbeat>,
+    pending: PendingViews,
+    stats: HashMap<(ShortCode, NaiveDate), DailyViews>,
+    /// salt of the current day, visitors are hashed with it as their views arrive
+    salt: Option<(NaiveDate, Vec<u8>)>,
+}
+
+impl Writer {
+    async fn salt(&mut self, day: NaiveDate) -> Option<&[u8]> {
+        if !matches!(&self.salt, Some((salted, _)) if *salted == day) {
+            match service::action::view_salt(day, self.repository.as_ref()).await {
+                Ok(salt) => self.salt = Some((day, salt)),
+                Err(e) => {
+                    tracing::error!(error = %e, "failed to load view salt");
+                    return None;
+                }
             }
         }
+        self.salt.as_ref().map(|(_, salt)| salt.as_slice())
+    }
+
+    /// counts a view, returning whether the batch is full
+    async fn record(&mut self, event: ViewEvent, batch_size: usize) -> bool {
+        let day = Utc::now().date_naive();
+        let ViewEvent {
+            short_code,
+            source,
+            viewer,
+        } = event;
 
-        Ok(())
+        let visitor = match viewer.address {
+            Some(address) => self
+                .salt(day)
+                .await
+                .map(|salt| stats::visitor_hash(salt, &address.to_string(), &viewer.user_agent)),
+            None => None,
+        };
+        self.stats
+            .entry((short_code.clone(), day))
+            .or_insert_with(|| DailyViews::new(short_code.clone(), day))
+            .record(source, visitor, viewer.referrer);
+
+        let mut pending = self.pending.lock();
+        *pending.entry(short_code).or_insert(0) += 1;
+        pending.len() >= batch_size
     }
 
-    pub fn new(pool: DatabasePool, handle: Handle) -> Self {
-        let (tx, rx) = unbounded();
-        let tx_clone = tx.clone();
-        let rx_clone = rx.clone();
+    #[tracing::instrument(name = "views_flush", skip_all)]
+    async fn flush(&mut self) {
+        let batch: Vec<(ShortCode, u32)> = self
+            .pending
+            .lock()
+            .iter()
+            .map(|(short_code, views)| (short_code.clone(), *views))
+            .collect();
+        // statistics that failed to be recorded are retried even when no views are pending
+        if batch.is_empty() && self.stats.is_empty() {
+            self.heartbeat.beat();
+            return;
+        }
+        let started = std::time::Instant::now();
 
-        let _ = std::thread::spawn(move || {
-            println!("Views thread spawned");
-            let store: ViewStore = Arc::new(Mutex::new(HashMap::new()));
+        // statistics go first, so a clip whose total is committed has its statistics as well
+        let stats = std::mem::take(&mut self.stats);
+        let daily = stats.values().cloned().collect();
+        let recorded = service::action::record_view_stats(daily, self.repository.as_ref()).await;
+        if let Err(e) = &recorded {
+            tracing::error!(error = %e, "failed to record view statistics");
+            self.stats = stats;
+        }
 
-            loop {
-                match rx_clone.try_recv() {
-                    Ok(msg) => {
-                        if let Err(e) =
-                            Self::process_msg(msg, store.clone(), handle.clone(), pool.clone())
-                        {
-                            eprintln!("message processing error: {}", e);
+        match service::action::increase_views(&batch, self.repository.as_ref()).await {
+            Ok(()) => {
+                match recorded {
+                    Ok(()) => self.heartbeat.succeeded(),
+                    Err(e) => self.heartbeat.failed(e),
+                }
+                if !batch.is_empty() {
+                    tracing::debug!(clips = batch.len(), "views flushed");
+                    self.metrics.views_flush_size.observe(batch.len() as f64);
+                    self.metrics
+                        .views_flush_duration
+                        .observe(started.elapsed().as_secs_f64());
+                }
+                let mut pending = self.pending.lock();
+                for (short_code, views) in batch {
+                    if let Some(count) = pending.get_mut(&short_code) {
+                        *count -= views;
+                        if *count == 0 {
+                            pending.remove(&short_code);
                         }
                     }
-                    Err(e) => match e {
-                        TryRecvError::Empty => {
-                            std::thread::sleep(Duration::from_secs(5));
-                            if let Err(e) = tx_clone.send(ViewMsg::Commit) {
-                                eprintln!("error sending commit msg to views channel: {}", e);
+                }
+            }
+            // counts stay pending and are retried with the next flush
+            Err(e) => {
+                tracing::error!(error = %e, "failed to increase views");
+                self.heartbeat.failed(e);
+            }
+        }
+    }
+}
+
+/// counts clip views in memory and writes them to the repository in batches, once
+/// `batch_size` clips have pending views or every `flush_interval`. alongside the total, each
+/// view is added to the per-day statistics of its clip. views by bots are not counted
+pub struct Views {
+    tx: mpsc::Sender<ViewMsg>,
+    pending: PendingViews,
+    metrics: AppMetrics,
+    heartbeat: Arc<Heartbeat>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Views {
+    pub fn new(
+        repository: AppRepository,
+        metrics: AppMetrics,
+        flush_interval: Duration,
+        batch_size: usize,
+        handle: Handle,
+    ) -> Self {
+        let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let pending = PendingViews::default();
+        let heartbeat = Arc::new(Heartbeat::new(flush_interval));
+
+        let mut writer = Writer {
+            repository,
+            metrics: metrics.clone(),
+            heartbeat: heartbeat.clone(),
+            pending: pending.clone(),
+            stats: HashMap::new(),
+            salt: None,
+        };
+        let task = handle.spawn(async move {
+            // the first tick is a full interval away, an immediate one would flush nothing
+            let start = tokio::time::Instant::now() + flush_interval;
+            let mut interval = tokio::time::interval_at(start, flush_interval);
+            loop {
+                tokio::select! {
+                    msg = rx.recv() => match msg {
+                        Some(ViewMsg::View(event)) => {
+                            if writer.record(*event, batch_size).await {
+                                writer.flush().await;
+                                interval.reset();
                             }
                         }
-                        _ => break,
+                        Some(ViewMsg::Shutdown(done)) => {
+                            writer.flush().await;
+                            let _ = done.send(());
+                            break;
+                        }
+                        None => break,
                     },
+                    _ = interval.tick() => writer.flush().await,
                 }
             }
         });
-        Self { tx }
+
+        Self {
+            tx,
+            pending,
+            metrics,
+            heartbeat,
+            task: Mutex::new(Some(task)),
+        }
+    }
+
+    pub async fn view(&self, short_code: ShortCode, source: ViewSource, viewer: Viewer) {
+        if viewer.is_bot() {
+            return;
+        }
+        let label = match source {
+            ViewSource::Web => "web",
+            ViewSource::Raw => "raw",
+            ViewSource::Api => "api",
+        };
+        self.metrics.clip_views.with_label_values(&[label]).inc();
+        let event = ViewEvent {
+            short_code,
+            source,
+            viewer,
+        };
+        if let Err(e) = self.tx.send(ViewMsg::View(Box::new(event))).await {
+            tracing::error!(error = %e, "failed to count view");
+        }
+    }
+
+    /// views of `short_code` that are not yet committed
+    pub fn pending(&self, short_code: &ShortCode) -> u32 {
+        self.pending
+            .lock()
+            .get(short_code)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// `clip` with its pending views added to the committed ones
+    pub fn with_pending(&self, mut clip: crate::Clip) -> crate::Clip {
+        use crate::domain::clip::field;
+
+        let pending = u64::from(self.pending(&clip.short_code));
+        clip.views = field::Views::new(clip.views.into_inner() + pending);
+        clip
+    }
+
+    /// degraded when the writer stopped running or failed to commit the last batch
+    pub fn health(&self) -> ComponentHealth {
+        self.heartbeat.health()
     }
 
-    pub fn view(&self, short_code: ShortCode, count: u32) {
-        if let Err(e) = self.tx.send(ViewMsg::View(short_code, count)) {
-            eprintln!("view count error: {}", e);
+    /// commits pending views and stops the writer. resolves once the final commit is done
+    pub async fn shutdown(&self) {
+        let (done, finished) = oneshot::channel();
+        if self.tx.send(ViewMsg::Shutdown(done)).await.is_ok() {
+            let _ = finished.await;
         }
+        let task = self.task.lock().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::repository::MemoryRepository;
+    use crate::domain::clip::field::Content;
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+
+    /// waits up to a second for `done` to hold
+    async fn wait_until(mut done: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("timed out waiting for the view writer");
+    }
+
+    async fn committed(repository: &AppRepository, short_code: &ShortCode) -> crate::Clip {
+        crate::Clip::try_from(repository.get_clip(short_code).await.unwrap()).unwrap()
+    }
+
+    fn viewer(address: &str, user_agent: &str) -> Viewer {
+        Viewer {
+            address: Some(address.parse().unwrap()),
+            user_agent: user_agent.to_owned(),
+            referrer: Some("example.com".to_owned()),
+        }
+    }
+
+    #[test]
+    fn writes_full_batches_and_merges_pending_views() {
+        let rt = async_runtime();
+        let repository: AppRepository = Arc::new(MemoryRepository::default());
+        let blobs = crate::data::blob::MemoryBlobStore::default();
+        let views = Views::new(
+            repository.clone(),
+            Default::default(),
+            Duration::from_secs(3600),
+            2,
+            rt.handle().clone(),
+        );
+
+        rt.block_on(async {
+            let mut short_codes = vec![];
+            for content in ["first", "second"] {
+                let req = ask::NewClip {
+                    content: Content::new(content).unwrap(),
+                    title: Default::default(),
+                    exprires_at: Default::default(),
+                    password: Default::default(),
+                    attachment: None,
+                    owner: None,
+                    api_key: Some("creator".to_owned()),
+                };
+                let clip = action::new_clip(
+                    req,
+                    repository.as_ref(),
+                    &blobs,
+                    &Default::default(),
+                    &crate::service::audit::Audit::system(repository.as_ref()),
+                )
+                .await
+                .unwrap();
+                short_codes.push(clip.short_code);
+            }
+            let (first, second) = (&short_codes[0], &short_codes[1]);
+
+            views
+                .view(
+                    first.clone(),
+                    ViewSource::Web,
+                    viewer("10.0.0.1", "firefox"),
+                )
+                .await;
+            views
+                .view(
+                    first.clone(),
+                    ViewSource::Raw,
+                    viewer("10.0.0.1", "firefox"),
+                )
+                .await;
+            views
+                .view(
+                    first.clone(),
+                    ViewSource::Web,
+                    viewer("10.0.0.2", "Googlebot/2.1"),
+                )
+                .await;
+            wait_until(|| views.pending(first) == 2).await;
+            let clip = committed(&repository, first).await;
+            assert_eq!(clip.views.clone().into_inner(), 0);
+            assert_eq!(views.with_pending(clip).views.into_inner(), 2);
+
+            // a second clip fills the batch, which is written without waiting for the interval
+            views
+                .view(second.clone(), ViewSource::Api, viewer("10.0.0.3", "curl"))
+                .await;
+            wait_until(|| views.pending(first) == 0).await;
+            assert_eq!(committed(&repository, first).await.views.into_inner(), 2);
+            assert_eq!(committed(&repository, second).await.views.into_inner(), 1);
+
+            let stats = action::clip_stats(
+                first.clone(),
+                None,
+                Some("creator"),
+                1,
+                repository.as_ref(),
+                repository.as_ref(),
+            )
+            .await
+            .unwrap();
+            assert_eq!((stats.web, stats.raw, stats.api), (1, 1, 0));
+            assert_eq!(stats.visitors, 1);
+            assert_eq!(stats.referrers[0].views, 2);
+        });
     }
 }
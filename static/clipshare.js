@@ -0,0 +1,18 @@
+// lets the server show times in the browser's timezone
+(function () {
+    var tz = Intl.DateTimeFormat().resolvedOptions().timeZone;
+    if (tz && document.cookie.split('; ').indexOf('tz=' + tz) === -1) {
+        document.cookie = 'tz=' + tz + '; path=/; max-age=31536000; samesite=lax';
+    }
+})();
+
+// shows `text` in a small tooltip above `el` for a moment
+function showTooltip(el, text) {
+    var tip = document.createElement('span');
+    tip.className = 'tooltip';
+    tip.textContent = text;
+    el.appendChild(tip);
+    setTimeout(function () {
+        tip.remove();
+    }, 1500);
+}